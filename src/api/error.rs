@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone)]
+pub enum LemnuxError {
+    /// The request never produced a response (DNS, TLS, connection reset...).
+    Transport(String),
+    /// The instance answered with a non-success status code. `error` holds
+    /// the value of Lemmy's `{"error": "..."}` payload when there is one.
+    Http { status: u16, error: Option<String> },
+    /// The response body could not be decoded into the expected type.
+    Decode(String),
+    /// Local configuration could not be read or is incomplete.
+    Config(String),
+    /// Stored credentials are missing, malformed or rejected by the instance.
+    Auth(String),
+    /// The credential store holding account tokens failed.
+    Secret(String),
+    /// Reading or writing a local file failed.
+    Io(String),
+    /// The offline store could not be opened, read or written.
+    Store(String),
+    /// The content is in a format lemnux has no decoder for.
+    Unsupported(String),
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ErrorPayload {
+    pub error: String,
+}
+
+impl LemnuxError {
+    /// The instance could not be reached, so content saved offline is the
+    /// best there is.
+    pub fn is_offline(&self) -> bool {
+        matches!(
+            self,
+            LemnuxError::Transport(_)
+                | LemnuxError::Http {
+                    status: 502..=504,
+                    ..
+                }
+        )
+    }
+
+    /// The instance understood the request and turned it down with one of
+    /// Lemmy's error codes, so sending it again would fail the same way.
+    /// Logins that expired and rate limits pass with time and don't count.
+    pub fn is_refusal(&self) -> bool {
+        match self {
+            LemnuxError::Http {
+                status: 400..=499,
+                error: Some(error),
+            } => !matches!(
+                error.as_str(),
+                "not_logged_in" | "incorrect_login" | "rate_limit_error"
+            ),
+            _ => false,
+        }
+    }
+
+    /// Lemmy's error code (e.g. `incorrect_login`), if the instance sent one.
+    pub fn lemmy_error(&self) -> Option<&str> {
+        match self {
+            LemnuxError::Http { error, .. } => error.as_deref(),
+            LemnuxError::Auth(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LemnuxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LemnuxError::Transport(err) => write!(f, "Could not reach the instance: {}", err),
+            LemnuxError::Http {
+                status,
+                error: Some(error),
+            } => write!(f, "Instance returned {}: {}", status, error),
+            LemnuxError::Http {
+                status,
+                error: None,
+            } => write!(f, "Instance returned {}", status),
+            LemnuxError::Decode(err) => write!(f, "Unexpected response: {}", err),
+            LemnuxError::Config(err) => write!(f, "Configuration error: {}", err),
+            LemnuxError::Auth(err) => write!(f, "Authentication failed: {}", err),
+            LemnuxError::Secret(err) => write!(f, "Credential store error: {}", err),
+            LemnuxError::Io(err) => write!(f, "File error: {}", err),
+            LemnuxError::Store(err) => write!(f, "Offline store error: {}", err),
+            LemnuxError::Unsupported(err) => write!(f, "Unsupported format: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LemnuxError {}
+
+impl From<reqwest::Error> for LemnuxError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            LemnuxError::Decode(err.to_string())
+        } else if let Some(status) = err.status() {
+            LemnuxError::Http {
+                status: status.as_u16(),
+                error: None,
+            }
+        } else {
+            LemnuxError::Transport(err.to_string())
+        }
+    }
+}
+
+impl From<confy::ConfyError> for LemnuxError {
+    fn from(err: confy::ConfyError) -> Self {
+        LemnuxError::Config(err.to_string())
+    }
+}
+
+impl From<secret_service::Error> for LemnuxError {
+    fn from(err: secret_service::Error) -> Self {
+        LemnuxError::Secret(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for LemnuxError {
+    fn from(err: std::io::Error) -> Self {
+        LemnuxError::Io(err.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for LemnuxError {
+    fn from(err: rusqlite::Error) -> Self {
+        LemnuxError::Store(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for LemnuxError {
+    fn from(err: serde_json::Error) -> Self {
+        LemnuxError::Store(err.to_string())
+    }
+}
@@ -0,0 +1,645 @@
+//! Downloaded images, kept on disk under `$XDG_CACHE_HOME/lemnux/images`
+//! and, as handles ready for the renderer, in memory.
+//!
+//! Files are named after the SHA-256 of their URL. Each one has a JSON
+//! sidecar recording the `ETag` and until when the server said the copy is
+//! fresh, so stale entries are revalidated instead of downloaded again.
+//!
+//! Thumbnails are decoded and scaled down on a blocking thread and kept
+//! in memory as pixels, so the renderer never sees the full size image.
+//!
+//! Images come from whatever host a post or comment links to, so only
+//! requests to the instance itself carry the account's token.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::SystemTime,
+};
+
+use directories::UserDirs;
+use iced::advanced::image::{Bytes, Handle};
+use image::{imageops::FilterType, ImageFormat};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CACHE_CONTROL, ETAG, IF_NONE_MATCH, USER_AGENT},
+    Client, ClientBuilder, RequestBuilder, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{LemnuxError, API};
+use crate::settings::{cache_dir, CachePreferences, LEMNUX_UA};
+
+/// How long an image stays fresh when the server gives no `max-age`.
+const DEFAULT_MAX_AGE: i64 = 24 * 60 * 60;
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+static DISK_LIMIT: AtomicU64 = AtomicU64::new(CachePreferences::DEFAULT_DISK_MB * MEGABYTE);
+
+/// Tells temporary files of concurrent downloads apart.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    etag: Option<String>,
+    /// Unix time after which the copy must be revalidated.
+    expires: i64,
+}
+
+/// What `Cache-Control` allows doing with a response.
+#[derive(Debug, PartialEq)]
+enum Freshness {
+    NoStore,
+    Until(i64),
+}
+
+impl Freshness {
+    fn from_header(value: Option<&str>, now: i64) -> Self {
+        let mut max_age = DEFAULT_MAX_AGE;
+
+        for directive in value.unwrap_or_default().split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+
+            if directive == "no-store" {
+                return Freshness::NoStore;
+            } else if directive == "no-cache" {
+                max_age = 0;
+            } else if let Some(age) = directive.strip_prefix("max-age=") {
+                max_age = age.trim_matches('"').parse().unwrap_or(0);
+            }
+        }
+
+        Freshness::Until(now + max_age)
+    }
+}
+
+/// Applies the size caps from the preferences to both caches.
+pub fn set_limits(limits: &CachePreferences) {
+    DISK_LIMIT.store(limits.disk_mb * MEGABYTE, Ordering::Relaxed);
+    memory()
+        .lock()
+        .unwrap()
+        .set_limit(limits.memory_mb * MEGABYTE);
+}
+
+fn images_dir() -> Result<PathBuf, LemnuxError> {
+    Ok(cache_dir()?.join("images"))
+}
+
+fn key(url: &str) -> String {
+    Sha256::digest(url.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Client without credentials, for images hosted anywhere.
+fn media_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+
+    CLIENT.get_or_init(|| {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static(LEMNUX_UA));
+
+        ClientBuilder::new()
+            .default_headers(headers)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// A GET for `url`, logged in only when it goes to the instance: private
+/// instances serve their pict-rs images to accounts alone.
+fn media_request(api: &API, url: &str) -> RequestBuilder {
+    let origin_of = |url: &str| Url::parse(url).ok().map(|url| url.origin());
+
+    match origin_of(url) {
+        Some(origin) if origin.is_tuple() && Some(&origin) == origin_of(&api.url).as_ref() => {
+            api.client.get(url)
+        }
+        _ => media_client().get(url),
+    }
+}
+
+/// The image at `url`, from the cache when the copy there is still fresh
+/// or the server confirms it did not change.
+pub async fn fetch(api: &API, url: &str) -> Result<Bytes, LemnuxError> {
+    let dir = images_dir()?;
+    let key = key(url);
+    let data_path = dir.join(&key);
+    let entry_path = dir.join(format!("{}.json", key));
+
+    let entry = tokio::fs::read(&entry_path)
+        .await
+        .ok()
+        .and_then(|json| serde_json::from_slice::<Entry>(&json).ok())
+        .filter(|entry| entry.url == url);
+    let cached = match entry {
+        Some(_) => tokio::fs::read(&data_path).await.ok(),
+        None => None,
+    };
+
+    if let (Some(entry), Some(data)) = (&entry, &cached) {
+        if entry.expires > now() {
+            touch(&data_path);
+            return Ok(Bytes::new(data.clone()));
+        }
+    }
+
+    let mut request = media_request(api, url);
+
+    if let (Some(etag), Some(_)) = (
+        entry.as_ref().and_then(|entry| entry.etag.as_ref()),
+        &cached,
+    ) {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send().await?;
+    let freshness = Freshness::from_header(
+        response
+            .headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok()),
+        now(),
+    );
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        if let (Some(mut entry), Some(data)) = (entry, cached) {
+            if let Freshness::Until(expires) = freshness {
+                entry.expires = expires;
+                // Failing to record the new expiry only means asking again.
+                let _ = write_file(&entry_path, &serde_json::to_vec(&entry).unwrap()).await;
+            }
+            touch(&data_path);
+
+            return Ok(Bytes::new(data));
+        }
+    }
+
+    let response = response.error_for_status()?;
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let data = response.bytes().await?;
+
+    if let Freshness::Until(expires) = freshness {
+        let entry = Entry {
+            url: url.to_string(),
+            etag,
+            expires,
+        };
+
+        // The image is shown either way; a full or read-only disk only
+        // costs a download next time.
+        if store(&dir, &data_path, &entry_path, &entry, &data)
+            .await
+            .is_ok()
+        {
+            let _ = tokio::task::spawn_blocking(move || evict(&dir)).await;
+        }
+    }
+
+    Ok(Bytes::new(data))
+}
+
+async fn store(
+    dir: &Path,
+    data_path: &Path,
+    entry_path: &Path,
+    entry: &Entry,
+    data: &[u8],
+) -> Result<(), LemnuxError> {
+    tokio::fs::create_dir_all(dir).await?;
+    write_file(data_path, data).await?;
+    write_file(entry_path, &serde_json::to_vec(entry).unwrap()).await
+}
+
+/// Writes through a temporary file so readers never see half an image.
+async fn write_file(path: &Path, contents: &[u8]) -> Result<(), LemnuxError> {
+    let part = path.with_extension(format!("part{}", WRITES.fetch_add(1, Ordering::Relaxed)));
+
+    tokio::fs::write(&part, contents).await?;
+    tokio::fs::rename(&part, path).await?;
+
+    Ok(())
+}
+
+/// Marks a file as just used; eviction removes the least recently used.
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// Removes the least recently used images until the cache fits its cap.
+fn evict(dir: &Path) -> Result<(), LemnuxError> {
+    let limit = DISK_LIMIT.load(Ordering::Relaxed);
+    let mut images = Vec::new();
+    let mut total = 0;
+
+    for file in fs::read_dir(dir)? {
+        let file = file?;
+        let metadata = file.metadata()?;
+
+        total += metadata.len();
+
+        if file.path().extension().is_none() {
+            images.push((metadata.modified()?, metadata.len(), file.path()));
+        }
+    }
+
+    if total <= limit {
+        return Ok(());
+    }
+
+    images.sort_by_key(|(modified, _, _)| *modified);
+
+    for (_, size, path) in images {
+        if total <= limit {
+            break;
+        }
+
+        let entry = path.with_extension("json");
+        let entry_size = fs::metadata(&entry).map(|entry| entry.len()).unwrap_or(0);
+
+        fs::remove_file(&path)?;
+        let _ = fs::remove_file(&entry);
+        total = total.saturating_sub(size + entry_size);
+    }
+
+    Ok(())
+}
+
+/// Bytes used by the disk and memory caches.
+pub async fn usage() -> Result<(u64, u64), LemnuxError> {
+    let dir = images_dir()?;
+    let disk = tokio::task::spawn_blocking(move || -> Result<u64, LemnuxError> {
+        match fs::read_dir(dir) {
+            Ok(files) => files
+                .map(|file| Ok(file?.metadata()?.len()))
+                .sum::<Result<u64, LemnuxError>>(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await
+    .map_err(|err| LemnuxError::Io(err.to_string()))??;
+
+    Ok((disk, memory().lock().unwrap().size))
+}
+
+/// Empties both caches.
+pub async fn clear() -> Result<(), LemnuxError> {
+    memory().lock().unwrap().clear();
+
+    match tokio::fs::remove_dir_all(images_dir()?).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Handles of recently shown images, so going back to a listing neither
+/// reads the disk nor hashes the bytes again.
+#[derive(Debug)]
+struct MemoryCache {
+    handles: HashMap<String, (Handle, u64, u64)>,
+    /// Bumped on every use; the entry with the lowest value goes first.
+    clock: u64,
+    size: u64,
+    limit: u64,
+}
+
+fn memory() -> &'static Mutex<MemoryCache> {
+    static MEMORY: OnceLock<Mutex<MemoryCache>> = OnceLock::new();
+
+    MEMORY.get_or_init(|| {
+        Mutex::new(MemoryCache {
+            handles: HashMap::new(),
+            clock: 0,
+            size: 0,
+            limit: CachePreferences::DEFAULT_MEMORY_MB * MEGABYTE,
+        })
+    })
+}
+
+impl MemoryCache {
+    fn get(&mut self, url: &str) -> Option<Handle> {
+        self.clock += 1;
+
+        let (handle, _, used) = self.handles.get_mut(url)?;
+        *used = self.clock;
+
+        Some(handle.clone())
+    }
+
+    fn insert(&mut self, url: String, handle: Handle, size: u64) {
+        self.clock += 1;
+
+        if let Some((_, old, _)) = self.handles.insert(url, (handle, size, self.clock)) {
+            self.size -= old;
+        }
+        self.size += size;
+        self.shrink();
+    }
+
+    fn set_limit(&mut self, limit: u64) {
+        self.limit = limit;
+        self.shrink();
+    }
+
+    fn shrink(&mut self) {
+        while self.size > self.limit {
+            let Some(oldest) = self
+                .handles
+                .iter()
+                .min_by_key(|(_, (_, _, used))| *used)
+                .map(|(url, _)| url.clone())
+            else {
+                break;
+            };
+
+            if let Some((_, size, _)) = self.handles.remove(&oldest) {
+                self.size -= size;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.handles.clear();
+        self.size = 0;
+    }
+}
+
+/// The image at `url` as a handle for the renderer.
+pub async fn load_handle(api: &API, url: &str) -> Result<Handle, LemnuxError> {
+    if let Some(handle) = memory().lock().unwrap().get(url) {
+        return Ok(handle);
+    }
+
+    let bytes = decodable(api, url, fetch(api, url).await?).await?;
+    // The renderer keeps the decoded pixels, not the download.
+    let (width, height) = image::io::Reader::new(Cursor::new(&bytes))
+        .with_guessed_format()?
+        .into_dimensions()
+        .map_err(|err| LemnuxError::Decode(err.to_string()))?;
+    let size = u64::from(width) * u64::from(height) * 4;
+    let handle = Handle::from_memory(bytes);
+
+    memory()
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), handle.clone(), size);
+
+    Ok(handle)
+}
+
+/// The image at `url` decoded and scaled down to at most `max_width`
+/// pixels wide. Animated GIFs and WebPs show their first frame.
+pub async fn load_thumbnail(api: &API, url: &str, max_width: u32) -> Result<Handle, LemnuxError> {
+    let key = format!("{}#{}", url, max_width);
+
+    if let Some(handle) = memory().lock().unwrap().get(&key) {
+        return Ok(handle);
+    }
+
+    let bytes = decodable(api, url, fetch(api, url).await?).await?;
+
+    let (width, height, pixels) =
+        tokio::task::spawn_blocking(move || decode_thumbnail(&bytes, max_width))
+            .await
+            .map_err(|err| LemnuxError::Decode(err.to_string()))??;
+    let size = pixels.len() as u64;
+    let handle = Handle::from_pixels(width, height, pixels);
+
+    memory().lock().unwrap().insert(key, handle.clone(), size);
+
+    Ok(handle)
+}
+
+/// `bytes` downloaded from `url`, in a format that can be decoded. There is
+/// no AVIF decoder without dav1d, but pict-rs converts its images on
+/// request; AVIF from anywhere else is reported as unsupported.
+async fn decodable(api: &API, url: &str, bytes: Bytes) -> Result<Bytes, LemnuxError> {
+    if !matches!(image::guess_format(&bytes), Ok(ImageFormat::Avif)) {
+        return Ok(bytes);
+    }
+
+    match pictrs_format(url, "webp") {
+        Some(webp) => fetch(api, &webp).await,
+        None => Err(LemnuxError::Unsupported(String::from("AVIF"))),
+    }
+}
+
+/// `url` asking pict-rs for `format`, if it is a pict-rs image.
+fn pictrs_format(url: &str, format: &str) -> Option<String> {
+    let mut url = Url::parse(url).ok()?;
+
+    if !url.path().starts_with("/pictrs/image/") {
+        return None;
+    }
+
+    url.query_pairs_mut().append_pair("format", format);
+
+    Some(url.into())
+}
+
+/// RGBA pixels of `bytes`, no wider than `max_width`.
+fn decode_thumbnail(bytes: &[u8], max_width: u32) -> Result<(u32, u32, Vec<u8>), LemnuxError> {
+    let image =
+        image::load_from_memory(bytes).map_err(|err| LemnuxError::Decode(err.to_string()))?;
+    let image = if image.width() > max_width {
+        image.resize(max_width, u32::MAX, FilterType::Triangle)
+    } else {
+        image
+    };
+    let rgba = image.into_rgba8();
+
+    Ok((rgba.width(), rgba.height(), rgba.into_raw()))
+}
+
+/// Writes the image at `url` to the user's Pictures directory as
+/// `file_name`, and returns where it went. Like the viewer, it fetches
+/// links to other hosts without the account's token.
+pub async fn save_to_pictures(
+    api: &API,
+    url: &str,
+    file_name: &str,
+) -> Result<PathBuf, LemnuxError> {
+    let dir = UserDirs::new()
+        .and_then(|dirs| dirs.picture_dir().map(Path::to_path_buf))
+        .ok_or_else(|| LemnuxError::Config(String::from("no Pictures directory")))?;
+    // Only the name is used, so the file cannot land outside the directory.
+    let name = Path::new(file_name.trim())
+        .file_name()
+        .ok_or_else(|| LemnuxError::Io(String::from("invalid file name")))?;
+    let bytes = fetch(api, url).await?;
+    let path = dir.join(name);
+
+    tokio::fs::create_dir_all(&dir).await?;
+    tokio::fs::write(&path, &*bytes).await?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    /// Answers one request on a local port with a 1×1 PNG the cache must
+    /// not keep, and hands back the request's head in lower case.
+    async fn serve_once() -> (String, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+        let mut png = Vec::new();
+        image::RgbaImage::new(1, 1)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let served = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0];
+                socket.read_exact(&mut byte).await.unwrap();
+                head.push(byte[0]);
+            }
+
+            let answer = format!(
+                "HTTP/1.1 200 OK\r\nCache-Control: no-store\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                png.len()
+            );
+            socket.write_all(answer.as_bytes()).await.unwrap();
+            socket.write_all(&png).await.unwrap();
+
+            String::from_utf8_lossy(&head).to_lowercase()
+        });
+
+        (url, served)
+    }
+
+    /// A client logged into the instance at `url`.
+    fn logged_in(url: &str) -> API {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+
+        API {
+            domain: String::from("lemmy.example"),
+            username: Some(String::from("alice")),
+            url: url.to_string(),
+            client: ClientBuilder::new()
+                .default_headers(headers)
+                .build()
+                .unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn images_elsewhere_are_fetched_without_the_token() {
+        let (url, served) = serve_once().await;
+        let api = logged_in("https://lemmy.example/api/v3");
+
+        fetch(&api, &url).await.unwrap();
+
+        assert!(!served.await.unwrap().contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn inline_images_are_fetched_without_the_token() {
+        let (url, served) = serve_once().await;
+        let api = logged_in("https://lemmy.example/api/v3");
+
+        load_handle(&api, &url).await.unwrap();
+
+        assert!(!served.await.unwrap().contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn linked_images_are_viewed_without_the_token() {
+        let (url, served) = serve_once().await;
+        let api = logged_in("https://lemmy.example/api/v3");
+
+        load_thumbnail(&api, &url, u32::MAX).await.unwrap();
+
+        assert!(!served.await.unwrap().contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn images_on_the_instance_are_fetched_logged_in() {
+        let (url, served) = serve_once().await;
+        let api = logged_in(&url.replace("/image.png", "/api/v3"));
+
+        fetch(&api, &url).await.unwrap();
+
+        assert!(served
+            .await
+            .unwrap()
+            .contains("authorization: bearer secret"));
+    }
+
+    #[test]
+    fn missing_header_uses_the_default_age() {
+        assert_eq!(
+            Freshness::from_header(None, NOW),
+            Freshness::Until(NOW + DEFAULT_MAX_AGE)
+        );
+    }
+
+    #[test]
+    fn max_age_is_honoured() {
+        assert_eq!(
+            Freshness::from_header(Some("public, max-age=600"), NOW),
+            Freshness::Until(NOW + 600)
+        );
+        assert_eq!(
+            Freshness::from_header(Some("Max-Age=\"60\""), NOW),
+            Freshness::Until(NOW + 60)
+        );
+    }
+
+    #[test]
+    fn no_cache_revalidates_at_once() {
+        assert_eq!(
+            Freshness::from_header(Some("no-cache"), NOW),
+            Freshness::Until(NOW)
+        );
+    }
+
+    #[test]
+    fn no_store_wins_over_max_age() {
+        assert_eq!(
+            Freshness::from_header(Some("max-age=600, no-store"), NOW),
+            Freshness::NoStore
+        );
+    }
+
+    #[test]
+    fn unreadable_max_age_revalidates_at_once() {
+        assert_eq!(
+            Freshness::from_header(Some("max-age=soon"), NOW),
+            Freshness::Until(NOW)
+        );
+    }
+}
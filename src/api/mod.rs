@@ -1,29 +1,78 @@
 #![allow(clippy::unnecessary_to_owned, clippy::to_string_in_format_args)]
 
-use std::fmt::Display;
+pub mod error;
+pub mod image_cache;
+
+use std::{collections::HashSet, fmt::Display, future::Future, sync::Arc};
+
+use chrono::{DateTime, Utc};
 
 use iced::advanced::image::Bytes;
 use lemmy_api_common::{
-    lemmy_db_schema::{newtypes::CommunityId, ListingType, SortType},
+    comment::{
+        CommentResponse, CreateComment, DeleteComment, EditComment, GetComments,
+        GetCommentsResponse, SaveComment,
+    },
+    community::{
+        CommunityResponse, FollowCommunity, GetCommunity, GetCommunityResponse, ListCommunities,
+        ListCommunitiesResponse,
+    },
+    lemmy_db_schema::{
+        newtypes::{
+            CommentId, CommentReplyId, CommunityId, PersonId, PersonMentionId, PostId,
+            PrivateMessageId,
+        },
+        source::person::Person,
+        CommentSortType, ListingType, SortType,
+    },
     lemmy_db_views::structs::PaginationCursor,
-    person::{Login, LoginResponse},
-    post::GetPostsResponse,
+    person::{
+        BlockPerson, BlockPersonResponse, CommentReplyResponse, GetCaptchaResponse,
+        GetPersonDetails, GetPersonDetailsResponse, GetPersonMentions, GetPersonMentionsResponse,
+        GetReplies, GetRepliesResponse, GetUnreadCountResponse, Login, LoginResponse,
+        MarkCommentReplyAsRead, MarkPersonMentionAsRead, PersonMentionResponse, Register,
+    },
+    post::{
+        CreatePost, CreatePostLike, DeletePost, EditPost, GetPost, GetPostResponse,
+        GetPostsResponse, GetSiteMetadata, GetSiteMetadataResponse, PostResponse, SavePost,
+    },
+    private_message::{
+        CreatePrivateMessage, CreatePrivateMessageReport, DeletePrivateMessage, EditPrivateMessage,
+        GetPrivateMessages, MarkPrivateMessageAsRead, PrivateMessageReportResponse,
+        PrivateMessageResponse, PrivateMessagesResponse,
+    },
     sensitive::Sensitive,
+    site::{GetSiteResponse, Search, SearchResponse},
 };
 use reqwest::{
     header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, USER_AGENT},
-    Client, ClientBuilder,
+    Client, ClientBuilder, RequestBuilder, StatusCode, Url,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::settings::{Settings, JWT, LEMNUX_UA};
+use self::error::ErrorPayload;
+pub use self::error::LemnuxError;
+use crate::{
+    settings::{
+        with_credential_store, Accounts, CredentialStore, ListingPreferences, JWT, LEMNUX_UA,
+    },
+    store::{self, Kind, PendingAction},
+};
 
 const API_URL: &str = "/api";
 const API_VER: &str = "/v3";
 
-#[derive(Debug)]
+/// Shared HTTP handle for the configured instance and account.
+///
+/// Built once by `gui::load` and handed around as `Arc<API>`, so every
+/// request reuses the same `reqwest` connection pool. Rebuild it only when
+/// the instance or the logged in account changes.
+#[derive(Debug, Clone)]
 pub struct API {
-    pub instance: Option<Instance>,
+    /// Instance the handle talks to, e.g. `lemmy.ml`.
+    pub domain: String,
+    /// Name of the logged in account, `None` when browsing anonymously.
+    pub username: Option<String>,
     pub url: String,
     client: Client,
 }
@@ -67,8 +116,33 @@ pub struct FederationState {
     pub next_retry: Option<String>,
 }
 
+/// Sends `request` and decodes a successful response as `T`.
+///
+/// Non-success statuses are turned into [`LemnuxError::Http`] carrying
+/// Lemmy's error code, or [`LemnuxError::Auth`] for `401 Unauthorized`.
+async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, LemnuxError> {
+    let response = request.send().await?;
+    let status = response.status();
+
+    if !status.is_success() {
+        let error = response.json::<ErrorPayload>().await.ok().map(|e| e.error);
+
+        return Err(match (status, error) {
+            (StatusCode::UNAUTHORIZED, error) => {
+                LemnuxError::Auth(error.unwrap_or_else(|| String::from("not_logged_in")))
+            }
+            (status, error) => LemnuxError::Http {
+                status: status.as_u16(),
+                error,
+            },
+        });
+    }
+
+    Ok(response.json::<T>().await?)
+}
+
 impl Instances {
-    pub async fn new() -> Instances {
+    pub async fn new() -> Result<Instances, LemnuxError> {
         let domain = "lemmy.ml";
         let url = format!(
             "https://{}{}{}/federated_instances",
@@ -77,124 +151,266 @@ impl Instances {
         let mut headers = HeaderMap::new();
         headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
         headers.insert(USER_AGENT, HeaderValue::from_static(LEMNUX_UA));
-        let client = ClientBuilder::new()
-            .default_headers(headers)
-            .build()
-            .unwrap();
+        let client = ClientBuilder::new().default_headers(headers).build()?;
 
-        client.get(url).send().await.unwrap().json().await.unwrap()
+        send(client.get(url)).await
+    }
+}
+
+/// The saved accounts and a client for the active one. Without a readable
+/// account list no account is known.
+pub async fn connect() -> (Accounts, Result<API, LemnuxError>) {
+    let loaded = with_credential_store(|store| {
+        let accounts = Accounts::load(store)?;
+        let api = API::new(&accounts, store, true);
+
+        Ok((accounts, api))
+    })
+    .await;
+
+    match loaded {
+        Ok((accounts, api)) => (accounts, api),
+        Err(error) => (Accounts::default(), Err(error)),
     }
 }
 
 impl API {
-    pub fn new(secure: bool) -> Self {
-        let instance_setting: Settings = confy::load("lemnux", "instance").unwrap();
-        let user_setting: Settings = confy::load("lemnux", "user").unwrap();
+    /// Client for the active account, or an anonymous one for the instance
+    /// picked in settings when no account is active.
+    pub fn new(
+        accounts: &Accounts,
+        store: &dyn CredentialStore,
+        secure: bool,
+    ) -> Result<Self, LemnuxError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.insert(USER_AGENT, HeaderValue::from_static(LEMNUX_UA));
+
+        if let Some(account) = accounts.active() {
+            let token = account
+                .token(store)?
+                .ok_or_else(|| LemnuxError::Auth(String::from("missing_token")))?;
+            let bearer_token = format!("Bearer {}", token.to_string());
+            let value = HeaderValue::from_str(&bearer_token)
+                .map_err(|_| LemnuxError::Auth(String::from("malformed_token")))?;
+
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let client = ClientBuilder::new().default_headers(headers).build()?;
+
+        let domain = accounts.domain().to_string();
+        let username = accounts
+            .active()
+            .map(|account| account.username.to_string());
+        let url = format!(
+            "http{}://{}{}{}",
+            if secure { "s" } else { "" },
+            domain,
+            API_URL,
+            API_VER
+        );
+
+        Ok(Self {
+            domain,
+            username,
+            url,
+            client,
+        })
+    }
 
+    /// Unauthenticated handle for the default instance, used when the stored
+    /// configuration can't be turned into a working client.
+    pub fn anonymous(secure: bool) -> Self {
         let mut headers = HeaderMap::new();
         headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
         headers.insert(USER_AGENT, HeaderValue::from_static(LEMNUX_UA));
 
-        let client = if user_setting.user.is_some() {
-            let bearer_token = format!(
-                "Bearer {}",
-                user_setting
-                    .user
-                    .unwrap()
-                    .jwt
-                    .unwrap()
-                    .token
-                    .unwrap()
-                    .to_string()
-            );
-            headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token).unwrap());
-            ClientBuilder::new()
-                .default_headers(headers)
-                .build()
-                .unwrap()
-        } else {
-            ClientBuilder::new()
-                .default_headers(headers)
-                .build()
-                .unwrap()
-        };
+        let client = ClientBuilder::new()
+            .default_headers(headers)
+            .build()
+            .unwrap_or_default();
 
-        let url = if instance_setting.instance.is_some() {
-            format!(
-                "http{}://{}{}{}",
-                if secure { "s" } else { "" },
-                instance_setting.instance.as_ref().unwrap().domain,
-                API_URL,
-                API_VER
-            )
-        } else {
-            format!(
+        Self {
+            domain: String::from("lemmy.ml"),
+            username: None,
+            url: format!(
                 "http{}://lemmy.ml{}{}",
                 if secure { "s" } else { "" },
                 API_URL,
                 API_VER
-            )
-        };
-
-        Self {
-            instance: instance_setting.instance,
-            url,
+            ),
             client,
         }
     }
+
+    /// Whether `person` is the logged in account.
+    pub fn is_me(&self, person: &Person) -> bool {
+        self.username.as_deref() == Some(person.name.as_str())
+            && person.actor_id.host_str() == Some(self.domain.as_str())
+    }
+
+    /// `instance/username`, or `instance/` when anonymous. Stored content
+    /// is kept per account since it carries the account's votes.
+    fn store_key(&self) -> String {
+        format!(
+            "{}/{}",
+            self.domain,
+            self.username.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// A response, and whether it came from the offline store.
+#[derive(Debug, Clone)]
+pub struct Fetched<T> {
+    pub value: T,
+    /// When the stored copy in `value` was fetched, if the instance could
+    /// not be reached.
+    pub stale: Option<DateTime<Utc>>,
 }
 
-pub async fn load_img_to_memory(url: &str) -> Bytes {
-    let api = API::new(true);
+/// Runs `request` and keeps its answer under `key` for offline use. When
+/// the instance cannot be reached, the kept answer stands in for it.
+async fn fetch_stored<T: Serialize + DeserializeOwned + Send + 'static>(
+    kind: Kind,
+    key: Option<String>,
+    request: impl Future<Output = Result<T, LemnuxError>>,
+) -> Result<Fetched<T>, LemnuxError> {
+    let result = request.await;
 
-    let response = api
-        .client
-        .get(url)
-        .send()
-        .await
-        .unwrap()
-        .bytes()
-        .await
-        .unwrap();
+    let Some(key) = key else {
+        return result.map(|value| Fetched { value, stale: None });
+    };
 
-    Bytes::new(response)
+    match result {
+        Ok(value) => {
+            // The answer is shown either way; failing to keep it only
+            // matters once offline.
+            if let Ok(body) = serde_json::to_string(&value) {
+                let _ = store::blocking(move || store::put(kind, &key, &body)).await;
+            }
+
+            Ok(Fetched { value, stale: None })
+        }
+        Err(error) if error.is_offline() => {
+            match store::blocking(move || store::get(kind, &key)).await {
+                Ok(Some((value, fetched))) => Ok(Fetched {
+                    value,
+                    stale: Some(fetched),
+                }),
+                _ => Err(error),
+            }
+        }
+        Err(error) => Err(error),
+    }
+}
+
+pub async fn load_img_to_memory(api: &API, url: &str) -> Result<Bytes, LemnuxError> {
+    image_cache::fetch(api, url).await
 }
 
 pub async fn login(
+    api: Arc<API>,
     username_or_email: Sensitive<String>,
     password: Sensitive<String>,
     totp_2fa_token: Option<String>,
-) -> Option<JWT> {
+) -> Result<JWT, LemnuxError> {
     let params = Login {
         username_or_email,
         password,
         totp_2fa_token,
     };
 
-    let api = API::new(true);
     let url = format!("{}/user/login", api.url.clone());
 
-    let response = api
-        .client
-        .post(url)
-        .json(&params)
-        .send()
-        .await
-        .unwrap()
-        .json::<LoginResponse>()
-        .await
-        .unwrap();
+    let response: LoginResponse = send(api.client.post(url).json(&params)).await?;
 
-    let jwt = JWT {
+    Ok(JWT {
         token: response.jwt,
         registration_created: response.registration_created,
         verify_email_sent: response.verify_email_sent,
-    };
+    })
+}
+
+pub async fn create_post(api: Arc<API>, params: CreatePost) -> Result<PostResponse, LemnuxError> {
+    let url = format!("{}/post", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+pub async fn edit_post(api: Arc<API>, params: EditPost) -> Result<PostResponse, LemnuxError> {
+    let url = format!("{}/post", api.url.clone());
+
+    send(api.client.put(url).json(&params)).await
+}
+
+pub async fn delete_post(
+    api: Arc<API>,
+    post_id: PostId,
+    deleted: bool,
+) -> Result<PostResponse, LemnuxError> {
+    let params = DeletePost { post_id, deleted };
+    let url = format!("{}/post/delete", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+/// Title, description and embed of the page at `link`, read from its
+/// opengraph tags by the instance.
+pub async fn get_site_metadata(
+    api: Arc<API>,
+    link: Url,
+) -> Result<GetSiteMetadataResponse, LemnuxError> {
+    let params = GetSiteMetadata { url: link };
+    let url = format!("{}/post/site_metadata", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn list_communities(
+    api: Arc<API>,
+    params: ListCommunities,
+) -> Result<ListCommunitiesResponse, LemnuxError> {
+    let url = format!("{}/community/list", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn get_site(api: Arc<API>) -> Result<GetSiteResponse, LemnuxError> {
+    let url = format!("{}/site", api.url.clone());
+
+    send(api.client.get(url)).await
+}
+
+/// `ok` is `None` when the instance has captchas turned off.
+pub async fn get_captcha(api: Arc<API>) -> Result<GetCaptchaResponse, LemnuxError> {
+    let url = format!("{}/user/get_captcha", api.url.clone());
+
+    send(api.client.get(url)).await
+}
+
+pub async fn register(api: Arc<API>, params: Register) -> Result<JWT, LemnuxError> {
+    let url = format!("{}/user/register", api.url.clone());
+
+    let response: LoginResponse = send(api.client.post(url).json(&params)).await?;
+
+    Ok(JWT {
+        token: response.jwt,
+        registration_created: response.registration_created,
+        verify_email_sent: response.verify_email_sent,
+    })
+}
 
-    Some(jwt)
+/// Listings of posts picked by the logged in user rather than by a feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersonalListing {
+    Saved,
+    Upvoted,
+    Downvoted,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PostsList {
     pub type_: Option<ListingType>,
     pub sort: Option<SortType>,
@@ -209,38 +425,542 @@ pub struct PostsList {
 }
 
 impl PostsList {
-    pub fn new(type_: Option<ListingType>, page_cursor: Option<PaginationCursor>) -> Self {
+    pub fn new(type_: Option<ListingType>, listing: ListingPreferences) -> Self {
         Self {
             type_,
-            sort: Some(SortType::Hot),
+            sort: Some(listing.sort),
             page: None,
-            limit: Some(20),
+            limit: Some(listing.limit),
             community_id: None,
             community_name: None,
             saved_only: Some(false),
             liked_only: Some(false),
             disliked_only: Some(false),
+            page_cursor: None,
+        }
+    }
+
+    /// Posts the logged in user saved, upvoted or downvoted, newest first.
+    pub fn personal(listing: PersonalListing, limit: i64) -> Self {
+        Self {
+            saved_only: Some(listing == PersonalListing::Saved),
+            liked_only: Some(listing == PersonalListing::Upvoted),
+            disliked_only: Some(listing == PersonalListing::Downvoted),
+            ..Self::new(
+                Some(ListingType::All),
+                ListingPreferences {
+                    sort: SortType::New,
+                    limit,
+                },
+            )
+        }
+    }
+
+    /// Whether the listing only holds the logged in user's own picks.
+    pub fn is_personal(&self) -> bool {
+        [self.saved_only, self.liked_only, self.disliked_only].contains(&Some(true))
+    }
+
+    pub fn with_cursor(&self, page_cursor: Option<PaginationCursor>) -> Self {
+        Self {
             page_cursor,
+            ..self.clone()
+        }
+    }
+
+    /// Only first pages of public listings are kept offline.
+    fn store_key(&self, api: &API) -> Option<String> {
+        if self.page_cursor.is_some() || self.is_personal() {
+            return None;
         }
+
+        let query = serde_json::to_string(self).ok()?;
+
+        Some(format!("{}/{}", api.store_key(), query))
     }
 }
 
 pub async fn get_posts(
-    type_: Option<ListingType>,
-    page_cursor: Option<PaginationCursor>,
-) -> GetPostsResponse {
-    let post_config = PostsList::new(type_, page_cursor.clone());
-    let api = API::new(true);
-
+    api: Arc<API>,
+    post_config: PostsList,
+) -> Result<GetPostsResponse, LemnuxError> {
     let url = format!("{}/post/list", api.url.clone());
 
-    api.client
-        .get(url)
-        .query(&post_config)
-        .send()
-        .await
-        .unwrap()
-        .json::<GetPostsResponse>()
+    send(api.client.get(url).query(&post_config)).await
+}
+
+/// [`get_posts`], falling back to the stored first page when offline.
+pub async fn get_posts_stored(
+    api: Arc<API>,
+    query: PostsList,
+) -> Result<Fetched<GetPostsResponse>, LemnuxError> {
+    let key = query.store_key(&api);
+
+    fetch_stored(Kind::Listing, key, get_posts(api, query)).await
+}
+
+/// The first page of `query` as it was last fetched, to show while the
+/// instance is asked for a fresh one.
+pub async fn stored_posts(api: Arc<API>, query: PostsList) -> Option<Fetched<GetPostsResponse>> {
+    let key = query.store_key(&api)?;
+    let (value, fetched) = store::blocking(move || store::get(Kind::Listing, &key))
         .await
-        .unwrap()
+        .ok()??;
+
+    Some(Fetched {
+        value,
+        stale: Some(fetched),
+    })
+}
+
+pub async fn get_post(api: Arc<API>, post_id: PostId) -> Result<GetPostResponse, LemnuxError> {
+    let params = GetPost {
+        id: Some(post_id),
+        comment_id: None,
+    };
+
+    let url = format!("{}/post", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn get_post_stored(
+    api: Arc<API>,
+    post_id: PostId,
+) -> Result<Fetched<GetPostResponse>, LemnuxError> {
+    let key = format!("{}/{}", api.store_key(), post_id.0);
+
+    fetch_stored(Kind::Post, Some(key), get_post(api, post_id)).await
+}
+
+/// Fetches the comment tree of a post, or of a single thread when
+/// `parent_id` is set. `max_depth` is counted from the top of that tree.
+pub async fn get_comments(
+    api: Arc<API>,
+    post_id: PostId,
+    parent_id: Option<CommentId>,
+    max_depth: i32,
+) -> Result<GetCommentsResponse, LemnuxError> {
+    let params = GetComments {
+        type_: Some(ListingType::All),
+        sort: Some(CommentSortType::Hot),
+        max_depth: Some(max_depth),
+        post_id: Some(post_id),
+        parent_id,
+        ..Default::default()
+    };
+
+    let url = format!("{}/comment/list", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn get_comments_stored(
+    api: Arc<API>,
+    post_id: PostId,
+    parent_id: Option<CommentId>,
+    max_depth: i32,
+) -> Result<Fetched<GetCommentsResponse>, LemnuxError> {
+    let key = format!(
+        "{}/{}/{}/{}",
+        api.store_key(),
+        post_id.0,
+        parent_id.map_or(0, |id| id.0),
+        max_depth
+    );
+
+    fetch_stored(
+        Kind::Comments,
+        Some(key),
+        get_comments(api, post_id, parent_id, max_depth),
+    )
+    .await
+}
+
+/// Posts a comment under the post, or under `params.parent_id` when set.
+pub async fn create_comment(
+    api: Arc<API>,
+    params: CreateComment,
+) -> Result<CommentResponse, LemnuxError> {
+    let url = format!("{}/comment", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+pub async fn edit_comment(
+    api: Arc<API>,
+    params: EditComment,
+) -> Result<CommentResponse, LemnuxError> {
+    let url = format!("{}/comment", api.url.clone());
+
+    send(api.client.put(url).json(&params)).await
+}
+
+/// Deletes one of the user's own comments, or restores it when `deleted`
+/// is false.
+pub async fn delete_comment(
+    api: Arc<API>,
+    comment_id: CommentId,
+    deleted: bool,
+) -> Result<CommentResponse, LemnuxError> {
+    let params = DeleteComment {
+        comment_id,
+        deleted,
+    };
+    let url = format!("{}/comment/delete", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+/// Casts `score` (-1, 0 or 1) as the logged in user's vote on a post.
+pub async fn like_post(
+    api: Arc<API>,
+    post_id: PostId,
+    score: i16,
+) -> Result<PostResponse, LemnuxError> {
+    let params = CreatePostLike { post_id, score };
+    let url = format!("{}/post/like", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+pub async fn save_post(
+    api: Arc<API>,
+    post_id: PostId,
+    save: bool,
+) -> Result<PostResponse, LemnuxError> {
+    let params = SavePost { post_id, save };
+    let url = format!("{}/post/save", api.url.clone());
+
+    send(api.client.put(url).json(&params)).await
+}
+
+/// Keeps votes and saves that could not reach the instance, to be sent by
+/// [`replay_pending`].
+pub async fn queue_pending(api: Arc<API>, actions: Vec<PendingAction>) -> Result<(), LemnuxError> {
+    if api.username.is_none() {
+        return Err(LemnuxError::Auth(String::from("not_logged_in")));
+    }
+
+    let account = api.store_key();
+
+    store::blocking(move || store::queue(&account, &actions)).await
+}
+
+/// Hides `post_id` from the listings of the client's account for good.
+/// Lemmy 0.19.3 has no hiding of its own, so this stays on this device.
+pub async fn hide_post(api: Arc<API>, post_id: PostId) -> Result<(), LemnuxError> {
+    let account = api.store_key();
+
+    store::blocking(move || store::hide(&account, post_id)).await
+}
+
+/// Posts hidden with [`hide_post`] by the client's account.
+pub async fn hidden_posts(api: Arc<API>) -> Result<HashSet<PostId>, LemnuxError> {
+    let account = api.store_key();
+
+    store::blocking(move || store::hidden(&account)).await
+}
+
+/// What came of sending the queued votes and saves.
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    /// Posts whose actions went through.
+    pub sent: Vec<PostId>,
+    /// Actions the instance refused for good, which were dropped.
+    pub dropped: Vec<(PendingAction, LemnuxError)>,
+    /// Why the rest of the queue is still waiting, if it is.
+    pub unsent: Option<LemnuxError>,
+}
+
+/// Sends the votes and saves queued while offline, oldest first. An action
+/// is only dropped when the instance refuses it for good; any other error
+/// stops the replay and keeps the rest queued for the next attempt.
+pub async fn replay_pending(api: Arc<API>) -> Result<Replay, LemnuxError> {
+    let mut replay = Replay::default();
+
+    if api.username.is_none() {
+        return Ok(replay);
+    }
+
+    let account = api.store_key();
+
+    for (id, action) in store::blocking(move || store::pending(&account)).await? {
+        let result = match action {
+            PendingAction::Vote(post_id, score) => like_post(api.clone(), post_id, score).await,
+            PendingAction::Save(post_id, save) => save_post(api.clone(), post_id, save).await,
+        };
+
+        match result {
+            Ok(_) => replay.sent.push(action.post_id()),
+            Err(error) if error.is_refusal() => replay.dropped.push((action, error)),
+            Err(error) => {
+                replay.unsent = Some(error);
+                break;
+            }
+        }
+
+        store::blocking(move || store::done(id)).await?;
+    }
+
+    Ok(replay)
+}
+
+/// Comments the logged in user saved, newest first.
+pub async fn list_saved_comments(
+    api: Arc<API>,
+    page: i64,
+    limit: i64,
+) -> Result<GetCommentsResponse, LemnuxError> {
+    let params = GetComments {
+        type_: Some(ListingType::All),
+        sort: Some(CommentSortType::New),
+        page: Some(page),
+        limit: Some(limit),
+        saved_only: Some(true),
+        ..Default::default()
+    };
+    let url = format!("{}/comment/list", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn save_comment(
+    api: Arc<API>,
+    comment_id: CommentId,
+    save: bool,
+) -> Result<CommentResponse, LemnuxError> {
+    let params = SaveComment { comment_id, save };
+    let url = format!("{}/comment/save", api.url.clone());
+
+    send(api.client.put(url).json(&params)).await
+}
+
+/// `params` names the community either by id or by `name@instance`.
+pub async fn get_community(
+    api: Arc<API>,
+    params: GetCommunity,
+) -> Result<GetCommunityResponse, LemnuxError> {
+    let url = format!("{}/community", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn get_community_stored(
+    api: Arc<API>,
+    params: GetCommunity,
+) -> Result<Fetched<GetCommunityResponse>, LemnuxError> {
+    let key = format!(
+        "{}/{}",
+        api.store_key(),
+        serde_json::to_string(&params).unwrap_or_default()
+    );
+
+    fetch_stored(Kind::Community, Some(key), get_community(api, params)).await
+}
+
+pub async fn follow_community(
+    api: Arc<API>,
+    community_id: CommunityId,
+    follow: bool,
+) -> Result<CommunityResponse, LemnuxError> {
+    let params = FollowCommunity {
+        community_id,
+        follow,
+    };
+    let url = format!("{}/community/follow", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+pub async fn search(api: Arc<API>, params: Search) -> Result<SearchResponse, LemnuxError> {
+    let url = format!("{}/search", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+/// How many inbox entries of each kind are fetched at once.
+pub const INBOX_LIMIT: i64 = 50;
+
+/// Replies to the logged in user's posts and comments, newest first.
+pub async fn get_replies(
+    api: Arc<API>,
+    unread_only: bool,
+) -> Result<GetRepliesResponse, LemnuxError> {
+    let params = GetReplies {
+        sort: Some(CommentSortType::New),
+        limit: Some(INBOX_LIMIT),
+        unread_only: Some(unread_only),
+        ..Default::default()
+    };
+    let url = format!("{}/user/replies", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn get_mentions(
+    api: Arc<API>,
+    unread_only: bool,
+) -> Result<GetPersonMentionsResponse, LemnuxError> {
+    let params = GetPersonMentions {
+        sort: Some(CommentSortType::New),
+        limit: Some(INBOX_LIMIT),
+        unread_only: Some(unread_only),
+        ..Default::default()
+    };
+    let url = format!("{}/user/mention", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+/// Private messages sent and received, or only those with `creator_id`.
+pub async fn list_private_messages(
+    api: Arc<API>,
+    unread_only: bool,
+    creator_id: Option<PersonId>,
+    page: i64,
+) -> Result<PrivateMessagesResponse, LemnuxError> {
+    let params = GetPrivateMessages {
+        unread_only: Some(unread_only),
+        page: Some(page),
+        limit: Some(INBOX_LIMIT),
+        creator_id,
+    };
+    let url = format!("{}/private_message/list", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn get_unread_count(api: Arc<API>) -> Result<GetUnreadCountResponse, LemnuxError> {
+    let url = format!("{}/user/unread_count", api.url.clone());
+
+    send(api.client.get(url)).await
+}
+
+pub async fn mark_reply_read(
+    api: Arc<API>,
+    comment_reply_id: CommentReplyId,
+    read: bool,
+) -> Result<CommentReplyResponse, LemnuxError> {
+    let params = MarkCommentReplyAsRead {
+        comment_reply_id,
+        read,
+    };
+    let url = format!("{}/comment/mark_as_read", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+pub async fn mark_mention_read(
+    api: Arc<API>,
+    person_mention_id: PersonMentionId,
+    read: bool,
+) -> Result<PersonMentionResponse, LemnuxError> {
+    let params = MarkPersonMentionAsRead {
+        person_mention_id,
+        read,
+    };
+    let url = format!("{}/user/mention/mark_as_read", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+pub async fn mark_private_message_read(
+    api: Arc<API>,
+    private_message_id: PrivateMessageId,
+    read: bool,
+) -> Result<PrivateMessageResponse, LemnuxError> {
+    let params = MarkPrivateMessageAsRead {
+        private_message_id,
+        read,
+    };
+    let url = format!("{}/private_message/mark_as_read", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+/// Marks every reply, mention and private message as read.
+pub async fn mark_all_read(api: Arc<API>) -> Result<GetRepliesResponse, LemnuxError> {
+    let url = format!("{}/user/mark_all_as_read", api.url.clone());
+
+    send(api.client.post(url)).await
+}
+
+pub async fn create_private_message(
+    api: Arc<API>,
+    recipient_id: PersonId,
+    content: String,
+) -> Result<PrivateMessageResponse, LemnuxError> {
+    let params = CreatePrivateMessage {
+        content,
+        recipient_id,
+    };
+    let url = format!("{}/private_message", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+pub async fn edit_private_message(
+    api: Arc<API>,
+    private_message_id: PrivateMessageId,
+    content: String,
+) -> Result<PrivateMessageResponse, LemnuxError> {
+    let params = EditPrivateMessage {
+        private_message_id,
+        content,
+    };
+    let url = format!("{}/private_message", api.url.clone());
+
+    send(api.client.put(url).json(&params)).await
+}
+
+pub async fn delete_private_message(
+    api: Arc<API>,
+    private_message_id: PrivateMessageId,
+    deleted: bool,
+) -> Result<PrivateMessageResponse, LemnuxError> {
+    let params = DeletePrivateMessage {
+        private_message_id,
+        deleted,
+    };
+    let url = format!("{}/private_message/delete", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+/// Reports a received message to the instance admins.
+pub async fn report_private_message(
+    api: Arc<API>,
+    private_message_id: PrivateMessageId,
+    reason: String,
+) -> Result<PrivateMessageReportResponse, LemnuxError> {
+    let params = CreatePrivateMessageReport {
+        private_message_id,
+        reason,
+    };
+    let url = format!("{}/private_message/report", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
+}
+
+/// A person with their posts and comments, or with what they saved when
+/// `params.saved_only` is set for the logged in account.
+pub async fn get_person_details(
+    api: Arc<API>,
+    params: GetPersonDetails,
+) -> Result<GetPersonDetailsResponse, LemnuxError> {
+    let url = format!("{}/user", api.url.clone());
+
+    send(api.client.get(url).query(&params)).await
+}
+
+pub async fn block_person(
+    api: Arc<API>,
+    person_id: PersonId,
+    block: bool,
+) -> Result<BlockPersonResponse, LemnuxError> {
+    let params = BlockPerson { person_id, block };
+    let url = format!("{}/user/block", api.url.clone());
+
+    send(api.client.post(url).json(&params)).await
 }
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use iced::{
+    advanced::image::Handle,
+    theme,
+    widget::{button, column, row, text, Column, Container, Image},
+    Alignment, Command, Element, Length, Subscription,
+};
+use iced_aw::{badge, BadgeStyles};
+use lemmy_api_common::{
+    community::{CommunityResponse, GetCommunity, GetCommunityResponse},
+    lemmy_db_schema::{ListingType, SubscribedType},
+};
+
+use super::{
+    error_banner,
+    markdown::{self, Markdown},
+    posts::{self, load_image, Posts},
+    stale_banner,
+};
+use crate::{
+    api::{follow_community, get_community_stored, Fetched, LemnuxError, PostsList, API},
+    settings::ListingPreferences,
+};
+
+#[derive(Debug)]
+pub struct Community {
+    api: Arc<API>,
+    community: GetCommunity,
+    details: Option<GetCommunityResponse>,
+    icon: Option<Handle>,
+    banner: Option<Handle>,
+    posts: Posts,
+    description: Markdown,
+    following: bool,
+    /// When the shown details were fetched, if they come from the offline
+    /// store.
+    stale: Option<DateTime<Utc>>,
+    error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Back,
+    Retry,
+    Loaded(Result<Fetched<GetCommunityResponse>, LemnuxError>),
+    IconLoaded(Result<Handle, LemnuxError>),
+    BannerLoaded(Result<Handle, LemnuxError>),
+    Follow(bool),
+    Followed(Result<CommunityResponse, LemnuxError>),
+    Posts(posts::Message),
+    Markdown(markdown::Message),
+}
+
+impl Community {
+    /// Opens the community `community` points at, by id or by name.
+    pub fn new(api: Arc<API>, community: GetCommunity) -> (Self, Command<Message>) {
+        let query = PostsList {
+            community_id: community.id,
+            community_name: community.name.clone(),
+            ..PostsList::new(Some(ListingType::All), ListingPreferences::default())
+        };
+
+        let mut page = Self {
+            posts: Posts::new(api.clone(), query),
+            api,
+            community,
+            details: None,
+            icon: None,
+            banner: None,
+            description: Markdown::new(),
+            following: false,
+            stale: None,
+            error: None,
+        };
+        let cmd = Command::batch(vec![page.fetch(), page.posts.refresh().map(Message::Posts)]);
+
+        (page, cmd)
+    }
+
+    fn fetch(&mut self) -> Command<Message> {
+        self.error = None;
+
+        Command::perform(
+            get_community_stored(self.api.clone(), self.community.clone()),
+            Message::Loaded,
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Back => Command::none(),
+            Message::Retry => self.fetch(),
+            Message::Loaded(Ok(fetched)) => {
+                let details = fetched.value;
+
+                self.stale = fetched.stale;
+
+                let community = &details.community_view.community;
+                let mut cmds = Vec::new();
+
+                if let Some(icon) = &community.icon {
+                    cmds.push(Command::perform(
+                        load_image(self.api.clone(), icon.to_string()),
+                        Message::IconLoaded,
+                    ));
+                }
+
+                if let Some(banner) = &community.banner {
+                    cmds.push(Command::perform(
+                        load_image(self.api.clone(), banner.to_string()),
+                        Message::BannerLoaded,
+                    ));
+                }
+
+                if let Some(description) = &community.description {
+                    cmds.push(
+                        self.description
+                            .load_images(self.api.clone(), description)
+                            .map(Message::Markdown),
+                    );
+                }
+
+                self.details = Some(details);
+
+                Command::batch(cmds)
+            }
+            Message::Loaded(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            // Icons and banners are decoration; the page works without them.
+            Message::IconLoaded(icon) => {
+                self.icon = icon.ok();
+                Command::none()
+            }
+            Message::BannerLoaded(banner) => {
+                self.banner = banner.ok();
+                Command::none()
+            }
+            Message::Follow(follow) => {
+                let Some(details) = &self.details else {
+                    return Command::none();
+                };
+
+                self.following = true;
+
+                Command::perform(
+                    follow_community(
+                        self.api.clone(),
+                        details.community_view.community.id,
+                        follow,
+                    ),
+                    Message::Followed,
+                )
+            }
+            Message::Followed(result) => {
+                self.following = false;
+
+                match result {
+                    Ok(response) => {
+                        if let Some(details) = &mut self.details {
+                            details.community_view = response.community_view;
+                        }
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+
+                Command::none()
+            }
+            Message::Posts(message) => self.posts.update(message).map(Message::Posts),
+            Message::Markdown(message) => {
+                self.description.update(message);
+                Command::none()
+            }
+        }
+    }
+
+    fn view_header(&self, details: &GetCommunityResponse) -> Element<'_, Message> {
+        let view = &details.community_view;
+        let mut col = column!().spacing(15);
+
+        if let Some(banner) = &self.banner {
+            col = col.push(
+                Container::new(Image::new(banner.clone()).height(120))
+                    .width(Length::Fill)
+                    .center_x(),
+            );
+        }
+
+        let follow_btn = match view.subscribed {
+            SubscribedType::Subscribed => button("Unsubscribe").on_press(Message::Follow(false)),
+            SubscribedType::Pending => button("Pending (cancel)").on_press(Message::Follow(false)),
+            SubscribedType::NotSubscribed => button("Subscribe").on_press(Message::Follow(true)),
+        };
+        let follow_btn = if self.following {
+            follow_btn.on_press_maybe(None)
+        } else {
+            follow_btn
+        };
+
+        let mut title_row = row!().spacing(15).align_items(Alignment::Center);
+
+        if let Some(icon) = &self.icon {
+            title_row = title_row.push(Image::new(icon.clone()).width(48).height(48));
+        }
+
+        title_row = title_row
+            .push(text(&view.community.title).size(24))
+            .push(follow_btn);
+
+        col = col.push(title_row).push(
+            row!(
+                badge(text(format!("{} subscribers", view.counts.subscribers)))
+                    .style(BadgeStyles::Info),
+                badge(text(format!("{} posts", view.counts.posts))).style(BadgeStyles::Info),
+                badge(text(format!("{} comments", view.counts.comments))).style(BadgeStyles::Info),
+            )
+            .spacing(10),
+        );
+
+        if let Some(description) = &view.community.description {
+            col = col.push(self.description.view(description).map(Message::Markdown));
+        }
+
+        if !details.moderators.is_empty() {
+            let moderators = Column::with_children(
+                details
+                    .moderators
+                    .iter()
+                    .map(|moderator| text(&moderator.moderator.name).into()),
+            );
+
+            col = col.push(text("Moderators")).push(moderators);
+        }
+
+        col.into()
+    }
+
+    pub fn posts_mut(&mut self) -> &mut Posts {
+        &mut self.posts
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        self.posts.subscription().map(Message::Posts)
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut col = column!(button("Back")
+            .style(theme::Button::Secondary)
+            .on_press(Message::Back))
+        .spacing(15)
+        .padding(30);
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(error, Some(Message::Retry)));
+        }
+
+        if let Some(fetched) = self.stale {
+            col = col.push(stale_banner(fetched, false, Some(Message::Retry)));
+        }
+
+        if let Some(details) = &self.details {
+            col = col.push(self.view_header(details));
+        }
+
+        column!(col, self.posts.view().map(Message::Posts)).into()
+    }
+}
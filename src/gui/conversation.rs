@@ -0,0 +1,508 @@
+use std::sync::Arc;
+
+use iced::{
+    alignment, theme,
+    widget::{
+        button, column, row, text, text_editor,
+        text_editor::{Action, Content, Motion},
+        text_input, Column, Container, Scrollable,
+    },
+    Alignment, Command, Element, Length,
+};
+use iced_aw::{badge, BadgeStyles};
+use lemmy_api_common::{
+    lemmy_db_schema::{newtypes::PrivateMessageId, source::person::Person},
+    lemmy_db_views::structs::PrivateMessageView,
+    private_message::{
+        PrivateMessageReportResponse, PrivateMessageResponse, PrivateMessagesResponse,
+    },
+};
+
+use super::{
+    error_banner,
+    markdown::{self, Markdown},
+};
+use crate::{
+    api::{
+        create_private_message, delete_private_message, edit_private_message,
+        list_private_messages, report_private_message, LemnuxError, API, INBOX_LIMIT,
+    },
+    settings::Drafts,
+};
+
+/// Private messages exchanged with one person, oldest first.
+#[derive(Debug)]
+pub struct Conversation {
+    api: Arc<API>,
+    person: Person,
+    messages: Vec<PrivateMessageView>,
+    /// Newest page of messages is 1; older ones are loaded on demand.
+    page: i64,
+    /// The last page loaded was full, so there may be older messages.
+    has_older: bool,
+    loading: bool,
+    draft: Content,
+    editing: Option<(PrivateMessageId, Content)>,
+    /// Message being reported and the reason typed so far.
+    reporting: Option<(PrivateMessageId, String)>,
+    /// Own message whose Delete button was pressed once.
+    confirm_delete: Option<PrivateMessageId>,
+    sending: bool,
+    notice: Option<String>,
+    markdown: Markdown,
+    error: Option<LemnuxError>,
+    action_error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Back,
+    Retry,
+    LoadOlder,
+    Loaded(i64, Result<PrivateMessagesResponse, LemnuxError>),
+    DraftAction(Action),
+    Send,
+    Sent(Result<PrivateMessageResponse, LemnuxError>),
+    Edit(PrivateMessageId),
+    EditAction(Action),
+    CancelEdit,
+    SaveEdit,
+    Edited(Result<PrivateMessageResponse, LemnuxError>),
+    Delete(PrivateMessageId),
+    Deleted(
+        PrivateMessageId,
+        Result<PrivateMessageResponse, LemnuxError>,
+    ),
+    Report(PrivateMessageId),
+    ReportReason(String),
+    CancelReport,
+    SubmitReport,
+    Reported(Result<PrivateMessageReportResponse, LemnuxError>),
+    Markdown(markdown::Message),
+}
+
+impl Conversation {
+    pub fn new(api: Arc<API>, person: Person) -> (Self, Command<Message>) {
+        let mut draft = Content::with_text(
+            &Drafts::get(&Drafts::key(&api.domain, "message", person.id.0)).unwrap_or_default(),
+        );
+        draft.perform(Action::Move(Motion::DocumentEnd));
+
+        let mut page = Self {
+            api,
+            person,
+            messages: Vec::new(),
+            page: 1,
+            has_older: false,
+            loading: false,
+            draft,
+            editing: None,
+            reporting: None,
+            confirm_delete: None,
+            sending: false,
+            notice: None,
+            markdown: Markdown::new(),
+            error: None,
+            action_error: None,
+        };
+        let cmd = page.fetch(1);
+
+        (page, cmd)
+    }
+
+    fn fetch(&mut self, page: i64) -> Command<Message> {
+        self.error = None;
+        self.loading = true;
+
+        Command::perform(
+            list_private_messages(self.api.clone(), false, Some(self.person.id), page),
+            move |ret| Message::Loaded(page, ret),
+        )
+    }
+
+    fn draft_key(&self) -> String {
+        Drafts::key(&self.api.domain, "message", self.person.id.0)
+    }
+
+    fn save_draft(&self, text: String) {
+        Drafts::set(self.draft_key(), text);
+    }
+
+    /// Adds a message or replaces the copy shown, keeping the thread in
+    /// the order the messages were written.
+    fn upsert(&mut self, view: PrivateMessageView) -> Command<Message> {
+        let cmd = self
+            .markdown
+            .load_images(self.api.clone(), &view.private_message.content)
+            .map(Message::Markdown);
+
+        match self
+            .messages
+            .iter_mut()
+            .find(|message| message.private_message.id == view.private_message.id)
+        {
+            Some(message) => *message = view,
+            None => self.messages.push(view),
+        }
+
+        self.messages
+            .sort_by_key(|message| message.private_message.published);
+
+        cmd
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Back => Command::none(),
+            Message::Retry => self.fetch(self.page),
+            Message::LoadOlder => {
+                if self.loading {
+                    return Command::none();
+                }
+
+                self.fetch(self.page + 1)
+            }
+            Message::Loaded(page, Ok(response)) => {
+                self.loading = false;
+                self.page = page;
+                self.has_older = response.private_messages.len() as i64 >= INBOX_LIMIT;
+
+                // The newest page starts the thread over; older ones add to it.
+                if page == 1 {
+                    self.messages.clear();
+                }
+
+                let person_id = self.person.id;
+                let cmds: Vec<_> = response
+                    .private_messages
+                    .into_iter()
+                    .filter(|view| view.creator.id == person_id || view.recipient.id == person_id)
+                    .filter(|view| !view.private_message.deleted)
+                    .map(|view| self.upsert(view))
+                    .collect();
+
+                Command::batch(cmds)
+            }
+            Message::Loaded(_, Err(error)) => {
+                self.loading = false;
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::DraftAction(action) => {
+                let is_edit = action.is_edit();
+                self.draft.perform(action);
+
+                if is_edit {
+                    self.save_draft(self.draft.text());
+                }
+
+                Command::none()
+            }
+            Message::Send => {
+                let content = self.draft.text().trim().to_string();
+
+                if self.sending || content.is_empty() {
+                    return Command::none();
+                }
+
+                self.sending = true;
+                self.action_error = None;
+
+                Command::perform(
+                    create_private_message(self.api.clone(), self.person.id, content),
+                    Message::Sent,
+                )
+            }
+            Message::Sent(Ok(response)) => {
+                self.sending = false;
+                self.draft = Content::new();
+                self.save_draft(String::new());
+
+                self.upsert(response.private_message_view)
+            }
+            Message::Edit(id) => {
+                if let Some(view) = self
+                    .messages
+                    .iter()
+                    .find(|message| message.private_message.id == id)
+                {
+                    let mut content = Content::with_text(&view.private_message.content);
+                    content.perform(Action::Move(Motion::DocumentEnd));
+
+                    self.editing = Some((id, content));
+                }
+
+                Command::none()
+            }
+            Message::EditAction(action) => {
+                if let Some((_, content)) = &mut self.editing {
+                    content.perform(action);
+                }
+
+                Command::none()
+            }
+            Message::CancelEdit => {
+                self.editing = None;
+                Command::none()
+            }
+            Message::SaveEdit => {
+                let Some((id, content)) = &self.editing else {
+                    return Command::none();
+                };
+                let content = content.text().trim().to_string();
+
+                if self.sending || content.is_empty() {
+                    return Command::none();
+                }
+
+                self.sending = true;
+                self.action_error = None;
+
+                Command::perform(
+                    edit_private_message(self.api.clone(), *id, content),
+                    Message::Edited,
+                )
+            }
+            Message::Edited(Ok(response)) => {
+                self.sending = false;
+                self.editing = None;
+
+                self.upsert(response.private_message_view)
+            }
+            Message::Sent(Err(error)) | Message::Edited(Err(error)) => {
+                self.sending = false;
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::Delete(id) => {
+                if self.confirm_delete != Some(id) {
+                    self.confirm_delete = Some(id);
+                    return Command::none();
+                }
+
+                self.confirm_delete = None;
+                self.action_error = None;
+
+                Command::perform(
+                    delete_private_message(self.api.clone(), id, true),
+                    move |ret| Message::Deleted(id, ret),
+                )
+            }
+            Message::Deleted(id, Ok(_)) => {
+                self.messages
+                    .retain(|message| message.private_message.id != id);
+                Command::none()
+            }
+            Message::Deleted(_, Err(error)) => {
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::Report(id) => {
+                self.reporting = Some((id, String::new()));
+                Command::none()
+            }
+            Message::ReportReason(reason) => {
+                if let Some((_, current)) = &mut self.reporting {
+                    *current = reason;
+                }
+
+                Command::none()
+            }
+            Message::CancelReport => {
+                self.reporting = None;
+                Command::none()
+            }
+            Message::SubmitReport => {
+                let Some((id, reason)) = &self.reporting else {
+                    return Command::none();
+                };
+
+                if reason.trim().is_empty() {
+                    return Command::none();
+                }
+
+                self.action_error = None;
+
+                Command::perform(
+                    report_private_message(self.api.clone(), *id, reason.trim().to_string()),
+                    Message::Reported,
+                )
+            }
+            Message::Reported(Ok(_)) => {
+                self.reporting = None;
+                self.notice = Some(String::from(
+                    "Thanks, the admins of your instance will look at the report.",
+                ));
+                Command::none()
+            }
+            Message::Reported(Err(error)) => {
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::Markdown(message) => {
+                self.markdown.update(message);
+                Command::none()
+            }
+        }
+    }
+
+    fn view_message<'a>(&'a self, view: &'a PrivateMessageView) -> Element<'a, Message> {
+        let id = view.private_message.id;
+        let mine = self.api.is_me(&view.creator);
+
+        let mut header = row!(
+            badge(text(&view.creator.name)).style(if mine {
+                BadgeStyles::Primary
+            } else {
+                BadgeStyles::Secondary
+            }),
+            badge(text(view.private_message.published.to_rfc2822())).style(BadgeStyles::Info),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        if view.private_message.updated.is_some() {
+            header = header.push(text("(edited)"));
+        }
+
+        if mine {
+            let delete_label = if self.confirm_delete == Some(id) {
+                "Confirm delete"
+            } else {
+                "Delete"
+            };
+
+            header = header
+                .push(
+                    button("Edit")
+                        .style(theme::Button::Text)
+                        .on_press(Message::Edit(id)),
+                )
+                .push(
+                    button(delete_label)
+                        .style(theme::Button::Text)
+                        .on_press(Message::Delete(id)),
+                );
+        } else {
+            header = header.push(
+                button("Report")
+                    .style(theme::Button::Text)
+                    .on_press(Message::Report(id)),
+            );
+        }
+
+        let mut col = column!(header).spacing(8);
+
+        col = match &self.editing {
+            Some((editing, content)) if *editing == id => {
+                let label = if self.sending { "Saving..." } else { "Save" };
+
+                col.push(
+                    text_editor(content)
+                        .on_action(Message::EditAction)
+                        .height(Length::Fixed(100.0)),
+                )
+                .push(
+                    row!(
+                        button(label).on_press_maybe((!self.sending).then_some(Message::SaveEdit)),
+                        button("Cancel")
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::CancelEdit),
+                    )
+                    .spacing(10),
+                )
+            }
+            _ => col.push(
+                self.markdown
+                    .view(&view.private_message.content)
+                    .map(Message::Markdown),
+            ),
+        };
+
+        if let Some((reporting, reason)) = &self.reporting {
+            if *reporting == id {
+                col = col.push(
+                    row!(
+                        text_input("Why should the admins look at this?", reason)
+                            .on_input(Message::ReportReason)
+                            .on_submit(Message::SubmitReport),
+                        button("Report").on_press(Message::SubmitReport),
+                        button("Cancel")
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::CancelReport),
+                    )
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                );
+            }
+        }
+
+        // Own messages sit on the right, like in any messenger.
+        Container::new(Container::new(col).padding(10).style(theme::Container::Box))
+            .width(Length::Fill)
+            .align_x(if mine {
+                alignment::Horizontal::Right
+            } else {
+                alignment::Horizontal::Left
+            })
+            .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut col = column!(
+            button("Back")
+                .style(theme::Button::Secondary)
+                .on_press(Message::Back),
+            text(format!("Conversation with {}", self.person.name)).size(24),
+        )
+        .spacing(20)
+        .padding(30);
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(error, Some(Message::Retry)));
+        }
+
+        if let Some(error) = &self.action_error {
+            col = col.push(error_banner(error, None));
+        }
+
+        if let Some(notice) = &self.notice {
+            col = col.push(badge(text(notice)).style(BadgeStyles::Success));
+        }
+
+        if self.loading {
+            col = col.push(text("Loading..."));
+        } else if self.messages.is_empty() && self.error.is_none() {
+            col = col.push(text("No messages yet. Say hello!"));
+        }
+
+        if self.has_older {
+            col = col.push(
+                button("Load older messages")
+                    .style(theme::Button::Secondary)
+                    .on_press_maybe((!self.loading).then_some(Message::LoadOlder)),
+            );
+        }
+
+        col = col.push(
+            Column::with_children(
+                self.messages
+                    .iter()
+                    .map(|message| self.view_message(message)),
+            )
+            .spacing(15),
+        );
+
+        let label = if self.sending { "Sending..." } else { "Send" };
+
+        col = col
+            .push(
+                text_editor(&self.draft)
+                    .on_action(Message::DraftAction)
+                    .height(Length::Fixed(120.0)),
+            )
+            .push(button(label).on_press_maybe((!self.sending).then_some(Message::Send)));
+
+        Container::new(Scrollable::new(col).width(Length::Fill)).into()
+    }
+}
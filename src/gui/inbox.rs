@@ -0,0 +1,521 @@
+use std::{fmt::Display, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use iced::{
+    theme,
+    widget::{
+        button, checkbox, column, horizontal_rule, pick_list, row, text, Container, Scrollable,
+    },
+    Alignment, Command, Element, Length,
+};
+use iced_aw::{badge, BadgeStyles};
+use lemmy_api_common::{
+    lemmy_db_schema::{
+        newtypes::{CommentReplyId, PersonMentionId, PostId, PrivateMessageId},
+        source::person::Person,
+    },
+    lemmy_db_views::structs::PrivateMessageView,
+    lemmy_db_views_actor::structs::{CommentReplyView, PersonMentionView},
+    person::{
+        CommentReplyResponse, GetPersonMentionsResponse, GetRepliesResponse, PersonMentionResponse,
+    },
+    private_message::{PrivateMessageResponse, PrivateMessagesResponse},
+};
+
+use super::{
+    error_banner,
+    markdown::{self, Markdown},
+};
+use crate::api::{
+    get_mentions, get_replies, list_private_messages, mark_all_read, mark_mention_read,
+    mark_private_message_read, mark_reply_read, LemnuxError, API,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboxFilter {
+    All,
+    Replies,
+    Mentions,
+    Messages,
+}
+
+impl InboxFilter {
+    const ALL: [InboxFilter; 4] = [
+        InboxFilter::All,
+        InboxFilter::Replies,
+        InboxFilter::Mentions,
+        InboxFilter::Messages,
+    ];
+}
+
+impl Display for InboxFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            InboxFilter::All => "All",
+            InboxFilter::Replies => "Replies",
+            InboxFilter::Mentions => "Mentions",
+            InboxFilter::Messages => "Private messages",
+        };
+
+        write!(f, "{label}")
+    }
+}
+
+/// One row of the inbox, whatever it came from.
+enum Entry<'a> {
+    Reply(&'a CommentReplyView),
+    Mention(&'a PersonMentionView),
+    Message(&'a PrivateMessageView),
+}
+
+impl Entry<'_> {
+    fn published(&self) -> DateTime<Utc> {
+        match self {
+            Entry::Reply(view) => view.comment_reply.published,
+            Entry::Mention(view) => view.person_mention.published,
+            Entry::Message(view) => view.private_message.published,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Inbox {
+    api: Arc<API>,
+    filter: InboxFilter,
+    unread_only: bool,
+    replies: Vec<CommentReplyView>,
+    mentions: Vec<PersonMentionView>,
+    messages: Vec<PrivateMessageView>,
+    /// Requests of the last fetch still running.
+    loading: usize,
+    markdown: Markdown,
+    error: Option<LemnuxError>,
+    action_error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Retry,
+    SetFilter(InboxFilter),
+    UnreadOnly(bool),
+    RepliesLoaded(Result<GetRepliesResponse, LemnuxError>),
+    MentionsLoaded(Result<GetPersonMentionsResponse, LemnuxError>),
+    MessagesLoaded(Result<PrivateMessagesResponse, LemnuxError>),
+    MarkReply(CommentReplyId, bool),
+    ReplyMarked(Result<CommentReplyResponse, LemnuxError>),
+    MarkMention(PersonMentionId, bool),
+    MentionMarked(Result<PersonMentionResponse, LemnuxError>),
+    MarkMessage(PrivateMessageId, bool),
+    MessageMarked(Result<PrivateMessageResponse, LemnuxError>),
+    MarkAllRead,
+    AllMarked(Result<GetRepliesResponse, LemnuxError>),
+    OpenPost(PostId),
+    /// Opens the conversation with the other side of a private message.
+    OpenConversation(Person),
+    Markdown(markdown::Message),
+}
+
+impl Message {
+    /// Whether the message changed what is unread, so the counter next to
+    /// the tabs is out of date.
+    pub fn changes_unread(&self) -> bool {
+        matches!(
+            self,
+            Message::ReplyMarked(Ok(_))
+                | Message::MentionMarked(Ok(_))
+                | Message::MessageMarked(Ok(_))
+                | Message::AllMarked(Ok(_))
+        )
+    }
+}
+
+impl Inbox {
+    pub fn new(api: Arc<API>) -> (Self, Command<Message>) {
+        let mut page = Self {
+            api,
+            filter: InboxFilter::All,
+            unread_only: true,
+            replies: Vec::new(),
+            mentions: Vec::new(),
+            messages: Vec::new(),
+            loading: 0,
+            markdown: Markdown::new(),
+            error: None,
+            action_error: None,
+        };
+        let cmd = page.fetch();
+
+        (page, cmd)
+    }
+
+    fn fetch(&mut self) -> Command<Message> {
+        self.error = None;
+
+        if self.api.username.is_none() {
+            return Command::none();
+        }
+
+        self.loading = 3;
+
+        Command::batch(vec![
+            Command::perform(
+                get_replies(self.api.clone(), self.unread_only),
+                Message::RepliesLoaded,
+            ),
+            Command::perform(
+                get_mentions(self.api.clone(), self.unread_only),
+                Message::MentionsLoaded,
+            ),
+            Command::perform(
+                list_private_messages(self.api.clone(), self.unread_only, None, 1),
+                Message::MessagesLoaded,
+            ),
+        ])
+    }
+
+    /// Bookkeeping shared by the three fetches.
+    fn loaded<T>(&mut self, result: Result<T, LemnuxError>) -> Option<T> {
+        self.loading = self.loading.saturating_sub(1);
+
+        match result {
+            Ok(response) => Some(response),
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
+        }
+    }
+
+    fn load_images<'a>(
+        markdown: &mut Markdown,
+        api: &Arc<API>,
+        sources: impl Iterator<Item = &'a String>,
+    ) -> Command<Message> {
+        Command::batch(
+            sources
+                .map(|source| {
+                    markdown
+                        .load_images(api.clone(), source)
+                        .map(Message::Markdown)
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Retry => self.fetch(),
+            Message::SetFilter(filter) => {
+                self.filter = filter;
+                Command::none()
+            }
+            Message::UnreadOnly(unread_only) => {
+                self.unread_only = unread_only;
+                self.fetch()
+            }
+            Message::RepliesLoaded(result) => {
+                let Some(response) = self.loaded(result) else {
+                    return Command::none();
+                };
+
+                self.replies = response.replies;
+                Self::load_images(
+                    &mut self.markdown,
+                    &self.api,
+                    self.replies.iter().map(|view| &view.comment.content),
+                )
+            }
+            Message::MentionsLoaded(result) => {
+                let Some(response) = self.loaded(result) else {
+                    return Command::none();
+                };
+
+                self.mentions = response.mentions;
+                Self::load_images(
+                    &mut self.markdown,
+                    &self.api,
+                    self.mentions.iter().map(|view| &view.comment.content),
+                )
+            }
+            Message::MessagesLoaded(result) => {
+                let Some(response) = self.loaded(result) else {
+                    return Command::none();
+                };
+
+                self.messages = response.private_messages;
+                Self::load_images(
+                    &mut self.markdown,
+                    &self.api,
+                    self.messages
+                        .iter()
+                        .map(|view| &view.private_message.content),
+                )
+            }
+            Message::MarkReply(id, read) => {
+                self.action_error = None;
+
+                Command::perform(
+                    mark_reply_read(self.api.clone(), id, read),
+                    Message::ReplyMarked,
+                )
+            }
+            Message::ReplyMarked(Ok(response)) => {
+                let view = response.comment_reply_view;
+
+                if let Some(reply) = self
+                    .replies
+                    .iter_mut()
+                    .find(|reply| reply.comment_reply.id == view.comment_reply.id)
+                {
+                    *reply = view;
+                }
+
+                Command::none()
+            }
+            Message::MentionMarked(Ok(response)) => {
+                let view = response.person_mention_view;
+
+                if let Some(mention) = self
+                    .mentions
+                    .iter_mut()
+                    .find(|mention| mention.person_mention.id == view.person_mention.id)
+                {
+                    *mention = view;
+                }
+
+                Command::none()
+            }
+            Message::MessageMarked(Ok(response)) => {
+                let view = response.private_message_view;
+
+                if let Some(message) = self
+                    .messages
+                    .iter_mut()
+                    .find(|message| message.private_message.id == view.private_message.id)
+                {
+                    *message = view;
+                }
+
+                Command::none()
+            }
+            Message::MarkMention(id, read) => {
+                self.action_error = None;
+
+                Command::perform(
+                    mark_mention_read(self.api.clone(), id, read),
+                    Message::MentionMarked,
+                )
+            }
+            Message::MarkMessage(id, read) => {
+                self.action_error = None;
+
+                Command::perform(
+                    mark_private_message_read(self.api.clone(), id, read),
+                    Message::MessageMarked,
+                )
+            }
+            Message::MarkAllRead => {
+                self.action_error = None;
+
+                Command::perform(mark_all_read(self.api.clone()), Message::AllMarked)
+            }
+            Message::AllMarked(Ok(_)) => {
+                for reply in &mut self.replies {
+                    reply.comment_reply.read = true;
+                }
+
+                for mention in &mut self.mentions {
+                    mention.person_mention.read = true;
+                }
+
+                for message in &mut self.messages {
+                    if self.api.is_me(&message.recipient) {
+                        message.private_message.read = true;
+                    }
+                }
+
+                Command::none()
+            }
+            Message::ReplyMarked(Err(error))
+            | Message::MentionMarked(Err(error))
+            | Message::MessageMarked(Err(error))
+            | Message::AllMarked(Err(error)) => {
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::OpenPost(_) | Message::OpenConversation(_) => Command::none(),
+            Message::Markdown(message) => {
+                self.markdown.update(message);
+                Command::none()
+            }
+        }
+    }
+
+    fn entries(&self) -> Vec<Entry<'_>> {
+        let show = |filter| self.filter == InboxFilter::All || self.filter == filter;
+        let mut entries = Vec::new();
+
+        if show(InboxFilter::Replies) {
+            entries.extend(self.replies.iter().map(Entry::Reply));
+        }
+
+        if show(InboxFilter::Mentions) {
+            entries.extend(self.mentions.iter().map(Entry::Mention));
+        }
+
+        if show(InboxFilter::Messages) {
+            entries.extend(self.messages.iter().map(Entry::Message));
+        }
+
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.published()));
+
+        entries
+    }
+
+    fn view_entry<'a>(&'a self, entry: Entry<'a>) -> Element<'a, Message> {
+        let mut conversation = None;
+        let (kind, creator, content, read, mark, post) = match entry {
+            Entry::Reply(view) => (
+                "Reply",
+                &view.creator,
+                &view.comment.content,
+                view.comment_reply.read,
+                Some(Message::MarkReply(
+                    view.comment_reply.id,
+                    !view.comment_reply.read,
+                )),
+                Some(&view.post),
+            ),
+            Entry::Mention(view) => (
+                "Mention",
+                &view.creator,
+                &view.comment.content,
+                view.person_mention.read,
+                Some(Message::MarkMention(
+                    view.person_mention.id,
+                    !view.person_mention.read,
+                )),
+                Some(&view.post),
+            ),
+            Entry::Message(view) => {
+                // Only the recipient can mark a message read.
+                let received = self.api.is_me(&view.recipient);
+                conversation = Some(if received {
+                    &view.creator
+                } else {
+                    &view.recipient
+                });
+
+                (
+                    if received {
+                        "Private message"
+                    } else {
+                        "Sent message"
+                    },
+                    &view.creator,
+                    &view.private_message.content,
+                    view.private_message.read || !received,
+                    received.then_some(Message::MarkMessage(
+                        view.private_message.id,
+                        !view.private_message.read,
+                    )),
+                    None,
+                )
+            }
+        };
+
+        let mut header = row!(
+            badge(text(kind)).style(if read {
+                BadgeStyles::Secondary
+            } else {
+                BadgeStyles::Warning
+            }),
+            badge(text(&creator.name)).style(BadgeStyles::Primary),
+            badge(text(entry.published().to_rfc2822())).style(BadgeStyles::Info),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        if let Some(post) = post {
+            header = header.push(
+                button(text(format!("in {}", post.name)))
+                    .style(theme::Button::Text)
+                    .on_press(Message::OpenPost(post.id)),
+            );
+        }
+
+        if let Some(person) = conversation {
+            header = header.push(
+                button("Conversation")
+                    .style(theme::Button::Text)
+                    .on_press(Message::OpenConversation(person.clone())),
+            );
+        }
+
+        if let Some(mark) = mark {
+            header = header.push(
+                button(if read { "Mark unread" } else { "Mark read" })
+                    .style(theme::Button::Secondary)
+                    .on_press(mark),
+            );
+        }
+
+        column!(
+            header,
+            self.markdown.view(content).map(Message::Markdown),
+            horizontal_rule(1),
+        )
+        .spacing(8)
+        .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut col = column!().spacing(20).padding(30);
+
+        if self.api.username.is_none() {
+            col = col.push(text("Log in from the settings to see your inbox."));
+
+            return Container::new(col).into();
+        }
+
+        col = col.push(
+            row!(
+                text("Show"),
+                pick_list(&InboxFilter::ALL[..], Some(self.filter), Message::SetFilter),
+                checkbox("Unread only", self.unread_only).on_toggle(Message::UnreadOnly),
+                button("Mark all read").on_press(Message::MarkAllRead),
+                button("Refresh")
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::Retry),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+        );
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(error, Some(Message::Retry)));
+        }
+
+        if let Some(error) = &self.action_error {
+            col = col.push(error_banner(error, None));
+        }
+
+        let entries = self.entries();
+
+        if self.loading > 0 {
+            col = col.push(text("Loading..."));
+        } else if entries.is_empty() && self.error.is_none() {
+            col = col.push(text(if self.unread_only {
+                "Nothing unread."
+            } else {
+                "Your inbox is empty."
+            }));
+        }
+
+        for entry in entries {
+            col = col.push(self.view_entry(entry));
+        }
+
+        Container::new(Scrollable::new(col).width(Length::Fill)).into()
+    }
+}
@@ -0,0 +1,745 @@
+//! Renders Lemmy flavoured markdown into iced widgets.
+//!
+//! Lemmy adds two things on top of CommonMark: `::: spoiler title` blocks
+//! and `!community@instance` / `@user@instance` mentions. Mentions become
+//! [`Link`]s so pages can navigate to them inside the app.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use iced::{
+    advanced::image::Handle,
+    font, theme,
+    widget::{button, column, horizontal_rule, row, text, Column, Container, Image},
+    Command, Element, Font, Length,
+};
+use iced_aw::Wrap;
+use lemmy_api_common::lemmy_db_schema::newtypes::PostId;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use reqwest::Url;
+
+use super::posts::load_image;
+use crate::api::{LemnuxError, API};
+
+/// A link found in markdown, already sorted into what the app can open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Link {
+    Url(String),
+    Post(PostId),
+    /// `name@instance`, or just `name` for the current instance.
+    Community(String),
+    /// `name@instance`, or just `name` for the current instance.
+    Person(String),
+}
+
+impl Link {
+    fn from_href(href: &str) -> Self {
+        Self::from_path(href)
+            .or_else(|| Self::from_word(href))
+            .unwrap_or_else(|| Link::Url(href.to_string()))
+    }
+
+    /// Recognises the paths of Lemmy's own post, community and user pages.
+    fn from_path(path: &str) -> Option<Self> {
+        let path = path.trim_end_matches('/');
+
+        if let Some(name) = path.strip_prefix("/c/").filter(|name| !name.is_empty()) {
+            Some(Link::Community(name.to_string()))
+        } else if let Some(name) = path.strip_prefix("/u/").filter(|name| !name.is_empty()) {
+            Some(Link::Person(name.to_string()))
+        } else {
+            let id = path.strip_prefix("/post/")?.parse().ok()?;
+
+            Some(Link::Post(PostId(id)))
+        }
+    }
+
+    /// Makes a [`Link::Url`] absolute against the instance at `domain`.
+    /// Pages of that instance the app can show itself are turned into the
+    /// matching link, and anything that isn't http(s) gives `None`.
+    pub fn resolve(self, domain: &str) -> Option<Self> {
+        let Link::Url(href) = self else {
+            return Some(self);
+        };
+
+        let base = Url::parse(&format!("https://{}/", domain)).ok()?;
+        let url = base.join(&href).ok()?;
+
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+
+        if url.host_str() == Some(domain) && url.query().is_none() {
+            if let Some(link) = Self::from_path(url.path()) {
+                return Some(link);
+            }
+        }
+
+        Some(Link::Url(url.into()))
+    }
+
+    /// Recognises mentions and bare URLs written in plain text.
+    fn from_word(word: &str) -> Option<Self> {
+        let is_mention = |name: &str| {
+            name.split_once('@').is_some_and(|(user, instance)| {
+                !user.is_empty()
+                    && instance.contains('.')
+                    && user.chars().all(|c| c.is_alphanumeric() || c == '_')
+            })
+        };
+
+        if let Some(name) = word.strip_prefix('!').filter(|name| is_mention(name)) {
+            Some(Link::Community(name.to_string()))
+        } else if let Some(name) = word.strip_prefix('@').filter(|name| is_mention(name)) {
+            Some(Link::Person(name.to_string()))
+        } else if word.starts_with("https://") || word.starts_with("http://") {
+            Some(Link::Url(word.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Link(Link),
+    ToggleSpoiler(u64),
+    ImageLoaded(String, Result<Handle, LemnuxError>),
+}
+
+/// Per page state: the parsed documents, which spoilers are open and which
+/// inline images loaded.
+#[derive(Debug, Default)]
+pub struct Markdown {
+    /// Blocks of every source passed to [`Markdown::load_images`], by hash.
+    documents: HashMap<u64, Vec<Block>>,
+    revealed: HashSet<u64>,
+    images: HashMap<String, Option<Handle>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Style {
+    strong: bool,
+    emphasis: bool,
+    code: bool,
+}
+
+#[derive(Debug)]
+enum Inline {
+    Text {
+        text: String,
+        style: Style,
+        link: Option<Link>,
+    },
+    Image {
+        url: String,
+        alt: String,
+    },
+    Break,
+}
+
+#[derive(Debug)]
+enum Block {
+    Heading(HeadingLevel, Vec<Inline>),
+    Paragraph(Vec<Inline>),
+    List(Option<u64>, Vec<Vec<Block>>),
+    Code(String),
+    Quote(Vec<Block>),
+    Rule,
+    Spoiler {
+        key: u64,
+        title: String,
+        blocks: Vec<Block>,
+    },
+}
+
+enum Frame {
+    Blocks(Vec<Block>),
+    Quote(Vec<Block>),
+    List(Option<u64>, Vec<Vec<Block>>),
+    Item(Vec<Block>),
+}
+
+/// Turns pulldown-cmark's event stream into a block tree.
+#[derive(Default)]
+struct Builder {
+    stack: Vec<Frame>,
+    inlines: Vec<Inline>,
+    style: Style,
+    link: Option<Link>,
+    image: Option<(String, String)>,
+    code: Option<String>,
+}
+
+impl Builder {
+    fn push_block(&mut self, block: Block) {
+        if let Some(Frame::Blocks(blocks) | Frame::Quote(blocks) | Frame::Item(blocks)) =
+            self.stack.last_mut()
+        {
+            blocks.push(block);
+        }
+    }
+
+    /// Tight list items carry their text without a paragraph around it.
+    fn flush_inlines(&mut self) {
+        if !self.inlines.is_empty() {
+            let inlines = std::mem::take(&mut self.inlines);
+            self.push_block(Block::Paragraph(inlines));
+        }
+    }
+
+    fn push_text(&mut self, value: &str) {
+        if let Some(code) = &mut self.code {
+            code.push_str(value);
+        } else if let Some((_, alt)) = &mut self.image {
+            alt.push_str(value);
+        } else {
+            self.inlines.push(Inline::Text {
+                text: value.to_string(),
+                style: self.style,
+                link: self.link.clone(),
+            });
+        }
+    }
+
+    /// Raw HTML isn't rendered: `<br>` becomes a line break, every other
+    /// tag is dropped and the text around it kept.
+    fn push_html(&mut self, value: &str) {
+        let mut rest = value;
+
+        while let Some(start) = rest.find('<') {
+            let Some(end) = rest[start..].find('>') else {
+                break;
+            };
+
+            self.push_text(&rest[..start]);
+
+            let tag = rest[start + 1..start + end].trim_end_matches('/').trim();
+
+            if tag.eq_ignore_ascii_case("br") {
+                self.inlines.push(Inline::Break);
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+
+        self.push_text(rest);
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph | Tag::Heading { .. } | Tag::HtmlBlock => self.flush_inlines(),
+                Tag::BlockQuote(_) => {
+                    self.flush_inlines();
+                    self.stack.push(Frame::Quote(Vec::new()));
+                }
+                Tag::CodeBlock(_) => {
+                    self.flush_inlines();
+                    self.code = Some(String::new());
+                }
+                Tag::List(start) => {
+                    self.flush_inlines();
+                    self.stack.push(Frame::List(start, Vec::new()));
+                }
+                Tag::Item => self.stack.push(Frame::Item(Vec::new())),
+                Tag::Emphasis => self.style.emphasis = true,
+                Tag::Strong => self.style.strong = true,
+                Tag::Link { dest_url, .. } => self.link = Some(Link::from_href(&dest_url)),
+                Tag::Image { dest_url, .. } => {
+                    self.image = Some((dest_url.to_string(), String::new()));
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Paragraph | TagEnd::HtmlBlock => self.flush_inlines(),
+                TagEnd::Heading(level) => {
+                    let inlines = std::mem::take(&mut self.inlines);
+                    self.push_block(Block::Heading(level, inlines));
+                }
+                TagEnd::BlockQuote(_) => {
+                    self.flush_inlines();
+
+                    if let Some(Frame::Quote(blocks)) = self.stack.pop() {
+                        self.push_block(Block::Quote(blocks));
+                    }
+                }
+                TagEnd::CodeBlock => {
+                    if let Some(code) = self.code.take() {
+                        self.push_block(Block::Code(code.trim_end().to_string()));
+                    }
+                }
+                TagEnd::List(_) => {
+                    if let Some(Frame::List(start, items)) = self.stack.pop() {
+                        self.push_block(Block::List(start, items));
+                    }
+                }
+                TagEnd::Item => {
+                    self.flush_inlines();
+
+                    if let Some(Frame::Item(blocks)) = self.stack.pop() {
+                        if let Some(Frame::List(_, items)) = self.stack.last_mut() {
+                            items.push(blocks);
+                        }
+                    }
+                }
+                TagEnd::Emphasis => self.style.emphasis = false,
+                TagEnd::Strong => self.style.strong = false,
+                TagEnd::Link => self.link = None,
+                TagEnd::Image => {
+                    if let Some((url, alt)) = self.image.take() {
+                        self.inlines.push(Inline::Image { url, alt });
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(value) => self.push_text(&value),
+            Event::Html(value) | Event::InlineHtml(value) => self.push_html(&value),
+            Event::Code(value) => {
+                let style = self.style;
+                self.style.code = true;
+                self.push_text(&value);
+                self.style = style;
+            }
+            Event::SoftBreak => self.push_text(" "),
+            Event::HardBreak => self.inlines.push(Inline::Break),
+            Event::Rule => {
+                self.flush_inlines();
+                self.push_block(Block::Rule);
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Vec<Block> {
+        self.flush_inlines();
+
+        match self.stack.pop() {
+            Some(Frame::Blocks(blocks)) => blocks,
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn parse_commonmark(source: &str) -> Vec<Block> {
+    let mut builder = Builder {
+        stack: vec![Frame::Blocks(Vec::new())],
+        ..Default::default()
+    };
+
+    for event in Parser::new(source) {
+        builder.event(event);
+    }
+
+    builder.finish()
+}
+
+/// Splits out `::: spoiler title` ... `:::` blocks before handing the rest
+/// to the CommonMark parser.
+fn parse(source: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut plain = String::new();
+    let mut spoiler: Option<(String, String)> = None;
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+
+        match &mut spoiler {
+            Some((title, body)) if trimmed == ":::" => {
+                let mut hasher = DefaultHasher::new();
+                (&title, &body).hash(&mut hasher);
+
+                blocks.push(Block::Spoiler {
+                    key: hasher.finish(),
+                    title: std::mem::take(title),
+                    blocks: parse_commonmark(body),
+                });
+                spoiler = None;
+            }
+            Some((_, body)) => {
+                body.push_str(line);
+                body.push('\n');
+            }
+            None => match trimmed.strip_prefix(":::") {
+                Some(rest) if rest.trim_start().starts_with("spoiler") => {
+                    blocks.extend(parse_commonmark(&std::mem::take(&mut plain)));
+
+                    let title = rest.trim_start().trim_start_matches("spoiler").trim();
+                    spoiler = Some((title.to_string(), String::new()));
+                }
+                _ => {
+                    plain.push_str(line);
+                    plain.push('\n');
+                }
+            },
+        }
+    }
+
+    // An unterminated spoiler is shown as regular text.
+    if let Some((title, body)) = spoiler {
+        plain.push_str(&format!("::: spoiler {}\n{}", title, body));
+    }
+
+    blocks.extend(parse_commonmark(&plain));
+    blocks
+}
+
+fn document_key(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn image_urls(blocks: &[Block], urls: &mut Vec<String>) {
+    for block in blocks {
+        match block {
+            Block::Heading(_, inlines) | Block::Paragraph(inlines) => {
+                urls.extend(inlines.iter().filter_map(|inline| match inline {
+                    Inline::Image { url, .. } => Some(url.clone()),
+                    _ => None,
+                }))
+            }
+            Block::List(_, items) => items.iter().for_each(|item| image_urls(item, urls)),
+            Block::Quote(blocks) | Block::Spoiler { blocks, .. } => image_urls(blocks, urls),
+            Block::Code(_) | Block::Rule => {}
+        }
+    }
+}
+
+impl Markdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses `source` once for [`Markdown::view`] and lists its inline
+    /// images.
+    pub fn image_urls(&mut self, source: &str) -> Vec<String> {
+        let blocks = self
+            .documents
+            .entry(document_key(source))
+            .or_insert_with(|| parse(source));
+
+        let mut urls = Vec::new();
+        image_urls(blocks, &mut urls);
+
+        urls
+    }
+
+    /// Parses `source` once for [`Markdown::view`] and starts fetching its
+    /// inline images that aren't loaded yet. Anyone can write markdown, so
+    /// images on other hosts are fetched without the account's token.
+    pub fn load_images(&mut self, api: Arc<API>, source: &str) -> Command<Message> {
+        let mut cmds = Vec::new();
+
+        for url in self.image_urls(source) {
+            // `None` marks an image as requested so it isn't fetched twice.
+            if self.images.contains_key(&url) {
+                continue;
+            }
+
+            self.images.insert(url.clone(), None);
+
+            cmds.push(Command::perform(
+                load_image(api.clone(), url.clone()),
+                move |ret| Message::ImageLoaded(url.clone(), ret),
+            ));
+        }
+
+        Command::batch(cmds)
+    }
+
+    /// Handles spoiler toggles and loaded images; links are left to the
+    /// caller, which knows how to navigate.
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::Link(_) => {}
+            Message::ToggleSpoiler(key) => {
+                if !self.revealed.remove(&key) {
+                    self.revealed.insert(key);
+                }
+            }
+            Message::ImageLoaded(url, handle) => {
+                self.images.insert(url, handle.ok());
+            }
+        }
+    }
+
+    /// Drops the parsed copy of `source` once it is no longer shown.
+    pub fn forget(&mut self, source: &str) {
+        self.documents.remove(&document_key(source));
+    }
+
+    /// Sources that never went through [`Markdown::load_images`] are parsed
+    /// on every call.
+    pub fn view<'a>(&'a self, source: &str) -> Element<'a, Message> {
+        match self.documents.get(&document_key(source)) {
+            Some(blocks) => self.view_blocks(blocks),
+            None => self.view_blocks(&parse(source)),
+        }
+    }
+
+    fn view_blocks<'a>(&'a self, blocks: &[Block]) -> Element<'a, Message> {
+        Column::with_children(blocks.iter().map(|block| self.view_block(block)))
+            .spacing(10)
+            .into()
+    }
+
+    fn view_block<'a>(&'a self, block: &Block) -> Element<'a, Message> {
+        match block {
+            Block::Heading(level, inlines) => {
+                let size = match level {
+                    HeadingLevel::H1 => 28,
+                    HeadingLevel::H2 => 24,
+                    HeadingLevel::H3 => 20,
+                    _ => 18,
+                };
+
+                self.view_inlines(inlines, Some(size))
+            }
+            Block::Paragraph(inlines) => self.view_inlines(inlines, None),
+            Block::List(start, items) => {
+                Column::with_children(items.iter().enumerate().map(|(index, item)| {
+                    let marker = match start {
+                        Some(start) => format!("{}.", *start + index as u64),
+                        None => String::from("•"),
+                    };
+
+                    row!(text(marker), self.view_blocks(item)).spacing(8).into()
+                }))
+                .spacing(5)
+                .into()
+            }
+            Block::Code(code) => Container::new(text(code.clone()).font(Font::MONOSPACE))
+                .style(theme::Container::Box)
+                .width(Length::Fill)
+                .padding(10)
+                .into(),
+            Block::Quote(blocks) => Container::new(self.view_blocks(blocks))
+                .style(theme::Container::Box)
+                .width(Length::Fill)
+                .padding([5, 5, 5, 15])
+                .into(),
+            Block::Rule => horizontal_rule(1).into(),
+            Block::Spoiler { key, title, blocks } => {
+                let revealed = self.revealed.contains(key);
+                let label = if revealed {
+                    format!("Hide spoiler: {}", title)
+                } else {
+                    format!("Show spoiler: {}", title)
+                };
+
+                let mut col = column!(button(text(label))
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::ToggleSpoiler(*key)))
+                .spacing(10);
+
+                if revealed {
+                    col = col.push(Container::new(self.view_blocks(blocks)).padding([0, 0, 0, 15]));
+                }
+
+                col.into()
+            }
+        }
+    }
+
+    /// Lays out a paragraph word by word so emphasis, code and links can sit
+    /// next to plain text and still wrap. Hard breaks start a new line.
+    fn view_inlines<'a>(&'a self, inlines: &[Inline], size: Option<u16>) -> Element<'a, Message> {
+        let mut lines = vec![Wrap::new().spacing(4.).line_spacing(4.)];
+
+        for inline in inlines {
+            match inline {
+                Inline::Break => lines.push(Wrap::new().spacing(4.).line_spacing(4.)),
+                Inline::Image { url, alt } => {
+                    let element: Element<'a, Message> = match self.images.get(url) {
+                        Some(Some(handle)) => Container::new(Image::new(handle.clone()))
+                            .max_width(600)
+                            .into(),
+                        _ => button(text(format!("[image: {}]", alt)))
+                            .style(theme::Button::Text)
+                            .padding(0)
+                            .on_press(Message::Link(Link::Url(url.clone())))
+                            .into(),
+                    };
+
+                    let line = lines.pop().unwrap_or_default();
+                    lines.push(line.push(element));
+                }
+                Inline::Text {
+                    text: value,
+                    style,
+                    link,
+                } => {
+                    let font = Font {
+                        weight: if style.strong || size.is_some() {
+                            font::Weight::Bold
+                        } else {
+                            font::Weight::Normal
+                        },
+                        style: if style.emphasis {
+                            font::Style::Italic
+                        } else {
+                            font::Style::Normal
+                        },
+                        ..if style.code {
+                            Font::MONOSPACE
+                        } else {
+                            Font::DEFAULT
+                        }
+                    };
+
+                    for word in value.split_whitespace() {
+                        let word_link = link.clone().or_else(|| {
+                            Link::from_word(
+                                word.trim_end_matches(['.', ',', ':', ';', ')', '!', '?']),
+                            )
+                        });
+                        let mut label = text(word).font(font);
+
+                        if let Some(size) = size {
+                            label = label.size(size);
+                        }
+
+                        let element: Element<'a, Message> = match word_link {
+                            Some(link) => button(label.style(theme::Text::Color(
+                                iced::Color::from_rgb8(0x4a, 0x9e, 0xe8),
+                            )))
+                            .style(theme::Button::Text)
+                            .padding(0)
+                            .on_press(Message::Link(link))
+                            .into(),
+                            None => label.into(),
+                        };
+
+                        let line = lines.pop().unwrap_or_default();
+                        lines.push(line.push(element));
+                    }
+                }
+            }
+        }
+
+        Column::with_children(lines.into_iter().map(Element::from)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(inlines: &[Inline]) -> Vec<Link> {
+        inlines
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Text { link, .. } => link.clone(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spoiler_blocks_are_split_out() {
+        let blocks = parse("before\n\n::: spoiler Ending\nhidden *text*\n:::\n\nafter");
+
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(&blocks[0], Block::Paragraph(_)));
+        assert!(matches!(
+            &blocks[1],
+            Block::Spoiler { title, blocks, .. }
+                if title == "Ending" && matches!(blocks.as_slice(), [Block::Paragraph(_)])
+        ));
+        assert!(matches!(&blocks[2], Block::Paragraph(_)));
+    }
+
+    #[test]
+    fn unterminated_spoiler_stays_text() {
+        let blocks = parse("::: spoiler Ending\nhidden");
+
+        assert!(matches!(blocks.as_slice(), [Block::Paragraph(_)]));
+    }
+
+    #[test]
+    fn mentions_become_links() {
+        assert_eq!(
+            Link::from_word("!rust@programming.dev"),
+            Some(Link::Community(String::from("rust@programming.dev")))
+        );
+        assert_eq!(
+            Link::from_word("@alice@lemmy.ml"),
+            Some(Link::Person(String::from("alice@lemmy.ml")))
+        );
+        assert_eq!(Link::from_word("@alice"), None);
+        assert_eq!(Link::from_word("!not a@mention"), None);
+    }
+
+    #[test]
+    fn relative_hrefs_name_instance_pages() {
+        let blocks = parse("[a](/c/rust) [b](/u/alice) [c](/post/42)");
+        let [Block::Paragraph(inlines)] = blocks.as_slice() else {
+            panic!("expected one paragraph");
+        };
+
+        assert_eq!(
+            links(inlines),
+            vec![
+                Link::Community(String::from("rust")),
+                Link::Person(String::from("alice")),
+                Link::Post(PostId(42)),
+            ]
+        );
+    }
+
+    #[test]
+    fn html_tags_are_dropped() {
+        let blocks = parse("one<br>two <span>three</span>");
+        let [Block::Paragraph(inlines)] = blocks.as_slice() else {
+            panic!("expected one paragraph");
+        };
+        let words: Vec<_> = inlines
+            .iter()
+            .map(|inline| match inline {
+                Inline::Text { text, .. } => text.trim(),
+                Inline::Break => "\n",
+                Inline::Image { .. } => "image",
+            })
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        assert_eq!(words, vec!["one", "\n", "two", "three"]);
+    }
+
+    #[test]
+    fn resolve_keeps_only_web_links() {
+        let url = |href: &str| Link::Url(String::from(href)).resolve("lemmy.ml");
+
+        assert_eq!(
+            url("/pictrs/image/a.png"),
+            Some(Link::Url(String::from(
+                "https://lemmy.ml/pictrs/image/a.png"
+            )))
+        );
+        assert_eq!(
+            url("http://example.com/"),
+            Some(Link::Url(String::from("http://example.com/")))
+        );
+        assert_eq!(url("javascript:alert(1)"), None);
+        assert_eq!(url("file:///etc/passwd"), None);
+    }
+
+    #[test]
+    fn resolve_routes_instance_pages() {
+        let url = |href: &str| Link::Url(String::from(href)).resolve("lemmy.ml");
+
+        assert_eq!(url("https://lemmy.ml/post/7"), Some(Link::Post(PostId(7))));
+        assert_eq!(
+            url("https://lemmy.ml/c/rust/"),
+            Some(Link::Community(String::from("rust")))
+        );
+        assert_eq!(
+            url("https://other.example/post/7"),
+            Some(Link::Url(String::from("https://other.example/post/7")))
+        );
+    }
+}
@@ -1,27 +1,84 @@
 #![allow(clippy::large_enum_variant)]
 
+pub mod community;
+pub mod conversation;
+pub mod inbox;
+pub mod markdown;
+pub mod post_composer;
+pub mod post_detail;
 pub mod posts;
+pub mod profile;
+pub mod register;
+pub mod saved;
+pub mod search;
 pub mod settings;
 
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
 use iced::{
-    alignment, executor,
-    widget::{column, text, Container},
-    Application, Command, Element, Length, Theme,
+    alignment, event, executor, theme, time,
+    widget::{button, column, pick_list, row, text, Container},
+    window, Alignment, Application, Command, Element, Event, Length, Subscription, Theme,
+};
+use iced_aw::{
+    badge,
+    native::{TabBar, TabLabel},
+    BadgeStyles,
 };
-use iced_aw::native::{TabBar, TabLabel};
 use lemmy_api_common::{
-    lemmy_db_schema::ListingType, lemmy_db_views::structs::PaginationCursor, post::GetPostsResponse,
+    community::GetCommunity,
+    lemmy_db_schema::{
+        newtypes::{CommunityId, PersonId, PostId},
+        source::person::Person,
+        ListingType,
+    },
+    person::{GetPersonDetails, GetUnreadCountResponse},
 };
 
-use self::{
-    posts::{convert_postsview_to_card, PostCard},
-    settings::Settings,
+use self::{markdown::Link, settings::Settings};
+use crate::{
+    api::{
+        connect, get_unread_count, image_cache, replay_pending, Instance, Instances, LemnuxError,
+        PostsList, Replay, API,
+    },
+    settings::{Accounts, Drafts, Preferences},
 };
-use crate::api::{get_posts, Instance, Instances};
+
+/// Tells apart pages of a kind that can be opened from one another, so a
+/// request finishing after the user moved on reaches the page that sent it
+/// and no other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageId(u64);
+
+impl PageId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
 
 #[derive(Debug)]
 pub enum Pages {
     Posts(posts::Posts),
+    PostDetail(PageId, post_detail::PostDetail),
+    PostComposer(post_composer::PostComposer),
+    Community(PageId, community::Community),
+    Search(search::Search),
+    Inbox(inbox::Inbox),
+    Conversation(PageId, conversation::Conversation),
+    Profile(PageId, profile::Profile),
+    Saved(saved::Saved),
+    Register(register::Register),
     Settings(settings::Settings),
 }
 
@@ -30,18 +87,49 @@ pub enum TabId {
     All,
     Local,
     Subscribed,
+    Search,
+    Inbox,
+    Saved,
     Settings,
 }
 
+/// How often the unread counter is refreshed while logged in.
+const UNREAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long changed drafts wait before they are written out.
+const DRAFTS_INTERVAL: Duration = Duration::from_secs(3);
+
 #[derive(Debug)]
 pub struct Lemnux {
+    api: Arc<API>,
+    api_error: Option<LemnuxError>,
+    /// The preferences file couldn't be read; defaults are used until it
+    /// is fixed, and nothing is saved over it.
+    preferences_error: Option<LemnuxError>,
     page: Pages,
+    /// Pages left behind by navigating into a detail page, most recent last.
+    /// Cleared whenever a tab is selected.
+    history: Vec<Pages>,
     active_tab: TabId,
     theme: Theme,
-    posts_type: Option<ListingType>,
+    posts_query: PostsList,
     instances: Vec<Instance>,
-    post_cards: Vec<PostCard>,
-    next_page: Option<PaginationCursor>,
+    accounts: Accounts,
+    /// Unread replies, mentions and messages of the logged in account.
+    unread: Option<GetUnreadCountResponse>,
+}
+
+/// Entry of the account switcher next to the tabs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountChoice {
+    index: Option<usize>,
+    label: String,
+}
+
+impl Display for AccountChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)
+    }
 }
 
 pub enum App {
@@ -53,32 +141,319 @@ pub enum App {
 pub enum Message {
     Loaded(Lemnux),
     TabSelected(TabId),
-    PostFetched(GetPostsResponse),
-    PostRendered(PostCard),
-    RenderPosts,
+    SwitchAccount(AccountChoice),
+    /// The accounts were read again and the client rebuilt, after which the
+    /// tab, if any, is shown again.
+    Reconnected(Option<TabId>, Accounts, Result<API, LemnuxError>),
+    RefreshUnread,
+    UnreadCount(Result<GetUnreadCountResponse, LemnuxError>),
+    InstancesLoaded(Result<Instances, LemnuxError>),
+    StoreDrafts,
+    DraftsStored(Result<(), LemnuxError>),
+    CloseRequested(window::Id),
+    Close(window::Id),
+    /// Votes and saves queued offline were sent, or the instance is still
+    /// unreachable.
+    Replayed(Result<Replay, LemnuxError>),
     Posts(posts::Message),
+    PostDetail(PageId, post_detail::Message),
+    PostComposer(post_composer::Message),
+    Community(PageId, community::Message),
+    Search(search::Message),
+    Inbox(inbox::Message),
+    Conversation(PageId, conversation::Message),
+    Profile(PageId, profile::Message),
+    Saved(saved::Message),
+    Register(register::Message),
     Settings(settings::Message),
 }
 
+/// Inline banner shown by pages when a request failed, with an optional
+/// retry action.
+pub fn error_banner<'a, M: Clone + 'a>(error: &LemnuxError, retry: Option<M>) -> Element<'a, M> {
+    message_banner(error.to_string(), retry)
+}
+
+/// [`error_banner`] for pages that word the error themselves.
+pub fn message_banner<'a, M: Clone + 'a>(message: String, retry: Option<M>) -> Element<'a, M> {
+    let mut banner = row!(badge(text(message)).style(BadgeStyles::Danger))
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+    if let Some(message) = retry {
+        banner = banner.push(button("Retry").on_press(message));
+    }
+
+    banner.into()
+}
+
+/// Says content comes from the offline store, and since when.
+pub fn stale_banner<'a, M: Clone + 'a>(
+    fetched: DateTime<Utc>,
+    updating: bool,
+    retry: Option<M>,
+) -> Element<'a, M> {
+    let message = if updating {
+        format!(
+            "Showing content saved {}, updating...",
+            fetched.to_rfc2822()
+        )
+    } else {
+        format!("Offline: showing content saved {}", fetched.to_rfc2822())
+    };
+    let mut banner = row!(badge(text(message)).style(BadgeStyles::Warning))
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+    if let (Some(message), false) = (retry, updating) {
+        banner = banner.push(button("Retry").on_press(message));
+    }
+
+    banner.into()
+}
+
+/// First page of a listing, sorted the way the user left that tab.
+fn listing_query(type_: ListingType) -> PostsList {
+    let preferences = Preferences::load().unwrap_or_default();
+
+    PostsList::new(Some(type_), preferences.listing(Some(type_)))
+}
+
 async fn load() -> Lemnux {
-    let theme = crate::settings::Settings::load_theme();
-    let posts_type = Some(ListingType::All);
-    let posts = get_posts(posts_type, None).await;
-    let instances = Instances::new().await.federated_instances.linked;
-    let post_cards = Vec::new();
+    let (preferences, preferences_error) = match Preferences::load() {
+        Ok(preferences) => (preferences, None),
+        Err(error) => (Preferences::default(), Some(error)),
+    };
+    let theme = crate::settings::Settings::translate_app_theme(preferences.theme.clone());
+    image_cache::set_limits(&preferences.cache);
+    let posts_query = listing_query(ListingType::All);
+    let (accounts, api) = connect().await;
+    let (api, api_error) = match api {
+        Ok(api) => (Arc::new(api), None),
+        Err(error) => (Arc::new(API::anonymous(true)), Some(error)),
+    };
 
     Lemnux {
-        page: Pages::Posts(posts::Posts::new(
-            posts_type,
-            post_cards.clone(),
-            posts.next_page.clone(),
-        )),
+        page: Pages::Posts(posts::Posts::new(api.clone(), posts_query.clone())),
+        history: Vec::new(),
+        api,
+        api_error,
+        preferences_error,
         active_tab: TabId::All,
         theme,
-        posts_type,
-        instances,
-        post_cards,
-        next_page: posts.next_page,
+        posts_query,
+        // The instance list only feeds the settings picker, so it is
+        // fetched once the app is up.
+        instances: Vec::new(),
+        accounts,
+        unread: None,
+    }
+}
+
+impl Lemnux {
+    fn navigate(&mut self, page: Pages) {
+        let previous = std::mem::replace(&mut self.page, page);
+        self.history.push(previous);
+    }
+
+    fn open_post(&mut self, post_id: PostId) -> Command<Message> {
+        let (page, cmd) = post_detail::PostDetail::new(self.api.clone(), post_id);
+        let id = PageId::next();
+        self.navigate(Pages::PostDetail(id, page));
+
+        cmd.map(move |message| Message::PostDetail(id, message))
+    }
+
+    fn open_composer(
+        &mut self,
+        (page, cmd): (post_composer::PostComposer, Command<post_composer::Message>),
+    ) -> Command<Message> {
+        self.navigate(Pages::PostComposer(page));
+
+        cmd.map(Message::PostComposer)
+    }
+
+    fn open_profile(&mut self, person: GetPersonDetails) -> Command<Message> {
+        let (page, cmd) = profile::Profile::new(self.api.clone(), person);
+        let id = PageId::next();
+        self.navigate(Pages::Profile(id, page));
+
+        cmd.map(move |message| Message::Profile(id, message))
+    }
+
+    fn open_person_id(&mut self, person_id: PersonId) -> Command<Message> {
+        self.open_profile(GetPersonDetails {
+            person_id: Some(person_id),
+            ..Default::default()
+        })
+    }
+
+    fn open_conversation(&mut self, person: Person) -> Command<Message> {
+        let (page, cmd) = conversation::Conversation::new(self.api.clone(), person);
+        let id = PageId::next();
+        self.navigate(Pages::Conversation(id, page));
+
+        cmd.map(move |message| Message::Conversation(id, message))
+    }
+
+    fn open_community(&mut self, community: GetCommunity) -> Command<Message> {
+        let (page, cmd) = community::Community::new(self.api.clone(), community);
+        let id = PageId::next();
+        self.navigate(Pages::Community(id, page));
+
+        cmd.map(move |message| Message::Community(id, message))
+    }
+
+    fn open_community_id(&mut self, community_id: CommunityId) -> Command<Message> {
+        self.open_community(GetCommunity {
+            id: Some(community_id),
+            name: None,
+        })
+    }
+
+    /// Follows a link clicked in rendered markdown. Mentions and pages of
+    /// the current instance stay in the app; other web links go to the
+    /// desktop's browser and anything else is ignored.
+    fn open_link(&mut self, link: Link) -> Command<Message> {
+        let Some(link) = link.resolve(&self.api.domain) else {
+            return Command::none();
+        };
+
+        match link {
+            Link::Url(url) => {
+                // Waited on so the handler doesn't linger as a zombie. Nothing
+                // useful to show if no handler is installed.
+                std::thread::spawn(move || {
+                    let _ = std::process::Command::new("xdg-open").arg(url).status();
+                });
+
+                Command::none()
+            }
+            Link::Post(post_id) => self.open_post(post_id),
+            Link::Community(name) => self.open_community(GetCommunity {
+                id: None,
+                name: Some(name),
+            }),
+            Link::Person(name) => self.open_profile(GetPersonDetails {
+                username: Some(name),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Handles what a post listing asks of the app rather than of itself,
+    /// wherever the listing is embedded. `None` leaves it to the listing.
+    fn posts_navigation(&mut self, message: &posts::Message) -> Option<Command<Message>> {
+        let cmd = match message {
+            posts::Message::OpenPost(post_id) => self.open_post(*post_id),
+            posts::Message::OpenCommunity(community_id) => self.open_community_id(*community_id),
+            posts::Message::OpenPerson(person_id) => self.open_person_id(*person_id),
+            posts::Message::Markdown(markdown::Message::Link(link)) => self.open_link(link.clone()),
+            posts::Message::NewPost(community) => {
+                let composer = post_composer::PostComposer::new(self.api.clone(), *community);
+                self.open_composer(composer)
+            }
+            posts::Message::EditPost(post_id) => {
+                let composer = post_composer::PostComposer::edit(self.api.clone(), *post_id);
+                self.open_composer(composer)
+            }
+            _ => return None,
+        };
+
+        Some(cmd)
+    }
+
+    /// The listing page, whether it is shown or sits under a detail page.
+    fn posts_page(&mut self) -> Option<&mut posts::Posts> {
+        let page = match self.history.first_mut() {
+            Some(page) => page,
+            None => &mut self.page,
+        };
+
+        match page {
+            Pages::Posts(posts) => Some(posts),
+            _ => None,
+        }
+    }
+
+    /// Every post listing, whether it is shown or sits in the history.
+    fn listings(&mut self) -> impl Iterator<Item = &mut posts::Posts> {
+        std::iter::once(&mut self.page)
+            .chain(self.history.iter_mut())
+            .filter_map(|page| match page {
+                Pages::Posts(posts) => Some(posts),
+                Pages::Community(_, community) => Some(community.posts_mut()),
+                Pages::Saved(saved) => saved.voted_mut(),
+                _ => None,
+            })
+    }
+
+    /// The shown page or, failing that, the closest one in the history that
+    /// `pick` accepts. Requests finish after the user navigated away, so
+    /// pages that can be stacked are picked by their [`PageId`].
+    fn find_page<T>(&mut self, pick: impl Fn(&mut Pages) -> Option<&mut T>) -> Option<&mut T> {
+        std::iter::once(&mut self.page)
+            .chain(self.history.iter_mut().rev())
+            .find_map(pick)
+    }
+
+    /// Rebuilds the client after the account list changed on disk.
+    fn reconnect(tab: Option<TabId>) -> Command<Message> {
+        Command::perform(connect(), move |(accounts, api)| {
+            Message::Reconnected(tab, accounts, api)
+        })
+    }
+
+    /// Replaces the shown page with the listing of `posts_query`, starting
+    /// from its stored copy.
+    fn show_listing(&mut self) -> Command<Message> {
+        let mut page = posts::Posts::new(self.api.clone(), self.posts_query.clone());
+        let cmd = page.refresh().map(Message::Posts);
+
+        self.page = Pages::Posts(page);
+
+        cmd
+    }
+
+    fn replay_pending(&self) -> Command<Message> {
+        if self.api.username.is_none() {
+            return Command::none();
+        }
+
+        Command::perform(replay_pending(self.api.clone()), Message::Replayed)
+    }
+
+    fn refresh_unread(&mut self) -> Command<Message> {
+        if self.api.username.is_none() {
+            self.unread = None;
+            return Command::none();
+        }
+
+        Command::perform(get_unread_count(self.api.clone()), Message::UnreadCount)
+    }
+
+    fn account_choices(&self) -> Vec<AccountChoice> {
+        let anonymous = AccountChoice {
+            index: None,
+            label: format!("Anonymous on {}", self.accounts.domain()),
+        };
+        let accounts = self
+            .accounts
+            .accounts
+            .iter()
+            .enumerate()
+            .map(|(index, account)| AccountChoice {
+                index: Some(index),
+                label: account.label(),
+            });
+
+        std::iter::once(anonymous).chain(accounts).collect()
+    }
+
+    fn go_back(&mut self) {
+        if let Some(page) = self.history.pop() {
+            self.page = page;
+        }
     }
 }
 
@@ -105,85 +480,421 @@ impl Application for App {
     }
 
     fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::StoreDrafts => {
+                return Command::perform(Drafts::flush(), Message::DraftsStored);
+            }
+            // The drafts stay unsaved and are tried again on the next tick.
+            Message::DraftsStored(_) => return Command::none(),
+            // Whatever happened to the drafts, the window closes.
+            Message::CloseRequested(id) => {
+                return Command::perform(Drafts::flush(), move |_| Message::Close(id));
+            }
+            Message::Close(id) => return window::close(id),
+            _ => {}
+        }
+
         match self {
             App::Loading => {
-                if let Message::Loaded(init) = message {
-                    *self = App::Loaded(init)
-                }
-                Command::perform(
-                    get_posts(Some(ListingType::All), None),
-                    Message::PostFetched,
-                )
+                let Message::Loaded(init) = message else {
+                    return Command::none();
+                };
+                *self = App::Loaded(init);
+
+                let App::Loaded(config) = self else {
+                    return Command::none();
+                };
+
+                Command::batch(vec![
+                    config.show_listing(),
+                    Command::perform(Instances::new(), Message::InstancesLoaded),
+                    config.refresh_unread(),
+                    config.replay_pending(),
+                ])
             }
             App::Loaded(config) => match message {
                 Message::TabSelected(tab) => {
                     config.active_tab = tab.clone();
+                    config.history.clear();
 
                     match tab {
                         TabId::All => {
-                            config.posts_type = Some(ListingType::All);
+                            config.posts_query = listing_query(ListingType::All);
 
-                            Command::perform(
-                                get_posts(config.posts_type, None),
-                                Message::PostFetched,
-                            )
+                            config.show_listing()
                         }
                         TabId::Local => {
-                            config.posts_type = Some(ListingType::Local);
+                            config.posts_query = listing_query(ListingType::Local);
 
-                            Command::perform(
-                                get_posts(config.posts_type, None),
-                                Message::PostFetched,
-                            )
+                            config.show_listing()
                         }
                         TabId::Subscribed => {
-                            config.posts_type = Some(ListingType::Subscribed);
+                            config.posts_query = listing_query(ListingType::Subscribed);
 
-                            Command::perform(
-                                get_posts(config.posts_type, None),
-                                Message::PostFetched,
-                            )
+                            config.show_listing()
                         }
-                        TabId::Settings => {
-                            config.page =
-                                Pages::Settings(Settings::new(config.instances.to_owned()));
+                        TabId::Search => {
+                            config.page = Pages::Search(search::Search::new(config.api.clone()));
 
                             Command::none()
                         }
+                        TabId::Inbox => {
+                            let (page, cmd) = inbox::Inbox::new(config.api.clone());
+                            config.page = Pages::Inbox(page);
+
+                            Command::batch(vec![cmd.map(Message::Inbox), config.refresh_unread()])
+                        }
+                        TabId::Saved => {
+                            let (page, cmd) = saved::Saved::new(config.api.clone());
+                            config.page = Pages::Saved(page);
+
+                            cmd.map(Message::Saved)
+                        }
+                        TabId::Settings => {
+                            let mut settings_page = Settings::new(
+                                config.api.clone(),
+                                config.instances.to_owned(),
+                                config.accounts.clone(),
+                            );
+
+                            if let Some(error) = &config.api_error {
+                                settings_page = settings_page.with_error(error.clone());
+                            }
+
+                            config.page = Pages::Settings(settings_page);
+
+                            Settings::measure_cache().map(Message::Settings)
+                        }
                     }
                 }
-                Message::PostFetched(posts) => {
-                    config.next_page = posts.next_page;
+                Message::SwitchAccount(choice) => {
+                    config.accounts.switch(choice.index);
 
-                    let cmds = posts.posts.into_iter().map(|item| {
-                        Command::perform(convert_postsview_to_card(item), Message::PostRendered)
-                    });
+                    if let Err(error) = config.accounts.store() {
+                        config.api_error = Some(error);
+
+                        return Command::none();
+                    }
 
-                    config.post_cards.clear();
+                    // Everything shown belongs to the previous account.
+                    let tab = match config.active_tab {
+                        TabId::Search | TabId::Inbox | TabId::Saved | TabId::Settings => {
+                            config.active_tab.clone()
+                        }
+                        _ => TabId::All,
+                    };
 
-                    Command::batch(cmds)
+                    Lemnux::reconnect(Some(tab))
                 }
-                Message::PostRendered(card) => {
-                    config.post_cards.push(card);
+                Message::Reconnected(tab, accounts, api) => {
+                    config.accounts = accounts;
 
-                    let object = posts::Posts::new(
-                        config.posts_type,
-                        config.post_cards.to_owned(),
-                        config.next_page.to_owned(),
-                    );
+                    match api {
+                        Ok(api) => {
+                            config.api = Arc::new(api);
+                            config.api_error = None;
 
-                    config.page = Pages::Posts(object);
+                            if let Pages::Settings(settings_page) = &mut config.page {
+                                settings_page.set_api(config.api.clone());
+                            }
+                        }
+                        Err(error) => config.api_error = Some(error),
+                    }
+
+                    let mut cmds = vec![config.refresh_unread()];
+
+                    if let Some(tab) = tab {
+                        cmds.push(Command::perform(async { tab }, Message::TabSelected));
+                    }
+
+                    Command::batch(cmds)
+                }
+                Message::RefreshUnread => {
+                    Command::batch(vec![config.refresh_unread(), config.replay_pending()])
+                }
+                Message::UnreadCount(Ok(unread)) => {
+                    config.unread = Some(unread);
+                    Command::none()
+                }
+                // A stale counter is better than a banner every minute.
+                Message::UnreadCount(Err(_)) => Command::none(),
+                Message::InstancesLoaded(Ok(instances)) => {
+                    config.instances = instances.federated_instances.linked;
+                    Command::none()
+                }
+                // The settings picker just stays empty.
+                Message::InstancesLoaded(Err(_)) => Command::none(),
+                Message::Replayed(Ok(replay)) => {
+                    for page in config.listings() {
+                        page.replayed(&replay);
+                    }
 
                     Command::none()
                 }
+                // Still offline; the queue is tried again on the next tick.
+                Message::Replayed(Err(_)) => Command::none(),
                 Message::Posts(post_mess) => {
-                    let Pages::Posts(home_page) = &mut config.page else {
+                    if let Some(cmd) = config.posts_navigation(&post_mess) {
+                        return cmd;
+                    }
+
+                    // Back online: send what was queued meanwhile.
+                    let replay = match &post_mess {
+                        posts::Message::PostStatus(posts::PostFetching::LoadedResponse(
+                            _,
+                            Ok(fetched),
+                        )) if fetched.stale.is_none() => config.replay_pending(),
+                        _ => Command::none(),
+                    };
+
+                    let Some(home_page) = config.posts_page() else {
+                        return replay;
+                    };
+
+                    Command::batch(vec![
+                        home_page.update(post_mess).map(Message::Posts),
+                        replay,
+                    ])
+                }
+                Message::PostDetail(id, detail_mess) => {
+                    match detail_mess {
+                        post_detail::Message::Back => {
+                            config.go_back();
+                            return Command::none();
+                        }
+                        post_detail::Message::Markdown(markdown::Message::Link(link)) => {
+                            return config.open_link(link)
+                        }
+                        _ => {}
+                    }
+
+                    let Some(detail_page) = config.find_page(|page| match page {
+                        Pages::PostDetail(page_id, detail) if *page_id == id => Some(detail),
+                        _ => None,
+                    }) else {
+                        return Command::none();
+                    };
+
+                    detail_page
+                        .update(detail_mess)
+                        .map(move |message| Message::PostDetail(id, message))
+                }
+                Message::PostComposer(composer_mess) => {
+                    match composer_mess {
+                        post_composer::Message::Back => {
+                            config.go_back();
+                            return Command::none();
+                        }
+                        // The composer is done; show the post in its place.
+                        post_composer::Message::Submitted(Ok(response)) => {
+                            config.go_back();
+                            return config.open_post(response.post_view.post.id);
+                        }
+                        post_composer::Message::Markdown(markdown::Message::Link(link)) => {
+                            return config.open_link(link)
+                        }
+                        _ => {}
+                    }
+
+                    let Pages::PostComposer(composer) = &mut config.page else {
+                        return Command::none();
+                    };
+
+                    composer.update(composer_mess).map(Message::PostComposer)
+                }
+                Message::Community(id, community_mess) => {
+                    match &community_mess {
+                        community::Message::Back => {
+                            config.go_back();
+                            return Command::none();
+                        }
+                        community::Message::Markdown(markdown::Message::Link(link)) => {
+                            return config.open_link(link.clone())
+                        }
+                        community::Message::Posts(post_mess) => {
+                            if let Some(cmd) = config.posts_navigation(post_mess) {
+                                return cmd;
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    let Some(community_page) = config.find_page(|page| match page {
+                        Pages::Community(page_id, community) if *page_id == id => Some(community),
+                        _ => None,
+                    }) else {
+                        return Command::none();
+                    };
+
+                    community_page
+                        .update(community_mess)
+                        .map(move |message| Message::Community(id, message))
+                }
+                Message::Search(search_mess) => {
+                    match search_mess {
+                        search::Message::OpenPost(post_id) => return config.open_post(post_id),
+                        search::Message::OpenCommunity(community_id) => {
+                            return config.open_community_id(community_id)
+                        }
+                        search::Message::OpenPerson(person_id) => {
+                            return config.open_person_id(person_id)
+                        }
+                        search::Message::SendMessage(person) => {
+                            return config.open_conversation(person)
+                        }
+                        _ => {}
+                    }
+
+                    let Some(search_page) = config.find_page(|page| match page {
+                        Pages::Search(search) => Some(search),
+                        _ => None,
+                    }) else {
                         return Command::none();
                     };
 
-                    home_page.update(post_mess).map(Message::Posts)
+                    search_page.update(search_mess).map(Message::Search)
+                }
+                Message::Inbox(inbox_mess) => {
+                    if let inbox::Message::OpenPost(post_id) = inbox_mess {
+                        return config.open_post(post_id);
+                    }
+
+                    if let inbox::Message::Markdown(markdown::Message::Link(link)) = inbox_mess {
+                        return config.open_link(link);
+                    }
+
+                    if let inbox::Message::OpenConversation(person) = inbox_mess {
+                        return config.open_conversation(person);
+                    }
+
+                    let refresh = inbox_mess.changes_unread();
+
+                    let Some(inbox_page) = config.find_page(|page| match page {
+                        Pages::Inbox(inbox) => Some(inbox),
+                        _ => None,
+                    }) else {
+                        return Command::none();
+                    };
+
+                    let cmd = inbox_page.update(inbox_mess).map(Message::Inbox);
+
+                    if refresh {
+                        Command::batch(vec![cmd, config.refresh_unread()])
+                    } else {
+                        cmd
+                    }
+                }
+                Message::Conversation(id, conversation_mess) => {
+                    match conversation_mess {
+                        conversation::Message::Back => {
+                            config.go_back();
+                            return Command::none();
+                        }
+                        conversation::Message::Markdown(markdown::Message::Link(link)) => {
+                            return config.open_link(link)
+                        }
+                        _ => {}
+                    }
+
+                    let Some(conversation_page) = config.find_page(|page| match page {
+                        Pages::Conversation(page_id, conversation) if *page_id == id => {
+                            Some(conversation)
+                        }
+                        _ => None,
+                    }) else {
+                        return Command::none();
+                    };
+
+                    conversation_page
+                        .update(conversation_mess)
+                        .map(move |message| Message::Conversation(id, message))
+                }
+                Message::Profile(id, profile_mess) => {
+                    match profile_mess {
+                        profile::Message::Back => {
+                            config.go_back();
+                            return Command::none();
+                        }
+                        profile::Message::OpenPost(post_id) => return config.open_post(post_id),
+                        profile::Message::OpenCommunity(community_id) => {
+                            return config.open_community_id(community_id)
+                        }
+                        profile::Message::SendMessage(person) => {
+                            return config.open_conversation(person)
+                        }
+                        profile::Message::Markdown(markdown::Message::Link(link)) => {
+                            return config.open_link(link)
+                        }
+                        _ => {}
+                    }
+
+                    let Some(profile_page) = config.find_page(|page| match page {
+                        Pages::Profile(page_id, profile) if *page_id == id => Some(profile),
+                        _ => None,
+                    }) else {
+                        return Command::none();
+                    };
+
+                    profile_page
+                        .update(profile_mess)
+                        .map(move |message| Message::Profile(id, message))
+                }
+                Message::Saved(saved_mess) => {
+                    match &saved_mess {
+                        saved::Message::OpenPost(post_id) => return config.open_post(*post_id),
+                        saved::Message::OpenCommunity(community_id) => {
+                            return config.open_community_id(*community_id)
+                        }
+                        saved::Message::Markdown(markdown::Message::Link(link)) => {
+                            return config.open_link(link.clone())
+                        }
+                        saved::Message::Posts(post_mess) => {
+                            if let Some(cmd) = config.posts_navigation(post_mess) {
+                                return cmd;
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    let Some(saved_page) = config.find_page(|page| match page {
+                        Pages::Saved(saved) => Some(saved),
+                        _ => None,
+                    }) else {
+                        return Command::none();
+                    };
+
+                    saved_page.update(saved_mess).map(Message::Saved)
+                }
+                Message::Register(register_mess) => {
+                    if let register::Message::Back = register_mess {
+                        config.go_back();
+                        return Command::none();
+                    }
+
+                    let registered =
+                        matches!(register_mess, register::Message::AccountAdded(Ok(_)));
+
+                    let Pages::Register(register_page) = &mut config.page else {
+                        return Command::none();
+                    };
+
+                    let cmd = register_page.update(register_mess).map(Message::Register);
+
+                    // Signing up added and activated an account.
+                    if registered {
+                        return Command::batch(vec![cmd, Lemnux::reconnect(None)]);
+                    }
+
+                    cmd
                 }
                 Message::Settings(opt) => {
+                    if let settings::Message::OpenRegister = opt {
+                        let (page, cmd) = register::Register::new(config.api.clone());
+                        config.navigate(Pages::Register(page));
+
+                        return cmd.map(Message::Register);
+                    }
+
                     let Pages::Settings(settings_page) = &mut config.page else {
                         return Command::none();
                     };
@@ -193,14 +904,58 @@ impl Application for App {
                             crate::settings::Settings::translate_app_theme(theme.to_owned());
                     };
 
-                    settings_page.update(opt).map(Message::Settings)
+                    // Only a different instance or account needs a new client;
+                    // everything else keeps reusing the pooled connections.
+                    let rebuild_api = matches!(opt, settings::Message::AccountsChanged(Ok(_)));
+
+                    let cmd = settings_page.update(opt).map(Message::Settings);
+
+                    if rebuild_api {
+                        return Command::batch(vec![cmd, Lemnux::reconnect(None)]);
+                    }
+
+                    cmd
                 }
                 _ => Command::none(),
             },
         }
     }
 
-    fn view(&self) -> Element<Self::Message> {
+    fn subscription(&self) -> Subscription<Message> {
+        let close = event::listen_with(|event, _| match event {
+            Event::Window(id, window::Event::CloseRequested) => Some(Message::CloseRequested(id)),
+            _ => None,
+        });
+        let drafts = if Drafts::unsaved() {
+            time::every(DRAFTS_INTERVAL).map(|_| Message::StoreDrafts)
+        } else {
+            Subscription::none()
+        };
+
+        let App::Loaded(config) = self else {
+            return Subscription::batch(vec![close, drafts]);
+        };
+
+        let page = match &config.page {
+            Pages::Posts(posts) => posts.subscription().map(Message::Posts),
+            Pages::Community(id, community) => community
+                .subscription()
+                .with(*id)
+                .map(|(id, message)| Message::Community(id, message)),
+            Pages::Saved(saved) => saved.subscription().map(Message::Saved),
+            _ => Subscription::none(),
+        };
+
+        let unread = if config.api.username.is_some() {
+            time::every(UNREAD_INTERVAL).map(|_| Message::RefreshUnread)
+        } else {
+            Subscription::none()
+        };
+
+        Subscription::batch(vec![close, drafts, unread, page])
+    }
+
+    fn view(&self) -> Element<'_, Self::Message> {
         match self {
             App::Loading => Container::new(
                 text("Loading...")
@@ -220,15 +975,68 @@ impl Application for App {
                         TabId::Subscribed,
                         TabLabel::Text(String::from("Subscribed")),
                     )
+                    .push(TabId::Search, TabLabel::Text(String::from("Search")))
+                    .push(TabId::Inbox, TabLabel::Text(String::from("Inbox")))
+                    .push(TabId::Saved, TabLabel::Text(String::from("Saved")))
                     .push(TabId::Settings, TabLabel::Text(String::from("Settings")))
                     .set_active_tab(&config.active_tab);
 
+                let choices = config.account_choices();
+                let current = choices
+                    .iter()
+                    .find(|choice| choice.index == config.accounts.active)
+                    .cloned();
+                // `Message` can't be cloned, which pick lists need.
+                let switcher = Element::from(pick_list(choices, current, |choice| choice))
+                    .map(Message::SwitchAccount);
+
                 let page = match &config.page {
                     Pages::Posts(posts) => posts.view().map(Message::Posts),
+                    Pages::PostDetail(id, detail) => detail
+                        .view()
+                        .map(|message| Message::PostDetail(*id, message)),
+                    Pages::PostComposer(composer) => composer.view().map(Message::PostComposer),
+                    Pages::Community(id, community) => community
+                        .view()
+                        .map(|message| Message::Community(*id, message)),
+                    Pages::Search(search) => search.view().map(Message::Search),
+                    Pages::Inbox(inbox) => inbox.view().map(Message::Inbox),
+                    Pages::Profile(id, profile) => {
+                        profile.view().map(|message| Message::Profile(*id, message))
+                    }
+                    Pages::Saved(saved) => saved.view().map(Message::Saved),
+                    Pages::Conversation(id, conversation) => conversation
+                        .view()
+                        .map(|message| Message::Conversation(*id, message)),
+                    Pages::Register(register) => register.view().map(Message::Register),
                     Pages::Settings(settings) => settings.view().map(Message::Settings),
                 };
 
-                let content = column!(tab_bar, page);
+                let mut header = row!(tab_bar).spacing(10).align_items(Alignment::Center);
+
+                if let Some(unread) = &config.unread {
+                    let count = unread.replies + unread.mentions + unread.private_messages;
+
+                    if count > 0 {
+                        let unread_badge = button(
+                            badge(text(format!("{count} unread"))).style(BadgeStyles::Warning),
+                        )
+                        .style(theme::Button::Text)
+                        .on_press(TabId::Inbox);
+
+                        header = header.push(Element::from(unread_badge).map(Message::TabSelected));
+                    }
+                }
+
+                let header = header.push(switcher);
+                let mut content = column!(header);
+
+                if let Some(error) = &config.preferences_error {
+                    content =
+                        content.push(error_banner::<TabId>(error, None).map(Message::TabSelected));
+                }
+
+                let content = content.push(page);
 
                 Container::new(content).into()
             }
@@ -0,0 +1,588 @@
+use std::{fmt::Display, sync::Arc};
+
+use iced::{
+    theme,
+    widget::{
+        button, checkbox, column, combo_box, pick_list, row, text, text_editor, text_input,
+        Container, Scrollable,
+    },
+    Alignment, Command, Element, Length,
+};
+use iced_aw::{badge, BadgeStyles, Card};
+use lemmy_api_common::{
+    community::{GetCommunity, GetCommunityResponse, ListCommunities, ListCommunitiesResponse},
+    lemmy_db_schema::{
+        newtypes::{CommunityId, LanguageId, PostId},
+        source::community::Community,
+        ListingType, SortType,
+    },
+    post::{CreatePost, EditPost, GetPostResponse, GetSiteMetadataResponse, PostResponse},
+    site::GetSiteResponse,
+};
+use reqwest::Url;
+
+use super::{
+    markdown::{self, Markdown},
+    message_banner,
+};
+use crate::api::{
+    create_post, edit_post, get_community, get_post, get_site, get_site_metadata, list_communities,
+    LemnuxError, API,
+};
+
+/// How many communities of each listing the selector offers.
+const COMMUNITY_LIMIT: i64 = 50;
+
+/// Entry of the community selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommunityChoice {
+    id: CommunityId,
+    label: String,
+}
+
+impl CommunityChoice {
+    fn from_community(community: &Community) -> Self {
+        let host = community.actor_id.host_str().unwrap_or_default();
+
+        Self {
+            id: community.id,
+            label: format!("{} (!{}@{})", community.title, community.name, host),
+        }
+    }
+}
+
+impl Display for CommunityChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+
+/// Entry of the language picker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageChoice {
+    id: LanguageId,
+    name: String,
+}
+
+impl Display for LanguageChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct PostComposer {
+    api: Arc<API>,
+    /// The post being edited; `None` writes a new one.
+    editing: Option<PostId>,
+    title: String,
+    url: String,
+    link_preview: Option<GetSiteMetadataResponse>,
+    body: text_editor::Content,
+    /// Text of `body` as of the last edit, which the preview renders.
+    body_text: String,
+    /// Inline images of `body_text`, so a keystroke outside them doesn't
+    /// look for images to load.
+    body_images: Vec<String>,
+    preview: Markdown,
+    nsfw: bool,
+    languages: Vec<LanguageChoice>,
+    language: Option<LanguageChoice>,
+    communities: Vec<CommunityChoice>,
+    community_search: combo_box::State<CommunityChoice>,
+    community: Option<CommunityChoice>,
+    submitting: bool,
+    /// Problem with the form itself, found before anything is sent.
+    invalid: Option<&'static str>,
+    error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Back,
+    SiteLoaded(Result<GetSiteResponse, LemnuxError>),
+    CommunitiesLoaded(Result<ListCommunitiesResponse, LemnuxError>),
+    CommunityLoaded(Result<GetCommunityResponse, LemnuxError>),
+    PostLoaded(Result<GetPostResponse, LemnuxError>),
+    Title(String),
+    Url(String),
+    FetchLinkPreview,
+    LinkPreviewLoaded(Result<GetSiteMetadataResponse, LemnuxError>),
+    Body(text_editor::Action),
+    Nsfw(bool),
+    SetLanguage(LanguageChoice),
+    SetCommunity(CommunityChoice),
+    Submit,
+    Submitted(Result<PostResponse, LemnuxError>),
+    Markdown(markdown::Message),
+}
+
+fn post_error_message(error: &LemnuxError) -> String {
+    let message = match error.lemmy_error() {
+        Some("not_logged_in") => "Log in from the settings to post.",
+        Some("banned_from_community") => "You are banned from this community.",
+        Some("only_mods_can_post_in_community") => "Only moderators can post in this community.",
+        Some("invalid_post_title") => "The title is empty, too long or contains line breaks.",
+        Some("invalid_body_field") => "The body is too long.",
+        Some("invalid_url") => "The instance rejected the link.",
+        Some("no_post_edit_allowed") => "You can only edit your own posts.",
+        _ => return error.to_string(),
+    };
+
+    message.to_string()
+}
+
+impl PostComposer {
+    fn empty(api: Arc<API>) -> Self {
+        Self {
+            api,
+            editing: None,
+            title: String::new(),
+            url: String::new(),
+            link_preview: None,
+            body: text_editor::Content::new(),
+            body_text: String::new(),
+            body_images: Vec::new(),
+            preview: Markdown::new(),
+            nsfw: false,
+            languages: Vec::new(),
+            language: None,
+            communities: Vec::new(),
+            community_search: combo_box::State::new(Vec::new()),
+            community: None,
+            submitting: false,
+            invalid: None,
+            error: None,
+        }
+    }
+
+    /// Composer for a new post, with `community` preselected when the user
+    /// came from a community page.
+    pub fn new(api: Arc<API>, community: Option<CommunityId>) -> (Self, Command<Message>) {
+        let page = Self::empty(api.clone());
+        let listing = |type_, sort| {
+            let params = ListCommunities {
+                type_: Some(type_),
+                sort: Some(sort),
+                limit: Some(COMMUNITY_LIMIT),
+                ..Default::default()
+            };
+
+            Command::perform(
+                list_communities(api.clone(), params),
+                Message::CommunitiesLoaded,
+            )
+        };
+
+        let mut cmds = vec![
+            Command::perform(get_site(api.clone()), Message::SiteLoaded),
+            listing(ListingType::Subscribed, SortType::Active),
+            listing(ListingType::All, SortType::TopAll),
+        ];
+
+        if let Some(id) = community {
+            let params = GetCommunity {
+                id: Some(id),
+                name: None,
+            };
+
+            cmds.push(Command::perform(
+                get_community(api.clone(), params),
+                Message::CommunityLoaded,
+            ));
+        }
+
+        (page, Command::batch(cmds))
+    }
+
+    /// Composer filled in with an existing post. Posts can't move between
+    /// communities, so the selector is left out.
+    pub fn edit(api: Arc<API>, post_id: PostId) -> (Self, Command<Message>) {
+        let page = Self {
+            editing: Some(post_id),
+            ..Self::empty(api.clone())
+        };
+        let cmd = Command::batch(vec![
+            Command::perform(get_site(api.clone()), Message::SiteLoaded),
+            Command::perform(get_post(api, post_id), Message::PostLoaded),
+        ]);
+
+        (page, cmd)
+    }
+
+    fn add_communities(&mut self, communities: impl IntoIterator<Item = CommunityChoice>) {
+        for community in communities {
+            if !self
+                .communities
+                .iter()
+                .any(|known| known.id == community.id)
+            {
+                self.communities.push(community);
+            }
+        }
+
+        self.community_search = combo_box::State::new(self.communities.clone());
+    }
+
+    /// The link as it will be sent; `Err` when something was typed that
+    /// isn't a URL.
+    fn link(&self) -> Result<Option<Url>, ()> {
+        let url = self.url.trim();
+
+        if url.is_empty() {
+            return Ok(None);
+        }
+
+        Url::parse(url).map(Some).map_err(|_| ())
+    }
+
+    /// Parses the edited body for the preview, loading its images only
+    /// when they changed.
+    fn body_changed(&mut self) -> Command<Message> {
+        self.preview.forget(&self.body_text);
+        self.body_text = self.body.text();
+
+        let images = self.preview.image_urls(&self.body_text);
+
+        if images == self.body_images {
+            return Command::none();
+        }
+
+        self.body_images = images;
+
+        self.preview
+            .load_images(self.api.clone(), &self.body_text)
+            .map(Message::Markdown)
+    }
+
+    fn submit(&mut self) -> Command<Message> {
+        let Ok(url) = self.link() else {
+            self.invalid = Some("The link isn't a valid URL.");
+            return Command::none();
+        };
+
+        if self.title.trim().is_empty() {
+            self.invalid = Some("A post needs a title.");
+            return Command::none();
+        }
+
+        let body = self.body_text.trim_end().to_string();
+        let language_id = self.language.as_ref().map(|language| language.id);
+
+        let request = match self.editing {
+            Some(post_id) => {
+                let params = EditPost {
+                    post_id,
+                    name: Some(self.title.trim().to_string()),
+                    url,
+                    // An emptied body has to be sent to be cleared; `None`
+                    // leaves the old one.
+                    body: Some(body),
+                    nsfw: Some(self.nsfw),
+                    language_id,
+                };
+
+                Command::perform(edit_post(self.api.clone(), params), Message::Submitted)
+            }
+            None => {
+                let Some(community) = &self.community else {
+                    self.invalid = Some("Pick a community to post in.");
+                    return Command::none();
+                };
+
+                let params = CreatePost {
+                    name: self.title.trim().to_string(),
+                    community_id: community.id,
+                    url,
+                    body: Some(body).filter(|body| !body.is_empty()),
+                    honeypot: None,
+                    nsfw: Some(self.nsfw),
+                    language_id,
+                };
+
+                Command::perform(create_post(self.api.clone(), params), Message::Submitted)
+            }
+        };
+
+        self.invalid = None;
+        self.error = None;
+        self.submitting = true;
+
+        request
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Back => Command::none(),
+            Message::SiteLoaded(Ok(site)) => {
+                // Instances may restrict the languages discussions can use.
+                let allowed = &site.discussion_languages;
+
+                self.languages = site
+                    .all_languages
+                    .iter()
+                    .filter(|language| allowed.is_empty() || allowed.contains(&language.id))
+                    .map(|language| LanguageChoice {
+                        id: language.id,
+                        name: language.name.clone(),
+                    })
+                    .collect();
+
+                // An edited post may have loaded first.
+                if let Some(current) = &self.language {
+                    if let Some(known) = self.languages.iter().find(|known| known.id == current.id)
+                    {
+                        self.language = Some(known.clone());
+                    }
+                }
+
+                Command::none()
+            }
+            Message::CommunitiesLoaded(Ok(response)) => {
+                self.add_communities(
+                    response
+                        .communities
+                        .iter()
+                        .map(|view| CommunityChoice::from_community(&view.community)),
+                );
+
+                Command::none()
+            }
+            Message::CommunityLoaded(Ok(response)) => {
+                let choice = CommunityChoice::from_community(&response.community_view.community);
+
+                self.add_communities([choice.clone()]);
+                self.community = Some(choice);
+
+                Command::none()
+            }
+            Message::PostLoaded(Ok(response)) => {
+                let post = response.post_view.post;
+                let body = post.body.unwrap_or_default();
+
+                self.title = post.name;
+                self.url = post.url.map(|url| url.to_string()).unwrap_or_default();
+                self.nsfw = post.nsfw;
+                self.language = self
+                    .languages
+                    .iter()
+                    .find(|language| language.id == post.language_id)
+                    .cloned()
+                    .or(Some(LanguageChoice {
+                        id: post.language_id,
+                        name: String::from("Unchanged"),
+                    }));
+                self.community = Some(CommunityChoice::from_community(
+                    &response.post_view.community,
+                ));
+                self.body = text_editor::Content::with_text(&body);
+
+                self.body_changed()
+            }
+            Message::SiteLoaded(Err(error))
+            | Message::CommunitiesLoaded(Err(error))
+            | Message::CommunityLoaded(Err(error))
+            | Message::PostLoaded(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::Title(title) => {
+                self.title = title;
+                Command::none()
+            }
+            Message::Url(url) => {
+                self.url = url;
+                self.link_preview = None;
+                Command::none()
+            }
+            Message::FetchLinkPreview => match self.link() {
+                Ok(Some(url)) => {
+                    self.invalid = None;
+
+                    Command::perform(
+                        get_site_metadata(self.api.clone(), url),
+                        Message::LinkPreviewLoaded,
+                    )
+                }
+                Ok(None) => Command::none(),
+                Err(()) => {
+                    self.invalid = Some("The link isn't a valid URL.");
+                    Command::none()
+                }
+            },
+            Message::LinkPreviewLoaded(Ok(preview)) => {
+                self.link_preview = Some(preview);
+                Command::none()
+            }
+            Message::LinkPreviewLoaded(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::Body(action) => {
+                let edited = action.is_edit();
+                self.body.perform(action);
+
+                if edited {
+                    self.body_changed()
+                } else {
+                    Command::none()
+                }
+            }
+            Message::Nsfw(nsfw) => {
+                self.nsfw = nsfw;
+                Command::none()
+            }
+            Message::SetLanguage(language) => {
+                self.language = Some(language);
+                Command::none()
+            }
+            Message::SetCommunity(community) => {
+                self.community = Some(community);
+                Command::none()
+            }
+            Message::Submit => {
+                if self.submitting {
+                    return Command::none();
+                }
+
+                self.submit()
+            }
+            Message::Submitted(result) => {
+                self.submitting = false;
+
+                if let Err(error) = result {
+                    self.error = Some(error);
+                }
+
+                Command::none()
+            }
+            Message::Markdown(message) => {
+                self.preview.update(message);
+                Command::none()
+            }
+        }
+    }
+
+    fn view_link_preview<'a>(&self, preview: &'a GetSiteMetadataResponse) -> Element<'a, Message> {
+        let metadata = &preview.metadata;
+        let mut col = column!().spacing(8);
+
+        if let Some(description) = &metadata.description {
+            col = col.push(text(description));
+        }
+
+        if let Some(video) = &metadata.embed_video_url {
+            col = col
+                .push(badge(text(format!("Video: {}", video.as_str()))).style(BadgeStyles::Info));
+        }
+
+        Card::new(
+            text(metadata.title.as_deref().unwrap_or("No title found")),
+            col,
+        )
+        .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let heading = if self.editing.is_some() {
+            "Edit post"
+        } else {
+            "New post"
+        };
+
+        let mut col = column!(
+            button("Back")
+                .style(theme::Button::Secondary)
+                .on_press(Message::Back),
+            text(heading).size(24),
+        )
+        .spacing(15)
+        .padding(30);
+
+        if let Some(error) = &self.error {
+            col = col.push(message_banner(post_error_message(error), None));
+        }
+
+        if let Some(invalid) = self.invalid {
+            col = col.push(message_banner(invalid.to_string(), None::<Message>));
+        }
+
+        if self.editing.is_some() {
+            if let Some(community) = &self.community {
+                col = col.push(text(format!("In {}", community)));
+            }
+        } else {
+            col = col.push(combo_box(
+                &self.community_search,
+                "Search your subscribed and popular communities",
+                self.community.as_ref(),
+                Message::SetCommunity,
+            ));
+        }
+
+        col = col
+            .push(text_input("Title", &self.title).on_input(Message::Title))
+            .push(
+                row!(
+                    text_input("Link (optional)", &self.url)
+                        .on_input(Message::Url)
+                        .on_submit(Message::FetchLinkPreview),
+                    button("Preview link")
+                        .style(theme::Button::Secondary)
+                        .on_press(Message::FetchLinkPreview),
+                )
+                .spacing(10),
+            );
+
+        if let Some(preview) = &self.link_preview {
+            col = col.push(self.view_link_preview(preview));
+        }
+
+        let editor = column!(
+            text("Body (markdown)"),
+            text_editor(&self.body).on_action(Message::Body).height(300)
+        )
+        .spacing(8)
+        .width(Length::FillPortion(1));
+
+        let preview = column!(
+            text("Preview"),
+            Container::new(self.preview.view(&self.body_text).map(Message::Markdown))
+                .style(theme::Container::Box)
+                .padding(10)
+                .width(Length::Fill)
+        )
+        .spacing(8)
+        .width(Length::FillPortion(1));
+
+        col = col.push(row!(editor, preview).spacing(15));
+
+        col = col.push(
+            row!(
+                checkbox("NSFW", self.nsfw).on_toggle(Message::Nsfw),
+                text("Language"),
+                pick_list(
+                    self.languages.clone(),
+                    self.language.clone(),
+                    Message::SetLanguage
+                ),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+        );
+
+        let label = match (self.submitting, self.editing) {
+            (true, _) => "Sending...",
+            (false, Some(_)) => "Save",
+            (false, None) => "Post",
+        };
+
+        col = col.push(
+            button(label)
+                .on_press_maybe((!self.submitting).then_some(Message::Submit))
+                .width(Length::Fill),
+        );
+
+        Container::new(Scrollable::new(col)).into()
+    }
+}
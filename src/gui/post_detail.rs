@@ -0,0 +1,895 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use iced::{
+    theme,
+    widget::{
+        button, column, horizontal_rule, row, text, text_editor,
+        text_editor::{Action, Content, Motion},
+        Column, Container, Scrollable,
+    },
+    Alignment, Command, Element, Length,
+};
+use iced_aw::{badge, BadgeStyles, Card};
+use lemmy_api_common::{
+    comment::{CommentResponse, CreateComment, EditComment, GetCommentsResponse},
+    lemmy_db_schema::newtypes::{CommentId, PostId},
+    lemmy_db_views::structs::{CommentView, PostView},
+    post::GetPostResponse,
+};
+
+use super::{
+    error_banner,
+    markdown::{self, Link, Markdown},
+    posts::LinkPreview,
+    stale_banner,
+};
+use crate::{
+    api::{
+        create_comment, delete_comment, edit_comment, get_comments_stored, get_post_stored,
+        Fetched, LemnuxError, API,
+    },
+    settings::Drafts,
+};
+
+/// How many levels of replies are requested at once. Deeper threads are
+/// fetched on demand through their parent comment.
+const MAX_DEPTH: i32 = 6;
+
+/// A reply box under the post (`parent` is `None`) or under a comment.
+#[derive(Debug)]
+struct Reply {
+    parent: Option<CommentId>,
+    content: Content,
+    /// The text being answered, shown while quoting so part of it can be
+    /// selected.
+    quoting: Option<Content>,
+}
+
+#[derive(Debug)]
+pub struct PostDetail {
+    api: Arc<API>,
+    post_id: PostId,
+    post: Option<PostView>,
+    comments: HashMap<CommentId, CommentView>,
+    children: HashMap<Option<CommentId>, Vec<CommentId>>,
+    collapsed: HashSet<CommentId>,
+    loading_threads: HashSet<CommentId>,
+    markdown: Markdown,
+    post_reply: Reply,
+    /// At most one comment is answered or edited at a time.
+    comment_reply: Option<Reply>,
+    editing: Option<(CommentId, Content)>,
+    /// A reply or edit is on its way to the instance.
+    sending: bool,
+    /// Own comment whose Delete button was pressed once.
+    confirm_delete: Option<CommentId>,
+    /// When the oldest of the shown post and comments was fetched, if they
+    /// come from the offline store.
+    stale: Option<DateTime<Utc>>,
+    error: Option<LemnuxError>,
+    action_error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Back,
+    Retry,
+    PostLoaded(Result<Fetched<GetPostResponse>, LemnuxError>),
+    CommentsLoaded(
+        Option<CommentId>,
+        Result<Fetched<GetCommentsResponse>, LemnuxError>,
+    ),
+    ToggleCollapse(CommentId),
+    LoadReplies(CommentId),
+    Reply(CommentId),
+    CloseReply,
+    /// Opens the reply box with the post or comment text to quote from.
+    Quote(Option<CommentId>),
+    CloseQuote(Option<CommentId>),
+    ReplyAction(Option<CommentId>, Action),
+    QuoteAction(Option<CommentId>, Action),
+    QuoteSelection(Option<CommentId>),
+    SendReply(Option<CommentId>),
+    Replied(Option<CommentId>, Result<CommentResponse, LemnuxError>),
+    Edit(CommentId),
+    EditAction(Action),
+    CancelEdit,
+    SaveEdit,
+    Edited(Result<CommentResponse, LemnuxError>),
+    /// Deletes an own comment, or restores it if it already is.
+    Delete(CommentId),
+    Deleted(Result<CommentResponse, LemnuxError>),
+    Markdown(markdown::Message),
+}
+
+/// Comment paths look like `0.12.45`: the root marker followed by the ids
+/// of every ancestor and finally the comment itself.
+fn parent_of(path: &str) -> Option<CommentId> {
+    let ids: Vec<&str> = path.split('.').skip(1).collect();
+
+    if ids.len() < 2 {
+        return None;
+    }
+
+    ids[ids.len() - 2].parse().ok().map(CommentId)
+}
+
+/// Replies under a comment that none of its loaded replies account for.
+/// Lemmy's `child_count` counts every descendant, so each loaded reply
+/// takes itself and its own `child_count` off the total; replies deeper
+/// down are left to the loaded reply they sit under.
+fn unloaded_replies(child_count: i32, loaded: impl IntoIterator<Item = i32>) -> i32 {
+    let accounted: i32 = loaded.into_iter().map(|child_count| 1 + child_count).sum();
+
+    (child_count - accounted).max(0)
+}
+
+/// Turns `text` into a markdown blockquote followed by an empty line.
+fn quote(text: &str) -> String {
+    let mut quoted: String = text
+        .trim_end()
+        .lines()
+        .map(|line| format!("> {line}\n"))
+        .collect();
+    quoted.push('\n');
+
+    quoted
+}
+
+/// An editor holding `text` with the cursor after it.
+fn content_at_end(text: &str) -> Content {
+    let mut content = Content::with_text(text);
+    content.perform(Action::Move(Motion::DocumentEnd));
+
+    content
+}
+
+impl PostDetail {
+    pub fn new(api: Arc<API>, post_id: PostId) -> (Self, Command<Message>) {
+        let post_reply = Reply {
+            parent: None,
+            content: content_at_end(
+                &Drafts::get(&Drafts::key(&api.domain, "post", post_id.0)).unwrap_or_default(),
+            ),
+            quoting: None,
+        };
+        let mut page = Self {
+            api,
+            post_id,
+            post: None,
+            comments: HashMap::new(),
+            children: HashMap::new(),
+            collapsed: HashSet::new(),
+            loading_threads: HashSet::new(),
+            markdown: Markdown::new(),
+            post_reply,
+            comment_reply: None,
+            editing: None,
+            sending: false,
+            confirm_delete: None,
+            stale: None,
+            error: None,
+            action_error: None,
+        };
+        let cmd = page.fetch();
+
+        (page, cmd)
+    }
+
+    fn fetch(&mut self) -> Command<Message> {
+        self.error = None;
+        self.stale = None;
+
+        Command::batch(vec![
+            Command::perform(
+                get_post_stored(self.api.clone(), self.post_id),
+                Message::PostLoaded,
+            ),
+            Command::perform(
+                get_comments_stored(self.api.clone(), self.post_id, None, MAX_DEPTH),
+                |ret| Message::CommentsLoaded(None, ret),
+            ),
+        ])
+    }
+
+    fn note_stale(&mut self, fetched: Option<DateTime<Utc>>) {
+        self.stale = match (self.stale, fetched) {
+            (Some(shown), Some(fetched)) => Some(shown.min(fetched)),
+            (shown, fetched) => shown.or(fetched),
+        };
+    }
+
+    /// Merges freshly fetched comments into the tree, keeping the order the
+    /// server sorted them in.
+    fn insert_comments(&mut self, comments: Vec<CommentView>) -> Command<Message> {
+        let mut cmds = Vec::new();
+
+        for comment in comments {
+            let id = comment.comment.id;
+
+            cmds.push(
+                self.markdown
+                    .load_images(self.api.clone(), &comment.comment.content)
+                    .map(Message::Markdown),
+            );
+
+            if self.comments.insert(id, comment).is_none() {
+                let parent = parent_of(&self.comments[&id].comment.path);
+                self.children.entry(parent).or_default().push(id);
+            }
+        }
+
+        Command::batch(cmds)
+    }
+
+    /// Where the draft of a reply to the post or to `parent` is kept.
+    fn reply_key(&self, parent: Option<CommentId>) -> String {
+        match parent {
+            Some(id) => Drafts::key(&self.api.domain, "comment", id.0),
+            None => Drafts::key(&self.api.domain, "post", self.post_id.0),
+        }
+    }
+
+    fn reply_mut(&mut self, parent: Option<CommentId>) -> Option<&mut Reply> {
+        match parent {
+            Some(_) => self
+                .comment_reply
+                .as_mut()
+                .filter(|reply| reply.parent == parent),
+            None => Some(&mut self.post_reply),
+        }
+    }
+
+    /// Opens the reply box under a comment, picking up its draft.
+    fn open_reply(&mut self, id: CommentId) {
+        if self.comment_reply.as_ref().and_then(|reply| reply.parent) == Some(id) {
+            return;
+        }
+
+        let key = self.reply_key(Some(id));
+
+        self.comment_reply = Some(Reply {
+            parent: Some(id),
+            content: content_at_end(&Drafts::get(&key).unwrap_or_default()),
+            quoting: None,
+        });
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Back => Command::none(),
+            Message::Retry => self.fetch(),
+            Message::PostLoaded(Ok(fetched)) => {
+                let response = fetched.value;
+
+                self.note_stale(fetched.stale);
+
+                let cmd = match &response.post_view.post.body {
+                    Some(body) => self
+                        .markdown
+                        .load_images(self.api.clone(), body)
+                        .map(Message::Markdown),
+                    None => Command::none(),
+                };
+
+                self.post = Some(response.post_view);
+                cmd
+            }
+            Message::CommentsLoaded(thread, Ok(fetched)) => {
+                if let Some(id) = thread {
+                    self.loading_threads.remove(&id);
+                }
+
+                self.note_stale(fetched.stale);
+                self.insert_comments(fetched.value.comments)
+            }
+            Message::PostLoaded(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::CommentsLoaded(thread, Err(error)) => {
+                if let Some(id) = thread {
+                    self.loading_threads.remove(&id);
+                }
+
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::ToggleCollapse(id) => {
+                if !self.collapsed.remove(&id) {
+                    self.collapsed.insert(id);
+                }
+
+                Command::none()
+            }
+            Message::LoadReplies(id) => {
+                if !self.loading_threads.insert(id) {
+                    return Command::none();
+                }
+
+                Command::perform(
+                    get_comments_stored(self.api.clone(), self.post_id, Some(id), MAX_DEPTH),
+                    move |ret| Message::CommentsLoaded(Some(id), ret),
+                )
+            }
+            Message::Reply(id) => {
+                self.open_reply(id);
+                Command::none()
+            }
+            Message::CloseReply => {
+                self.comment_reply = None;
+                Command::none()
+            }
+            Message::Quote(parent) => {
+                let source = match parent {
+                    Some(id) => self.comments.get(&id).map(|view| &view.comment.content),
+                    None => self
+                        .post
+                        .as_ref()
+                        .map(|view| view.post.body.as_ref().unwrap_or(&view.post.name)),
+                };
+                let Some(source) = source.map(|source| Content::with_text(source)) else {
+                    return Command::none();
+                };
+
+                if let Some(id) = parent {
+                    self.open_reply(id);
+                }
+
+                if let Some(reply) = self.reply_mut(parent) {
+                    reply.quoting = Some(source);
+                }
+
+                Command::none()
+            }
+            Message::CloseQuote(parent) => {
+                if let Some(reply) = self.reply_mut(parent) {
+                    reply.quoting = None;
+                }
+
+                Command::none()
+            }
+            Message::ReplyAction(parent, action) => {
+                let is_edit = action.is_edit();
+                let Some(reply) = self.reply_mut(parent) else {
+                    return Command::none();
+                };
+
+                reply.content.perform(action);
+
+                if is_edit {
+                    let text = reply.content.text();
+                    Drafts::set(self.reply_key(parent), text);
+                }
+
+                Command::none()
+            }
+            Message::QuoteAction(parent, action) => {
+                // The quoted text is only there to be selected.
+                if let Some(quoting) = self
+                    .reply_mut(parent)
+                    .and_then(|reply| reply.quoting.as_mut())
+                    .filter(|_| !action.is_edit())
+                {
+                    quoting.perform(action);
+                }
+
+                Command::none()
+            }
+            Message::QuoteSelection(parent) => {
+                let Some(reply) = self.reply_mut(parent) else {
+                    return Command::none();
+                };
+                let Some(quoting) = reply.quoting.take() else {
+                    return Command::none();
+                };
+
+                let selection = quoting
+                    .selection()
+                    .filter(|selection| !selection.trim().is_empty())
+                    .unwrap_or_else(|| quoting.text());
+                let text = quote(&selection) + reply.content.text().trim_start();
+                reply.content = content_at_end(&text);
+
+                Drafts::set(self.reply_key(parent), text);
+
+                Command::none()
+            }
+            Message::SendReply(parent) => {
+                if self.sending {
+                    return Command::none();
+                }
+
+                let post_id = self.post_id;
+                let Some(reply) = self.reply_mut(parent) else {
+                    return Command::none();
+                };
+                let content = reply.content.text().trim().to_string();
+
+                if content.is_empty() {
+                    return Command::none();
+                }
+
+                let params = CreateComment {
+                    content,
+                    post_id,
+                    parent_id: parent,
+                    ..Default::default()
+                };
+
+                self.sending = true;
+                self.action_error = None;
+
+                Command::perform(create_comment(self.api.clone(), params), move |ret| {
+                    Message::Replied(parent, ret)
+                })
+            }
+            Message::Replied(parent, Ok(response)) => {
+                self.sending = false;
+                Drafts::set(self.reply_key(parent), String::new());
+
+                match parent {
+                    Some(id) => {
+                        self.comment_reply = None;
+                        self.collapsed.remove(&id);
+                    }
+                    None => self.post_reply.content = Content::new(),
+                }
+
+                // Every ancestor counts the reply among its descendants.
+                let path = &response.comment_view.comment.path;
+                let ancestors = path.split('.').skip(1).filter_map(|id| id.parse().ok());
+
+                for ancestor in ancestors.map(CommentId) {
+                    if ancestor == response.comment_view.comment.id {
+                        continue;
+                    }
+
+                    if let Some(view) = self.comments.get_mut(&ancestor) {
+                        view.counts.child_count += 1;
+                    }
+                }
+
+                if let Some(view) = &mut self.post {
+                    view.counts.comments += 1;
+                }
+
+                self.insert_comments(vec![response.comment_view])
+            }
+            Message::Replied(_, Err(error)) => {
+                self.sending = false;
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::Edit(id) => {
+                let Some(view) = self.comments.get(&id) else {
+                    return Command::none();
+                };
+                let key = Drafts::key(&self.api.domain, "edit", id.0);
+                let text = Drafts::get(&key).unwrap_or_else(|| view.comment.content.clone());
+
+                self.editing = Some((id, content_at_end(&text)));
+                Command::none()
+            }
+            Message::EditAction(action) => {
+                let is_edit = action.is_edit();
+                let Some((id, content)) = &mut self.editing else {
+                    return Command::none();
+                };
+
+                content.perform(action);
+
+                if is_edit {
+                    let key = Drafts::key(&self.api.domain, "edit", id.0);
+                    let text = content.text();
+                    Drafts::set(key, text);
+                }
+
+                Command::none()
+            }
+            Message::CancelEdit => {
+                if let Some((id, _)) = self.editing.take() {
+                    let key = Drafts::key(&self.api.domain, "edit", id.0);
+                    Drafts::set(key, String::new());
+                }
+
+                Command::none()
+            }
+            Message::SaveEdit => {
+                let Some((id, content)) = &self.editing else {
+                    return Command::none();
+                };
+                let content = content.text().trim().to_string();
+
+                if self.sending || content.is_empty() {
+                    return Command::none();
+                }
+
+                let params = EditComment {
+                    comment_id: *id,
+                    content: Some(content),
+                    ..Default::default()
+                };
+
+                self.sending = true;
+                self.action_error = None;
+
+                Command::perform(edit_comment(self.api.clone(), params), Message::Edited)
+            }
+            Message::Edited(Ok(response)) => {
+                self.sending = false;
+
+                if let Some((id, _)) = self.editing.take() {
+                    let key = Drafts::key(&self.api.domain, "edit", id.0);
+                    Drafts::set(key, String::new());
+                }
+
+                self.insert_comments(vec![response.comment_view])
+            }
+            Message::Edited(Err(error)) => {
+                self.sending = false;
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::Delete(id) => {
+                let Some(view) = self.comments.get(&id) else {
+                    return Command::none();
+                };
+                let deleted = !view.comment.deleted;
+
+                if deleted && self.confirm_delete != Some(id) {
+                    self.confirm_delete = Some(id);
+                    return Command::none();
+                }
+
+                self.confirm_delete = None;
+                self.action_error = None;
+
+                Command::perform(
+                    delete_comment(self.api.clone(), id, deleted),
+                    Message::Deleted,
+                )
+            }
+            Message::Deleted(Ok(response)) => self.insert_comments(vec![response.comment_view]),
+            Message::Deleted(Err(error)) => {
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::Markdown(message) => {
+                self.markdown.update(message);
+                Command::none()
+            }
+        }
+    }
+
+    fn view_reply<'a>(&'a self, reply: &'a Reply) -> Element<'a, Message> {
+        let parent = reply.parent;
+        let mut col = column!().spacing(8);
+
+        if let Some(quoting) = &reply.quoting {
+            col = col
+                .push(text("Select what to quote, or quote all of it."))
+                .push(
+                    text_editor(quoting)
+                        .on_action(move |action| Message::QuoteAction(parent, action)),
+                );
+            col = col.push(
+                row!(
+                    button("Quote selection").on_press(Message::QuoteSelection(parent)),
+                    button("Cancel")
+                        .style(theme::Button::Secondary)
+                        .on_press(Message::CloseQuote(parent)),
+                )
+                .spacing(10),
+            );
+        }
+
+        let label = if self.sending { "Sending..." } else { "Reply" };
+        let mut actions = row!(
+            button(label).on_press_maybe((!self.sending).then_some(Message::SendReply(parent))),
+        )
+        .spacing(10);
+
+        if reply.quoting.is_none() {
+            actions = actions.push(
+                button("Quote")
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::Quote(parent)),
+            );
+        }
+
+        if parent.is_some() {
+            actions = actions.push(
+                button("Cancel")
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::CloseReply),
+            );
+        }
+
+        col.push(
+            text_editor(&reply.content)
+                .on_action(move |action| Message::ReplyAction(parent, action))
+                .height(Length::Fixed(120.0)),
+        )
+        .push(actions)
+        .into()
+    }
+
+    fn view_comment_actions(&self, view: &CommentView) -> Element<'_, Message> {
+        let id = view.comment.id;
+        let mut actions = row!().spacing(10);
+
+        if !view.comment.deleted {
+            actions = actions
+                .push(
+                    button("Reply")
+                        .style(theme::Button::Text)
+                        .on_press(Message::Reply(id)),
+                )
+                .push(
+                    button("Quote")
+                        .style(theme::Button::Text)
+                        .on_press(Message::Quote(Some(id))),
+                );
+        }
+
+        if self.api.is_me(&view.creator) {
+            let delete_label = if view.comment.deleted {
+                "Restore"
+            } else if self.confirm_delete == Some(id) {
+                "Confirm delete"
+            } else {
+                "Delete"
+            };
+
+            if !view.comment.deleted {
+                actions = actions.push(
+                    button("Edit")
+                        .style(theme::Button::Text)
+                        .on_press(Message::Edit(id)),
+                );
+            }
+
+            actions = actions.push(
+                button(delete_label)
+                    .style(theme::Button::Text)
+                    .on_press(Message::Delete(id)),
+            );
+        }
+
+        actions.into()
+    }
+
+    fn view_comment(&self, id: CommentId) -> Element<'_, Message> {
+        let view = &self.comments[&id];
+        let collapsed = self.collapsed.contains(&id);
+
+        let header = row!(
+            button(text(if collapsed { "[+]" } else { "[-]" }))
+                .style(theme::Button::Text)
+                .on_press(Message::ToggleCollapse(id)),
+            badge(text(&view.creator.name)).style(BadgeStyles::Primary),
+            badge(text(view.counts.score)).style(BadgeStyles::Secondary),
+            badge(text(view.comment.published.to_rfc2822())).style(BadgeStyles::Info),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        let mut col = column!(header).spacing(8);
+
+        if collapsed {
+            return col.into();
+        }
+
+        col = match &self.editing {
+            Some((editing, content)) if *editing == id => {
+                let label = if self.sending { "Saving..." } else { "Save" };
+
+                col.push(
+                    text_editor(content)
+                        .on_action(Message::EditAction)
+                        .height(Length::Fixed(120.0)),
+                )
+                .push(
+                    row!(
+                        button(label).on_press_maybe((!self.sending).then_some(Message::SaveEdit)),
+                        button("Cancel")
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::CancelEdit),
+                    )
+                    .spacing(10),
+                )
+            }
+            _ if view.comment.deleted => col.push(text("Deleted by its author")),
+            _ => col.push(
+                self.markdown
+                    .view(&view.comment.content)
+                    .map(Message::Markdown),
+            ),
+        };
+
+        if self.api.username.is_some() {
+            col = col.push(self.view_comment_actions(view));
+        }
+
+        if let Some(reply) = &self.comment_reply {
+            if reply.parent == Some(id) {
+                col = col.push(Container::new(self.view_reply(reply)).padding([0, 0, 0, 20]));
+            }
+        }
+
+        let children = self
+            .children
+            .get(&Some(id))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        if !children.is_empty() {
+            let replies =
+                Column::with_children(children.iter().map(|child| self.view_comment(*child)))
+                    .spacing(15);
+
+            col = col.push(Container::new(replies).padding([0, 0, 0, 20]));
+        }
+
+        // Replying under a thread that wasn't loaded yet only adds the
+        // reply, so the rest can still be asked for.
+        let unloaded = unloaded_replies(
+            view.counts.child_count,
+            children
+                .iter()
+                .filter_map(|child| self.comments.get(child))
+                .map(|child| child.counts.child_count),
+        );
+
+        if unloaded > 0 {
+            let label = if self.loading_threads.contains(&id) {
+                String::from("Loading replies...")
+            } else {
+                format!("Load {} more replies", unloaded)
+            };
+
+            col = col.push(
+                button(text(label))
+                    .style(theme::Button::Text)
+                    .on_press(Message::LoadReplies(id)),
+            );
+        }
+
+        col.into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut col = column!(button("Back").on_press(Message::Back))
+            .spacing(30)
+            .padding(30);
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(error, Some(Message::Retry)));
+        }
+
+        if let Some(fetched) = self.stale {
+            col = col.push(stale_banner(fetched, false, Some(Message::Retry)));
+        }
+
+        if let Some(error) = &self.action_error {
+            col = col.push(error_banner(error, None));
+        }
+
+        if let Some(view) = &self.post {
+            let title_row = column!(
+                text(&view.post.name).size(24),
+                horizontal_rule(1),
+                row!(
+                    badge(text(format!(
+                        "{}@{}",
+                        view.creator.name, view.community.title
+                    )))
+                    .style(BadgeStyles::Primary),
+                    badge(text(view.post.published.to_rfc2822())).style(BadgeStyles::Info),
+                    badge(text(format!("{} points", view.counts.score)))
+                        .style(BadgeStyles::Secondary),
+                    badge(text(format!("{} comments", view.counts.comments)))
+                        .style(BadgeStyles::Secondary),
+                )
+                .spacing(10)
+            )
+            .spacing(15);
+
+            let mut body = column!().spacing(15);
+
+            if let Some(preview) = LinkPreview::from_post(&view.post) {
+                body = body.push(preview.view().map(Message::Markdown));
+            } else if let Some(url) = &view.post.url {
+                body = body.push(
+                    button(text(url.to_string()))
+                        .style(theme::Button::Text)
+                        .padding(0)
+                        .on_press(Message::Markdown(markdown::Message::Link(Link::Url(
+                            url.to_string(),
+                        )))),
+                );
+            }
+
+            if let Some(content) = &view.post.body {
+                body = body.push(self.markdown.view(content).map(Message::Markdown));
+            }
+
+            col = col.push(Card::new(title_row, body));
+
+            if self.api.username.is_some() {
+                col = col.push(self.view_reply(&self.post_reply));
+            }
+        } else if self.error.is_none() {
+            col = col.push(text("Loading..."));
+        }
+
+        if let Some(top_level) = self.children.get(&None) {
+            col = col.push(
+                Column::with_children(top_level.iter().map(|id| self.view_comment(*id)))
+                    .spacing(25),
+            );
+        }
+
+        Container::new(Scrollable::new(col).width(Length::Fill)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_level_comments_have_no_parent() {
+        assert_eq!(parent_of("0.12"), None);
+        assert_eq!(parent_of("0"), None);
+    }
+
+    #[test]
+    fn replies_point_at_the_comment_before_them() {
+        assert_eq!(parent_of("0.12.45"), Some(CommentId(12)));
+        assert_eq!(parent_of("0.12.45.78"), Some(CommentId(45)));
+    }
+
+    #[test]
+    fn malformed_paths_have_no_parent() {
+        assert_eq!(parent_of("0.x.45"), None);
+        assert_eq!(parent_of(""), None);
+    }
+
+    #[test]
+    fn quotes_every_line_and_leaves_room_for_the_reply() {
+        assert_eq!(quote("first\nsecond\n\n"), "> first\n> second\n\n");
+    }
+
+    #[test]
+    fn quotes_blank_lines_inside_the_text() {
+        assert_eq!(quote("first\n\nthird"), "> first\n> \n> third\n\n");
+    }
+
+    #[test]
+    fn unloaded_threads_offer_every_reply() {
+        assert_eq!(unloaded_replies(3, []), 3);
+    }
+
+    #[test]
+    fn replying_then_loading_more_keeps_the_other_replies() {
+        // Three replies weren't loaded when one more was posted: the
+        // comment now counts four, and only the new one is shown.
+        assert_eq!(unloaded_replies(4, [0]), 3);
+
+        // Loading the thread brings the rest, and the button goes.
+        assert_eq!(unloaded_replies(4, [0, 0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn deeper_replies_are_left_to_their_own_thread() {
+        // Two loaded replies, one of which has two replies of its own.
+        assert_eq!(unloaded_replies(4, [2, 0]), 0);
+        assert_eq!(unloaded_replies(5, [2, 0]), 1);
+    }
+}
@@ -1,182 +1,1387 @@
+use std::{collections::HashSet, path::PathBuf, sync::Arc};
+
+use chrono::{DateTime, Utc};
+
 use iced::{
     advanced::image::Handle,
+    keyboard::{self, key::Named, Key},
     theme,
     widget::{
-        button, column, horizontal_rule, row,
-        scrollable::{scroll_to, AbsoluteOffset, Id, Scrollable},
-        text, Container, Image,
+        button, column, horizontal_rule, image, pick_list, row,
+        scrollable::{scroll_to, AbsoluteOffset, Id, Scrollable, Viewport},
+        text, text_input, Container, Image,
     },
-    Command, Element, Length,
+    Alignment, Command, Element, Length, Subscription,
 };
-use iced_aw::{badge, BadgeStyles, Card};
+use iced_aw::{badge, modal, BadgeStyles, Card};
 use lemmy_api_common::{
-    lemmy_db_schema::ListingType,
+    lemmy_db_schema::{
+        newtypes::{CommunityId, PersonId, PostId},
+        source::post::Post,
+        SortType,
+    },
     lemmy_db_views::structs::{PaginationCursor, PostView},
-    post::GetPostsResponse,
+    post::{GetPostsResponse, PostResponse},
+};
+
+use super::{
+    error_banner,
+    markdown::{self, Link, Markdown},
+    message_banner, stale_banner,
 };
+use crate::{
+    api::{
+        delete_post, get_posts_stored, hidden_posts, hide_post, image_cache, like_post,
+        queue_pending, save_post, stored_posts, Fetched, LemnuxError, PostsList, Replay, API,
+    },
+    settings::{ListingPreferences, Preferences, TitleAction},
+    store::PendingAction,
+};
+
+pub const SORT_TYPES: [SortType; 19] = [
+    SortType::Active,
+    SortType::Hot,
+    SortType::New,
+    SortType::Old,
+    SortType::Scaled,
+    SortType::Controversial,
+    SortType::MostComments,
+    SortType::NewComments,
+    SortType::TopHour,
+    SortType::TopSixHour,
+    SortType::TopTwelveHour,
+    SortType::TopDay,
+    SortType::TopWeek,
+    SortType::TopMonth,
+    SortType::TopThreeMonths,
+    SortType::TopSixMonths,
+    SortType::TopNineMonths,
+    SortType::TopYear,
+    SortType::TopAll,
+];
+
+pub const PAGE_SIZES: [i64; 4] = [10, 20, 30, 50];
+
+/// How many cards stay rendered in a long session. Older pages are dropped
+/// from the top and can be brought back through the page history.
+const MAX_CARDS: usize = 200;
 
-use crate::api::{get_posts, load_img_to_memory};
+/// Distance from the bottom of the list, in pixels, at which the next page
+/// starts loading.
+const LOAD_MORE_DISTANCE: f32 = 800.0;
+
+/// Height of the space kept for a thumbnail that is still loading.
+const THUMBNAIL_PLACEHOLDER_HEIGHT: f32 = 200.0;
 
 #[derive(Debug)]
 pub struct Posts {
-    type_: Option<ListingType>,
+    api: Arc<API>,
+    /// The listing being shown; its `page_cursor` is the last page requested.
+    query: PostsList,
     post_cards: Vec<PostCard>,
+    /// Cursor of every page loaded since the last refresh, so dropped pages
+    /// can be loaded again. Indexed like [`PostCard::page`].
+    pages: Vec<Option<PaginationCursor>>,
+    /// Oldest page whose cards are still rendered.
+    first_page: usize,
     next_page: Option<PaginationCursor>,
+    /// A page is on its way.
+    loading: bool,
+    /// Counts the pages requested, so an answer to a request that was
+    /// superseded, e.g. by a new sort, is told apart and dropped.
+    generation: u64,
+    /// The page on its way replaces the list instead of extending it.
+    replacing: bool,
+    /// Posts already listed, so pages that overlap don't repeat them.
+    seen: HashSet<PostId>,
+    /// Scrolled away from the top, which offers a way back.
+    scrolled: bool,
+    /// Where the list was last scrolled to, to keep the cards in view in
+    /// place when older ones are trimmed.
+    viewport: Option<Viewport>,
+    /// Posts the account hid, kept in the offline store across sessions.
+    hidden: HashSet<PostId>,
+    /// Own post whose Delete button was pressed once.
+    confirm_delete: Option<PostId>,
+    bodies: Markdown,
+    /// The image shown full screen over the listing.
+    viewer: Option<ImageViewer>,
+    /// Widest a thumbnail is decoded at, from the preferences.
+    thumbnail_width: u32,
+    title_opens: TitleAction,
+    /// When the shown posts were fetched, if they come from the offline
+    /// store.
+    stale: Option<DateTime<Utc>>,
+    /// Posts with a vote or save waiting to be sent once back online.
+    queued: HashSet<PostId>,
+    error: Option<LemnuxError>,
+    action_error: Option<LemnuxError>,
+    /// Says which queued reactions the instance refused once back online.
+    dropped: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum PostFetching {
     NextPage,
-    LoadedResponse(GetPostsResponse),
-    LoadedPost(PostCard),
+    /// Reloads the list starting at the page before the oldest one shown.
+    PreviousPage,
+    Retry,
+    /// Answer to the request of the given generation.
+    LoadedResponse(u64, Result<Fetched<GetPostsResponse>, LemnuxError>),
+    /// Stored copy of the first page, shown until the request of the given
+    /// generation is answered.
+    LoadedStored(u64, Option<Fetched<GetPostsResponse>>),
+    LoadedThumbnail(PostId, Result<Handle, LemnuxError>),
     Idle,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     PostStatus(PostFetching),
-    OpenPost(String),
+    Scrolled(Viewport),
+    BackToTop,
+    OpenPost(PostId),
+    OpenCommunity(CommunityId),
+    OpenPerson(PersonId),
+    Vote(PostId, i16),
+    Save(PostId),
+    Hide(PostId),
+    /// Posts hidden in earlier sessions, read from the offline store.
+    HiddenLoaded(Result<HashSet<PostId>, LemnuxError>),
+    /// The hidden post was written to the offline store, or couldn't be.
+    Hidden(Result<(), LemnuxError>),
+    SetSort(SortType),
+    SetLimit(i64),
+    /// Server answer to a vote or save, with the reactions to restore if it
+    /// failed.
+    Reacted(PostId, Reactions, Result<PostResponse, LemnuxError>),
+    /// A vote or save was kept for later, with the reactions to restore if
+    /// that failed.
+    Queued(PostId, Reactions, Result<(), LemnuxError>),
+    /// Opens the composer, in the listing's community if it has one.
+    NewPost(Option<CommunityId>),
+    EditPost(PostId),
+    Delete(PostId),
+    Deleted(PostId, Result<PostResponse, LemnuxError>),
+    /// Shows the post's full size image over the listing.
+    OpenImage(PostId),
+    CloseImage,
+    NextImage,
+    PreviousImage,
+    ImageLoaded(PostId, Result<Handle, LemnuxError>),
+    ImageFileName(String),
+    SaveImage,
+    ImageSaved(Result<PathBuf, LemnuxError>),
+    Markdown(markdown::Message),
+}
+
+/// The parts of a post the user can change from its card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reactions {
+    pub score: i64,
+    pub upvotes: i64,
+    pub downvotes: i64,
+    pub my_vote: Option<i16>,
+    pub saved: bool,
+}
+
+impl Reactions {
+    pub fn from_view(view: &PostView) -> Self {
+        Self {
+            score: view.counts.score,
+            upvotes: view.counts.upvotes,
+            downvotes: view.counts.downvotes,
+            my_vote: view.my_vote,
+            saved: view.saved,
+        }
+    }
+
+    /// Reactions as they will look once the instance accepts `score`.
+    pub fn with_vote(mut self, score: i16) -> Self {
+        match self.my_vote {
+            Some(1) => self.upvotes -= 1,
+            Some(-1) => self.downvotes -= 1,
+            _ => {}
+        }
+
+        match score {
+            1 => self.upvotes += 1,
+            -1 => self.downvotes += 1,
+            _ => {}
+        }
+
+        self.score = self.upvotes - self.downvotes;
+        self.my_vote = (score != 0).then_some(score);
+        self
+    }
+
+    /// Score to send when the vote button for `direction` is pressed;
+    /// pressing the active one again clears the vote.
+    pub fn toggled_vote(&self, direction: i16) -> i16 {
+        if self.my_vote == Some(direction) {
+            0
+        } else {
+            direction
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PostCard {
-    url: String,
+    id: PostId,
+    /// Index of the page the card came with.
+    page: usize,
     name: String,
     creator: String,
+    creator_id: PersonId,
+    community_id: CommunityId,
+    community: String,
     body: String,
-    thumbnail: Option<Handle>,
+    thumbnail: Thumbnail,
     updated: String,
+    reactions: Reactions,
+    /// Written by the logged in account, so it can be edited and deleted.
+    mine: bool,
+    /// Full size image opened by clicking the thumbnail: the post's link
+    /// when it points at an image, the thumbnail's original otherwise.
+    image_url: Option<String>,
+    /// Where the post links to, if anywhere.
+    link: Option<String>,
+    preview: Option<LinkPreview>,
+}
+
+/// What the instance found at a link post's URL.
+#[derive(Debug, Clone)]
+pub struct LinkPreview {
+    url: String,
+    domain: String,
+    title: Option<String>,
+    description: Option<String>,
+    video_url: Option<String>,
+}
+
+impl LinkPreview {
+    /// `None` for posts without a link and for image posts, whose image
+    /// says more than a preview would.
+    pub fn from_post(post: &Post) -> Option<Self> {
+        let url = post.url.as_ref()?;
+
+        if is_image_url(url.as_str()) {
+            return None;
+        }
+
+        Some(Self {
+            url: url.to_string(),
+            domain: url.domain().unwrap_or_default().to_string(),
+            title: post.embed_title.clone(),
+            description: post.embed_description.clone(),
+            video_url: post.embed_video_url.as_ref().map(|url| url.to_string()),
+        })
+    }
+
+    /// Buttons send [`Link`]s, so pages route them like links in markdown.
+    pub fn view<'a>(&self) -> Element<'a, markdown::Message> {
+        let mut col = column!(badge(text(&self.domain)).style(BadgeStyles::Info)).spacing(10);
+
+        if let Some(title) = &self.title {
+            col = col.push(text(title).size(18));
+        }
+
+        if let Some(description) = &self.description {
+            col = col.push(text(description));
+        }
+
+        let mut buttons = row!(
+            button("Open link").on_press(markdown::Message::Link(Link::Url(self.url.clone())))
+        )
+        .spacing(10);
+
+        if let Some(video_url) = &self.video_url {
+            buttons = buttons.push(
+                button("Play video")
+                    .style(theme::Button::Secondary)
+                    .on_press(markdown::Message::Link(Link::Url(video_url.clone()))),
+            );
+        }
+
+        Container::new(col.push(buttons))
+            .width(Length::Fill)
+            .padding(15)
+            .style(theme::Container::Box)
+            .into()
+    }
+}
+
+fn is_image_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+
+    path.rsplit_once('.').is_some_and(|(_, extension)| {
+        IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+    })
+}
+
+/// Extensions of links that are shown in the image viewer.
+const IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "gif", "webp", "avif", "bmp"];
+
+#[derive(Debug)]
+struct ImageViewer {
+    post_id: PostId,
+    url: String,
+    image: Option<Result<Handle, LemnuxError>>,
+    /// Name the image is saved under in the Pictures directory.
+    file_name: String,
+    saving: bool,
+    saved: Option<Result<PathBuf, LemnuxError>>,
 }
 
-pub async fn convert_postsview_to_card(item: PostView) -> PostCard {
-    let url = if let Some(u) = item.post.url {
-        u.to_string()
-    } else {
-        String::new()
-    };
-    let author = format!("{}@{}", item.creator.name, item.community.title);
-    let body = if let Some(text) = item.post.body {
-        text
-    } else {
-        String::from("Read more...")
-    };
-    let thumbnail = if let Some(url) = item.post.thumbnail_url {
-        Some(Handle::from_memory(
-            load_img_to_memory(&url.to_string()).await,
-        ))
-    } else {
-        None
-    };
-    let updated = if let Some(updated) = item.post.updated {
-        updated.to_rfc2822()
-    } else {
-        item.post.published.to_rfc2822()
-    };
-
-    PostCard {
-        url,
-        name: item.post.name,
-        creator: author,
-        body,
-        thumbnail,
-        updated,
+impl ImageViewer {
+    fn new(post_id: PostId, url: String) -> Self {
+        let file_name = url
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.split(['?', '#']).next())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("image")
+            .to_string();
+
+        Self {
+            post_id,
+            url,
+            image: None,
+            file_name,
+            saving: false,
+            saved: None,
+        }
+    }
+}
+
+pub async fn load_image(api: Arc<API>, url: String) -> Result<Handle, LemnuxError> {
+    image_cache::load_handle(&api, &url).await
+}
+
+async fn load_thumbnail(api: Arc<API>, url: String, max_width: u32) -> Result<Handle, LemnuxError> {
+    image_cache::load_thumbnail(&api, &url, max_width).await
+}
+
+/// A card is shown before its thumbnail arrives, so it tracks where the
+/// download stands.
+#[derive(Debug, Clone)]
+enum Thumbnail {
+    /// The post has no thumbnail: show the text body.
+    None,
+    Loading,
+    Loaded(Handle),
+    /// The thumbnail could not be downloaded or decoded. A placeholder
+    /// saying why goes above the text body.
+    Failed(&'static str),
+}
+
+impl PostCard {
+    fn from_view(api: &API, item: PostView, page: usize) -> (Self, Option<String>) {
+        let reactions = Reactions::from_view(&item);
+        let mine = api.is_me(&item.creator);
+        let preview = LinkPreview::from_post(&item.post);
+        let body = if let Some(text) = item.post.body {
+            text
+        } else {
+            String::from("Read more...")
+        };
+        let thumbnail_url = item.post.thumbnail_url.map(|url| url.to_string());
+        let link = item.post.url.as_ref().map(|url| url.to_string());
+        let image_url = link
+            .clone()
+            .filter(|url| is_image_url(url))
+            .or_else(|| thumbnail_url.clone());
+        let thumbnail = if thumbnail_url.is_some() {
+            Thumbnail::Loading
+        } else {
+            Thumbnail::None
+        };
+        let updated = if let Some(updated) = item.post.updated {
+            updated.to_rfc2822()
+        } else {
+            item.post.published.to_rfc2822()
+        };
+
+        let card = PostCard {
+            id: item.post.id,
+            page,
+            name: item.post.name,
+            creator: item.creator.name,
+            creator_id: item.creator.id,
+            community_id: item.community.id,
+            community: item.community.title,
+            body,
+            thumbnail,
+            updated,
+            reactions,
+            mine,
+            image_url,
+            link,
+            preview,
+        };
+
+        (card, thumbnail_url)
     }
 }
 
 impl Posts {
-    pub fn new(
-        type_: Option<ListingType>,
-        post_cards: Vec<PostCard>,
-        next_page: Option<PaginationCursor>,
-    ) -> Self {
+    pub fn new(api: Arc<API>, query: PostsList) -> Self {
+        // An unreadable file is reported by the app; its defaults do here.
+        let preferences = Preferences::load().unwrap_or_default();
+
         Self {
-            type_,
-            post_cards,
-            next_page,
+            api,
+            pages: vec![query.page_cursor.clone()],
+            query,
+            post_cards: Vec::new(),
+            first_page: 0,
+            next_page: None,
+            loading: false,
+            generation: 0,
+            // The first page may be fetched by whoever made the listing.
+            replacing: true,
+            seen: HashSet::new(),
+            scrolled: false,
+            viewport: None,
+            hidden: HashSet::new(),
+            confirm_delete: None,
+            bodies: Markdown::new(),
+            viewer: None,
+            thumbnail_width: preferences.thumbnail_width,
+            title_opens: preferences.title_opens,
+            stale: None,
+            queued: HashSet::new(),
+            error: None,
+            action_error: None,
+            dropped: None,
         }
     }
 
+    /// Loads the first page of the listing.
+    /// Shows the stored copy of the first page, if there is one, until
+    /// the instance answers.
+    pub fn refresh(&mut self) -> Command<Message> {
+        self.dropped = None;
+
+        let fetch = self.load_from(0);
+
+        if !self.post_cards.is_empty() {
+            return fetch;
+        }
+
+        let generation = self.generation;
+        let stored = Command::perform(
+            stored_posts(self.api.clone(), self.query.clone()),
+            move |stored| Message::PostStatus(PostFetching::LoadedStored(generation, stored)),
+        );
+        let hidden = Command::perform(hidden_posts(self.api.clone()), Message::HiddenLoaded);
+
+        Command::batch(vec![hidden, stored, fetch])
+    }
+
+    /// Replaces the list with the page at `index` of the history and what
+    /// follows it.
+    fn load_from(&mut self, index: usize) -> Command<Message> {
+        let cursor = self.pages.get(index).cloned().flatten();
+
+        self.pages.truncate(index);
+        self.pages.push(cursor.clone());
+        self.first_page = index;
+        self.replacing = true;
+
+        self.fetch(cursor)
+    }
+
+    /// Appends the next page, unless one is already loading or the listing
+    /// ended.
+    fn load_more(&mut self) -> Command<Message> {
+        if self.loading || self.error.is_some() {
+            return Command::none();
+        }
+
+        let Some(cursor) = self.next_page.clone() else {
+            return Command::none();
+        };
+
+        self.pages.push(Some(cursor.clone()));
+        self.replacing = false;
+
+        self.fetch(Some(cursor))
+    }
+
+    /// Drops the oldest pages until the list fits in [`MAX_CARDS`], and
+    /// scrolls up by about the height they took so the cards in view stay
+    /// where they are.
+    fn trim(&mut self) -> Command<Message> {
+        let newest = self.pages.len().saturating_sub(1);
+        // Cards the last viewport was measured with.
+        let laid_out = self
+            .post_cards
+            .iter()
+            .filter(|card| card.page != newest && !self.hidden.contains(&card.id))
+            .count();
+        let mut removed = 0;
+
+        while self.post_cards.len() > MAX_CARDS && self.first_page + 1 < self.pages.len() {
+            let oldest = self.first_page;
+            let (hidden, bodies) = (&self.hidden, &mut self.bodies);
+
+            self.post_cards.retain(|card| {
+                if card.page != oldest {
+                    return true;
+                }
+
+                if !hidden.contains(&card.id) {
+                    removed += 1;
+                }
+
+                bodies.forget(&card.body);
+                false
+            });
+            self.first_page += 1;
+        }
+
+        // Card heights aren't known, so the average one is taken. Without a
+        // fresh viewport the list is left as it is.
+        let Some(viewport) = self.viewport.take().filter(|_| removed > 0 && laid_out > 0) else {
+            return Command::none();
+        };
+        let card_height = viewport.content_bounds().height / laid_out as f32;
+        let y = (viewport.absolute_offset().y - card_height * removed as f32).max(0.0);
+
+        scroll_to(Id::new("PostsContainer"), AbsoluteOffset { x: 0., y })
+    }
+
+    /// Stores the current sort and page size as the default for this tab.
+    /// Community and personal listings are not tabs and keep the tab
+    /// defaults untouched.
+    fn remember_listing(&mut self) {
+        if self.query.community_id.is_some()
+            || self.query.community_name.is_some()
+            || self.query.is_personal()
+        {
+            return;
+        }
+
+        let listing = ListingPreferences {
+            sort: self.query.sort.unwrap_or(SortType::Hot),
+            limit: self.query.limit.unwrap_or(20),
+        };
+        self.action_error = Preferences::load()
+            .and_then(|mut preferences| preferences.set_listing(self.query.type_, listing))
+            .err();
+    }
+
+    /// Arrow keys page through the images while the viewer is open.
+    pub fn subscription(&self) -> Subscription<Message> {
+        if self.viewer.is_none() {
+            return Subscription::none();
+        }
+
+        keyboard::on_key_press(|key, _| match key {
+            Key::Named(Named::ArrowRight) => Some(Message::NextImage),
+            Key::Named(Named::ArrowLeft) => Some(Message::PreviousImage),
+            _ => None,
+        })
+    }
+
+    fn open_image(&mut self, post_id: PostId) -> Command<Message> {
+        let Some(url) = self
+            .card_mut(post_id)
+            .and_then(|card| card.image_url.clone())
+        else {
+            return Command::none();
+        };
+
+        self.viewer = Some(ImageViewer::new(post_id, url.clone()));
+
+        // Decoded here rather than by the renderer so AVIF works too.
+        Command::perform(
+            load_thumbnail(self.api.clone(), url, u32::MAX),
+            move |result| Message::ImageLoaded(post_id, result),
+        )
+    }
+
+    /// Moves the viewer `step` image posts along the listing, loading the
+    /// next page when it runs off the end.
+    fn step_image(&mut self, step: isize) -> Command<Message> {
+        let Some(viewer) = &self.viewer else {
+            return Command::none();
+        };
+
+        let images: Vec<PostId> = self
+            .post_cards
+            .iter()
+            .filter(|card| card.image_url.is_some() && !self.hidden.contains(&card.id))
+            .map(|card| card.id)
+            .collect();
+        let Some(index) = images.iter().position(|id| *id == viewer.post_id) else {
+            return Command::none();
+        };
+
+        match index
+            .checked_add_signed(step)
+            .and_then(|index| images.get(index))
+        {
+            Some(post_id) => self.open_image(*post_id),
+            None if step > 0 => self.load_more(),
+            None => Command::none(),
+        }
+    }
+
+    fn card_mut(&mut self, post_id: PostId) -> Option<&mut PostCard> {
+        self.post_cards.iter_mut().find(|card| card.id == post_id)
+    }
+
+    /// Applies `reactions` right away and lets the instance confirm them.
+    fn react(
+        &mut self,
+        post_id: PostId,
+        reactions: Reactions,
+        request: impl std::future::Future<Output = Result<PostResponse, LemnuxError>> + Send + 'static,
+    ) -> Command<Message> {
+        let Some(card) = self.card_mut(post_id) else {
+            return Command::none();
+        };
+        let previous = card.reactions;
+
+        card.reactions = reactions;
+
+        Command::perform(request, move |ret| Message::Reacted(post_id, previous, ret))
+    }
+
+    /// Takes the queued reactions that were sent or dropped off their cards,
+    /// and says which ones the instance refused.
+    pub fn replayed(&mut self, replay: &Replay) {
+        for post_id in &replay.sent {
+            self.queued.remove(post_id);
+        }
+
+        let mut dropped = replay.dropped.iter().filter(|(action, _)| {
+            let post_id = action.post_id();
+
+            // Listings that never queued the action still show what it did.
+            self.queued.remove(&post_id) || self.card_mut(post_id).is_some()
+        });
+
+        if let Some((_, error)) = dropped.next() {
+            self.dropped = Some(format!(
+                "{} vote(s) or save(s) made offline were refused and dropped: {}",
+                dropped.count() + 1,
+                error
+            ));
+        }
+    }
+
+    /// Keeps the card's reactions and queues what changed since `previous`
+    /// for when the instance can be reached again.
+    fn queue(&mut self, post_id: PostId, previous: Reactions) -> Command<Message> {
+        let Some(card) = self.card_mut(post_id) else {
+            return Command::none();
+        };
+        let current = card.reactions;
+        let mut actions = Vec::new();
+
+        if current.my_vote != previous.my_vote {
+            actions.push(PendingAction::Vote(post_id, current.my_vote.unwrap_or(0)));
+        }
+
+        if current.saved != previous.saved {
+            actions.push(PendingAction::Save(post_id, current.saved));
+        }
+
+        self.queued.insert(post_id);
+
+        Command::perform(queue_pending(self.api.clone(), actions), move |ret| {
+            Message::Queued(post_id, previous, ret)
+        })
+    }
+
+    /// Adds the cards of a page, or replaces the list with them.
+    fn show_page(&mut self, fetched: Fetched<GetPostsResponse>) -> Command<Message> {
+        let posts = fetched.value;
+
+        self.loading = false;
+        self.error = None;
+        self.stale = fetched.stale;
+        self.next_page = posts.next_page;
+
+        let mut cmds = Vec::new();
+
+        if self.replacing {
+            self.post_cards.clear();
+            self.seen.clear();
+            self.scrolled = false;
+            self.viewport = None;
+            cmds.push(scroll_to(
+                Id::new("PostsContainer"),
+                AbsoluteOffset { x: 0., y: 0. },
+            ));
+        }
+
+        let page = self.pages.len().saturating_sub(1);
+
+        // Cards go in right away so the server's order is kept;
+        // thumbnails are filled in by post id as they arrive.
+        for item in posts.posts.into_iter() {
+            if !self.seen.insert(item.post.id) {
+                continue;
+            }
+
+            let (card, thumbnail_url) = PostCard::from_view(&self.api, item, page);
+            let post_id = card.id;
+
+            if let Some(url) = thumbnail_url {
+                cmds.push(Command::perform(
+                    load_thumbnail(self.api.clone(), url, self.thumbnail_width),
+                    move |result| {
+                        Message::PostStatus(PostFetching::LoadedThumbnail(post_id, result))
+                    },
+                ));
+            } else {
+                cmds.push(
+                    self.bodies
+                        .load_images(self.api.clone(), &card.body)
+                        .map(Message::Markdown),
+                );
+            }
+
+            self.post_cards.push(card);
+        }
+
+        cmds.push(self.trim());
+        Command::batch(cmds)
+    }
+
+    fn fetch(&mut self, page_cursor: Option<PaginationCursor>) -> Command<Message> {
+        self.query = self.query.with_cursor(page_cursor);
+        self.loading = true;
+        self.generation += 1;
+
+        let generation = self.generation;
+
+        Command::perform(
+            get_posts_stored(self.api.clone(), self.query.clone()),
+            move |ret| Message::PostStatus(PostFetching::LoadedResponse(generation, ret)),
+        )
+    }
+
     pub fn update(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::PostStatus(fetcher) => match fetcher {
-                PostFetching::NextPage => {
-                    Command::perform(get_posts(self.type_, self.next_page.to_owned()), |ret| {
-                        Message::PostStatus(PostFetching::LoadedResponse(ret))
-                    })
-                }
-                PostFetching::LoadedResponse(posts) => {
-                    self.next_page = posts.next_page;
-                    self.post_cards.clear();
+                PostFetching::NextPage => self.load_more(),
+                PostFetching::PreviousPage => self.load_from(self.first_page.saturating_sub(1)),
+                PostFetching::Retry => {
+                    self.error = None;
+                    self.loading = false;
 
-                    let mut cmds = vec![scroll_to(
-                        Id::new("PostsContainer"),
-                        AbsoluteOffset { x: 0., y: 0. },
-                    )];
-
-                    for item in posts.posts.into_iter() {
-                        cmds.push(Command::perform(convert_postsview_to_card(item), |card| {
-                            Message::PostStatus(PostFetching::LoadedPost(card))
-                        }));
+                    if self.replacing {
+                        self.load_from(self.first_page)
+                    } else {
+                        self.pages.pop();
+                        self.load_more()
                     }
-
-                    Command::batch(cmds)
                 }
-                PostFetching::LoadedPost(card) => {
-                    self.post_cards.push(card);
+                PostFetching::LoadedResponse(generation, _) if generation != self.generation => {
                     Command::none()
                 }
+                PostFetching::LoadedResponse(_, Err(error)) => {
+                    self.loading = false;
+                    self.error = Some(error);
+                    Command::none()
+                }
+                PostFetching::LoadedResponse(_, Ok(fetched)) => self.show_page(fetched),
+                PostFetching::LoadedStored(generation, Some(fetched))
+                    if generation == self.generation
+                        && self.loading
+                        && self.post_cards.is_empty() =>
+                {
+                    let cmd = self.show_page(fetched);
+
+                    // The request is still on its way and replaces the copy.
+                    self.loading = true;
+                    self.replacing = true;
+                    cmd
+                }
+                PostFetching::LoadedStored(..) => Command::none(),
+                PostFetching::LoadedThumbnail(post_id, result) => {
+                    // The card may have been trimmed or replaced since.
+                    let Some(card) = self.card_mut(post_id) else {
+                        return Command::none();
+                    };
+
+                    match result {
+                        Ok(handle) => {
+                            card.thumbnail = Thumbnail::Loaded(handle);
+                            Command::none()
+                        }
+                        Err(error) => {
+                            card.thumbnail = Thumbnail::Failed(match error {
+                                LemnuxError::Unsupported(_) => "Image format not supported",
+                                _ => "Image unavailable",
+                            });
+                            let body = card.body.clone();
+                            self.bodies
+                                .load_images(self.api.clone(), &body)
+                                .map(Message::Markdown)
+                        }
+                    }
+                }
                 PostFetching::Idle => Command::none(),
             },
-            Message::OpenPost(link) => {
-                println!("{}", link);
+            Message::Scrolled(viewport) => {
+                let offset = viewport.absolute_offset().y;
+                let remaining =
+                    viewport.content_bounds().height - (offset + viewport.bounds().height);
+
+                self.scrolled = offset > 0.0;
+                self.viewport = Some(viewport);
+
+                if remaining < LOAD_MORE_DISTANCE {
+                    self.load_more()
+                } else {
+                    Command::none()
+                }
+            }
+            Message::BackToTop => {
+                self.scrolled = false;
+                self.viewport = None;
+
+                scroll_to(Id::new("PostsContainer"), AbsoluteOffset { x: 0., y: 0. })
+            }
+            Message::OpenPost(_) | Message::OpenCommunity(_) | Message::OpenPerson(_) => {
+                Command::none()
+            }
+            Message::Vote(post_id, score) => {
+                let Some(card) = self.card_mut(post_id) else {
+                    return Command::none();
+                };
+                let reactions = card.reactions.with_vote(score);
+
+                self.react(
+                    post_id,
+                    reactions,
+                    like_post(self.api.clone(), post_id, score),
+                )
+            }
+            Message::Save(post_id) => {
+                let Some(card) = self.card_mut(post_id) else {
+                    return Command::none();
+                };
+                let reactions = Reactions {
+                    saved: !card.reactions.saved,
+                    ..card.reactions
+                };
+
+                self.react(
+                    post_id,
+                    reactions,
+                    save_post(self.api.clone(), post_id, reactions.saved),
+                )
+            }
+            Message::Hide(post_id) => {
+                self.hidden.insert(post_id);
+
+                Command::perform(hide_post(self.api.clone(), post_id), Message::Hidden)
+            }
+            Message::HiddenLoaded(Ok(hidden)) => {
+                self.hidden.extend(hidden);
+                Command::none()
+            }
+            // Nothing was hidden before as far as this page can tell.
+            Message::HiddenLoaded(Err(_)) => Command::none(),
+            Message::Hidden(result) => {
+                self.action_error = result.err();
+                Command::none()
+            }
+            Message::NewPost(_) | Message::EditPost(_) => Command::none(),
+            Message::Delete(post_id) => {
+                if self.confirm_delete != Some(post_id) {
+                    self.confirm_delete = Some(post_id);
+                    return Command::none();
+                }
+
+                self.confirm_delete = None;
+
+                Command::perform(delete_post(self.api.clone(), post_id, true), move |ret| {
+                    Message::Deleted(post_id, ret)
+                })
+            }
+            Message::Deleted(post_id, Ok(_)) => {
+                self.action_error = None;
+                self.post_cards.retain(|card| card.id != post_id);
+                Command::none()
+            }
+            Message::Deleted(_, Err(error)) => {
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::SetSort(sort) => {
+                self.query.sort = Some(sort);
+                self.remember_listing();
+                self.refresh()
+            }
+            Message::SetLimit(limit) => {
+                self.query.limit = Some(limit);
+                self.remember_listing();
+                self.refresh()
+            }
+            Message::Reacted(post_id, previous, result) => {
+                let reactions = match result {
+                    Ok(response) => {
+                        self.action_error = None;
+                        Reactions::from_view(&response.post_view)
+                    }
+                    Err(error) if error.is_offline() => return self.queue(post_id, previous),
+                    Err(error) => {
+                        self.action_error = Some(error);
+                        previous
+                    }
+                };
+
+                self.queued.remove(&post_id);
+
+                if let Some(card) = self.card_mut(post_id) {
+                    card.reactions = reactions;
+                }
+
+                Command::none()
+            }
+            Message::Queued(_, _, Ok(())) => Command::none(),
+            Message::Queued(post_id, previous, Err(error)) => {
+                self.queued.remove(&post_id);
+
+                if let Some(card) = self.card_mut(post_id) {
+                    card.reactions = previous;
+                }
+
+                self.action_error = Some(error);
+                Command::none()
+            }
+            Message::OpenImage(post_id) => self.open_image(post_id),
+            Message::CloseImage => {
+                self.viewer = None;
+                Command::none()
+            }
+            Message::NextImage => self.step_image(1),
+            Message::PreviousImage => self.step_image(-1),
+            Message::ImageLoaded(post_id, result) => {
+                if let Some(viewer) = &mut self.viewer {
+                    if viewer.post_id == post_id {
+                        viewer.image = Some(result);
+                    }
+                }
+
+                Command::none()
+            }
+            Message::ImageFileName(file_name) => {
+                if let Some(viewer) = &mut self.viewer {
+                    viewer.file_name = file_name;
+                }
+
+                Command::none()
+            }
+            Message::SaveImage => {
+                let Some(viewer) = &mut self.viewer else {
+                    return Command::none();
+                };
+
+                viewer.saving = true;
+                viewer.saved = None;
+
+                let api = self.api.clone();
+                let url = viewer.url.clone();
+                let file_name = viewer.file_name.clone();
+
+                Command::perform(
+                    async move { image_cache::save_to_pictures(&api, &url, &file_name).await },
+                    Message::ImageSaved,
+                )
+            }
+            Message::ImageSaved(result) => {
+                if let Some(viewer) = &mut self.viewer {
+                    viewer.saving = false;
+                    viewer.saved = Some(result);
+                }
 
                 Command::none()
             }
+            Message::Markdown(message) => {
+                self.bodies.update(message);
+                Command::none()
+            }
         }
     }
 
-    pub fn view(&self) -> Element<Message> {
+    fn view_image(&self, viewer: &ImageViewer) -> Element<'_, Message> {
+        let title = self
+            .post_cards
+            .iter()
+            .find(|card| card.id == viewer.post_id)
+            .map(|card| card.name.as_str())
+            .unwrap_or_default();
+
+        let header = row!(
+            text(title).size(22).width(Length::Fill),
+            button("Previous")
+                .style(theme::Button::Secondary)
+                .on_press(Message::PreviousImage),
+            button("Next")
+                .style(theme::Button::Secondary)
+                .on_press(Message::NextImage),
+            button("Close").on_press(Message::CloseImage),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        let body: Element<'_, Message> = match &viewer.image {
+            None => text("Loading image...").into(),
+            // Scrolling zooms and dragging pans.
+            Some(Ok(handle)) => image::Viewer::new(handle.clone())
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into(),
+            Some(Err(error)) => error_banner(error, Some(Message::OpenImage(viewer.post_id))),
+        };
+
+        let save_row = row!(
+            text_input("File name", &viewer.file_name)
+                .on_input(Message::ImageFileName)
+                .on_submit(Message::SaveImage),
+            button(if viewer.saving {
+                "Saving..."
+            } else {
+                "Save to Pictures"
+            })
+            .on_press_maybe((!viewer.saving).then_some(Message::SaveImage)),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        let mut col = column!(
+            header,
+            Container::new(body)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .center_x()
+                .center_y(),
+            save_row
+        )
+        .spacing(15);
+
+        match &viewer.saved {
+            Some(Ok(path)) => {
+                col = col.push(
+                    badge(text(format!("Saved to {}", path.display()))).style(BadgeStyles::Success),
+                );
+            }
+            Some(Err(error)) => col = col.push(error_banner(error, Some(Message::SaveImage))),
+            None => {}
+        }
+
+        Container::new(col)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(30)
+            .style(theme::Container::Box)
+            .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let listing_row = row!(
+            text("Sort"),
+            pick_list(&SORT_TYPES[..], self.query.sort, Message::SetSort),
+            text("Posts per page"),
+            pick_list(&PAGE_SIZES[..], self.query.limit, Message::SetLimit),
+            button("New post").on_press(Message::NewPost(self.query.community_id)),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        let listing_row = if self.scrolled {
+            listing_row.push(
+                button("Back to top")
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::BackToTop),
+            )
+        } else {
+            listing_row
+        };
+
         let mut col = column!().spacing(60).padding(30);
 
-        for post in &self.post_cards {
+        if self.first_page > 0 {
+            col = col.push(
+                button(text(format!(
+                    "Show earlier posts (page {})",
+                    self.first_page
+                )))
+                .style(theme::Button::Secondary)
+                .on_press(Message::PostStatus(PostFetching::PreviousPage))
+                .width(Length::Fill),
+            );
+        }
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(
+                error,
+                Some(Message::PostStatus(PostFetching::Retry)),
+            ));
+        }
+
+        if let Some(error) = &self.action_error {
+            col = col.push(error_banner(error, None));
+        }
+
+        if let Some(dropped) = &self.dropped {
+            col = col.push(message_banner(dropped.clone(), None::<Message>));
+        }
+
+        if let Some(fetched) = self.stale {
+            col = col.push(stale_banner(
+                fetched,
+                self.loading,
+                Some(Message::PostStatus(PostFetching::Retry)),
+            ));
+        }
+
+        for post in self
+            .post_cards
+            .iter()
+            .filter(|card| !self.hidden.contains(&card.id))
+        {
+            let reactions = &post.reactions;
+            let vote_style = |direction: i16| {
+                if reactions.my_vote == Some(direction) {
+                    theme::Button::Primary
+                } else {
+                    theme::Button::Secondary
+                }
+            };
+
+            let mut actions_row = row!(
+                button(text(format!("Upvote ({})", reactions.upvotes)))
+                    .style(vote_style(1))
+                    .on_press(Message::Vote(post.id, reactions.toggled_vote(1))),
+                button(text(format!("Downvote ({})", reactions.downvotes)))
+                    .style(vote_style(-1))
+                    .on_press(Message::Vote(post.id, reactions.toggled_vote(-1))),
+                badge(text(format!("{} points", reactions.score))).style(BadgeStyles::Secondary),
+                button(if reactions.saved { "Unsave" } else { "Save" })
+                    .style(if reactions.saved {
+                        theme::Button::Primary
+                    } else {
+                        theme::Button::Secondary
+                    })
+                    .on_press(Message::Save(post.id)),
+                button("Hide")
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::Hide(post.id)),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center);
+
+            if self.queued.contains(&post.id) {
+                actions_row = actions_row
+                    .push(badge(text("Sent when back online")).style(BadgeStyles::Warning));
+            }
+
+            if post.mine {
+                let delete_label = if self.confirm_delete == Some(post.id) {
+                    "Confirm delete"
+                } else {
+                    "Delete"
+                };
+
+                actions_row = actions_row
+                    .push(
+                        button("Edit")
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::EditPost(post.id)),
+                    )
+                    .push(
+                        button(delete_label)
+                            .style(theme::Button::Destructive)
+                            .on_press(Message::Delete(post.id)),
+                    );
+            }
+
+            let open_title = match (&self.title_opens, &post.link) {
+                (TitleAction::Link, Some(link)) => {
+                    actions_row = actions_row.push(
+                        button("Comments")
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::OpenPost(post.id)),
+                    );
+
+                    Message::Markdown(markdown::Message::Link(Link::Url(link.clone())))
+                }
+                _ => Message::OpenPost(post.id),
+            };
+
             let title_row = column!(
                 button(text(&post.name))
                     .style(theme::Button::Secondary)
                     .width(Length::Fill)
-                    .on_press(Message::OpenPost(post.url.clone())),
+                    .on_press(open_title),
                 horizontal_rule(1),
                 row!(
-                    badge(text(&post.creator)).style(BadgeStyles::Primary),
+                    button(badge(text(&post.creator)).style(BadgeStyles::Primary))
+                        .style(theme::Button::Text)
+                        .padding(0)
+                        .on_press(Message::OpenPerson(post.creator_id)),
+                    button(badge(text(&post.community)).style(BadgeStyles::Success))
+                        .style(theme::Button::Text)
+                        .padding(0)
+                        .on_press(Message::OpenCommunity(post.community_id)),
                     badge(text(&post.updated)).style(BadgeStyles::Info)
                 )
-                .spacing(10)
+                .spacing(10),
+                actions_row
             )
             .spacing(15);
 
-            let body_row = if post.thumbnail.is_some() {
-                Container::new(Image::new(post.thumbnail.clone().unwrap()))
-                    .width(Length::Fill)
-                    .center_x()
-                    .center_y()
-            } else {
-                Container::new(text(&post.body))
+            let body_row = match &post.thumbnail {
+                Thumbnail::Loaded(handle) => Container::new(
+                    button(Image::new(handle.clone()))
+                        .style(theme::Button::Text)
+                        .padding(0)
+                        .on_press(Message::OpenImage(post.id)),
+                )
+                .width(Length::Fill)
+                .center_x()
+                .center_y(),
+                Thumbnail::Loading => Container::new(text("Loading image..."))
                     .width(Length::Fill)
+                    .height(THUMBNAIL_PLACEHOLDER_HEIGHT)
                     .center_x()
-                    .center_y()
-                    .padding(30)
+                    .center_y(),
+                Thumbnail::None => {
+                    Container::new(self.bodies.view(&post.body).map(Message::Markdown))
+                        .width(Length::Fill)
+                        .center_x()
+                        .center_y()
+                        .padding(30)
+                }
+                Thumbnail::Failed(reason) => Container::new(
+                    column!(
+                        badge(text(reason)).style(BadgeStyles::Warning),
+                        self.bodies.view(&post.body).map(Message::Markdown)
+                    )
+                    .spacing(15)
+                    .align_items(Alignment::Center),
+                )
+                .width(Length::Fill)
+                .center_x()
+                .center_y()
+                .padding(30),
+            };
+
+            let body = match &post.preview {
+                Some(preview) => column!(body_row, preview.view().map(Message::Markdown))
+                    .spacing(15)
+                    .into(),
+                None => Element::from(body_row),
             };
 
-            col = col.push(Card::new(title_row, body_row));
+            col = col.push(Card::new(title_row, body));
         }
 
-        let next_page_btn = button("Next Page")
-            .on_press(Message::PostStatus(PostFetching::NextPage))
-            .width(Length::Fill);
+        // Scrolling loads more on its own; the button covers lists too short
+        // to scroll.
+        if self.loading {
+            col = col.push(text("Loading..."));
+        } else if self.next_page.is_some() && self.error.is_none() {
+            col = col.push(
+                button("Load more")
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::PostStatus(PostFetching::NextPage))
+                    .width(Length::Fill),
+            );
+        } else if !self.post_cards.is_empty() {
+            col = col.push(text("No more posts."));
+        }
+
+        let scrollable = Scrollable::new(col)
+            .id(Id::new("PostsContainer"))
+            .on_scroll(Message::Scrolled);
+
+        let listing = column!(
+            Container::new(listing_row).padding([30, 30, 0, 30]),
+            scrollable
+        );
+        let overlay = self.viewer.as_ref().map(|viewer| self.view_image(viewer));
+
+        modal(listing, overlay)
+            .backdrop(Message::CloseImage)
+            .on_esc(Message::CloseImage)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reactions(upvotes: i64, downvotes: i64, my_vote: Option<i16>) -> Reactions {
+        Reactions {
+            score: upvotes - downvotes,
+            upvotes,
+            downvotes,
+            my_vote,
+            saved: false,
+        }
+    }
 
-        col = col.push(next_page_btn);
+    #[test]
+    fn voting_counts_the_vote() {
+        assert_eq!(reactions(3, 1, None).with_vote(1), reactions(4, 1, Some(1)));
+        assert_eq!(
+            reactions(3, 1, None).with_vote(-1),
+            reactions(3, 2, Some(-1))
+        );
+    }
 
-        let scrollable = Scrollable::new(col).id(Id::new("PostsContainer"));
+    #[test]
+    fn changing_the_vote_moves_it() {
+        assert_eq!(
+            reactions(4, 1, Some(1)).with_vote(-1),
+            reactions(3, 2, Some(-1))
+        );
+        assert_eq!(
+            reactions(3, 2, Some(-1)).with_vote(1),
+            reactions(4, 1, Some(1))
+        );
+    }
+
+    #[test]
+    fn clearing_the_vote_takes_it_back() {
+        assert_eq!(reactions(4, 1, Some(1)).with_vote(0), reactions(3, 1, None));
+        assert_eq!(
+            reactions(3, 2, Some(-1)).with_vote(0),
+            reactions(3, 1, None)
+        );
+    }
+
+    #[test]
+    fn pressing_the_active_vote_clears_it() {
+        assert_eq!(reactions(4, 1, Some(1)).toggled_vote(1), 0);
+        assert_eq!(reactions(4, 1, Some(1)).toggled_vote(-1), -1);
+        assert_eq!(reactions(3, 1, None).toggled_vote(1), 1);
+    }
+
+    #[test]
+    fn image_links_are_recognised_by_extension() {
+        assert!(is_image_url("https://lemmy.ml/pictrs/image/abc.webp"));
+        assert!(is_image_url("https://i.imgur.com/abc.JPG"));
+        assert!(is_image_url("https://example.com/a.png?width=640#top"));
+    }
 
-        Container::new(scrollable).into()
+    #[test]
+    fn other_links_are_not_images() {
+        assert!(!is_image_url("https://example.com/article.html"));
+        assert!(!is_image_url("https://example.com/page?file=a.png"));
+        assert!(!is_image_url("https://example.com/png"));
     }
 }
@@ -0,0 +1,487 @@
+use std::sync::Arc;
+
+use iced::{
+    advanced::image::Handle,
+    theme,
+    widget::{button, column, horizontal_rule, row, text, Container, Image, Scrollable},
+    Alignment, Command, Element, Length,
+};
+use iced_aw::{badge, BadgeStyles};
+use lemmy_api_common::{
+    lemmy_db_schema::{
+        newtypes::{CommunityId, PersonId, PostId},
+        source::person::Person,
+        SortType,
+    },
+    person::{BlockPersonResponse, GetPersonDetails, GetPersonDetailsResponse},
+    site::GetSiteResponse,
+};
+
+use super::{
+    error_banner,
+    markdown::{self, Markdown},
+    posts::load_image,
+};
+use crate::api::{block_person, get_person_details, get_site, LemnuxError, API};
+
+const PAGE_SIZE: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileTab {
+    Posts,
+    Comments,
+    /// What the logged in account saved, only offered on its own profile.
+    Saved,
+}
+
+#[derive(Debug)]
+pub struct Profile {
+    api: Arc<API>,
+    person: GetPersonDetails,
+    details: Option<GetPersonDetailsResponse>,
+    avatar: Option<Handle>,
+    banner: Option<Handle>,
+    tab: ProfileTab,
+    page: i64,
+    loading: bool,
+    /// People the logged in account blocks, once known.
+    blocks: Option<Vec<PersonId>>,
+    blocking: bool,
+    markdown: Markdown,
+    error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Back,
+    Retry,
+    Loaded(Result<GetPersonDetailsResponse, LemnuxError>),
+    SiteLoaded(Result<GetSiteResponse, LemnuxError>),
+    AvatarLoaded(Result<Handle, LemnuxError>),
+    BannerLoaded(Result<Handle, LemnuxError>),
+    SetTab(ProfileTab),
+    NextPage,
+    PreviousPage,
+    Block(bool),
+    Blocked(Result<BlockPersonResponse, LemnuxError>),
+    SendMessage(Person),
+    OpenPost(PostId),
+    OpenCommunity(CommunityId),
+    Markdown(markdown::Message),
+}
+
+impl Profile {
+    /// Opens the profile `person` points at, by id or by `name@instance`.
+    pub fn new(api: Arc<API>, person: GetPersonDetails) -> (Self, Command<Message>) {
+        let mut page = Self {
+            api,
+            person,
+            details: None,
+            avatar: None,
+            banner: None,
+            tab: ProfileTab::Posts,
+            page: 1,
+            loading: false,
+            blocks: None,
+            blocking: false,
+            markdown: Markdown::new(),
+            error: None,
+        };
+        let mut cmds = vec![page.fetch()];
+
+        if page.api.username.is_some() {
+            cmds.push(Command::perform(
+                get_site(page.api.clone()),
+                Message::SiteLoaded,
+            ));
+        }
+
+        (page, Command::batch(cmds))
+    }
+
+    fn fetch(&mut self) -> Command<Message> {
+        self.error = None;
+        self.loading = true;
+
+        // Once loaded, the id is the stable way to ask for more.
+        let (person_id, username) = match &self.details {
+            Some(details) => (Some(details.person_view.person.id), None),
+            None => (self.person.person_id, self.person.username.clone()),
+        };
+        let params = GetPersonDetails {
+            person_id,
+            username,
+            sort: Some(SortType::New),
+            page: Some(self.page),
+            limit: Some(PAGE_SIZE),
+            saved_only: Some(self.tab == ProfileTab::Saved),
+            ..Default::default()
+        };
+
+        Command::perform(
+            get_person_details(self.api.clone(), params),
+            Message::Loaded,
+        )
+    }
+
+    /// Whether the logged in account blocks this person, once known.
+    fn blocked(&self) -> Option<bool> {
+        let details = self.details.as_ref()?;
+
+        self.blocks
+            .as_ref()
+            .map(|blocks| blocks.contains(&details.person_view.person.id))
+    }
+
+    fn is_me(&self) -> bool {
+        self.details
+            .as_ref()
+            .is_some_and(|details| self.api.is_me(&details.person_view.person))
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Back
+            | Message::SendMessage(_)
+            | Message::OpenPost(_)
+            | Message::OpenCommunity(_) => Command::none(),
+            Message::Retry => self.fetch(),
+            Message::Loaded(Ok(details)) => {
+                self.loading = false;
+
+                let person = &details.person_view.person;
+                let mut cmds = Vec::new();
+
+                if self.details.is_none() {
+                    if let Some(avatar) = &person.avatar {
+                        cmds.push(Command::perform(
+                            load_image(self.api.clone(), avatar.to_string()),
+                            Message::AvatarLoaded,
+                        ));
+                    }
+
+                    if let Some(banner) = &person.banner {
+                        cmds.push(Command::perform(
+                            load_image(self.api.clone(), banner.to_string()),
+                            Message::BannerLoaded,
+                        ));
+                    }
+                }
+
+                let sources = person
+                    .bio
+                    .iter()
+                    .chain(
+                        details
+                            .posts
+                            .iter()
+                            .filter_map(|view| view.post.body.as_ref()),
+                    )
+                    .chain(details.comments.iter().map(|view| &view.comment.content));
+
+                for source in sources {
+                    cmds.push(
+                        self.markdown
+                            .load_images(self.api.clone(), source)
+                            .map(Message::Markdown),
+                    );
+                }
+
+                self.details = Some(details);
+
+                Command::batch(cmds)
+            }
+            Message::Loaded(Err(error)) => {
+                self.loading = false;
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::SiteLoaded(Ok(site)) => {
+                self.blocks = site.my_user.map(|my_user| {
+                    my_user
+                        .person_blocks
+                        .iter()
+                        .map(|block| block.target.id)
+                        .collect()
+                });
+
+                Command::none()
+            }
+            // Without the site, blocking just isn't offered.
+            Message::SiteLoaded(Err(_)) => Command::none(),
+            // Avatars and banners are decoration; the page works without them.
+            Message::AvatarLoaded(avatar) => {
+                self.avatar = avatar.ok();
+                Command::none()
+            }
+            Message::BannerLoaded(banner) => {
+                self.banner = banner.ok();
+                Command::none()
+            }
+            Message::SetTab(tab) => {
+                let refetch = (tab == ProfileTab::Saved) != (self.tab == ProfileTab::Saved);
+
+                self.tab = tab;
+
+                if refetch || self.page != 1 {
+                    self.page = 1;
+                    self.fetch()
+                } else {
+                    Command::none()
+                }
+            }
+            Message::NextPage => {
+                self.page += 1;
+                self.fetch()
+            }
+            Message::PreviousPage => {
+                self.page = (self.page - 1).max(1);
+                self.fetch()
+            }
+            Message::Block(block) => {
+                let Some(details) = &self.details else {
+                    return Command::none();
+                };
+
+                self.blocking = true;
+
+                Command::perform(
+                    block_person(self.api.clone(), details.person_view.person.id, block),
+                    Message::Blocked,
+                )
+            }
+            Message::Blocked(result) => {
+                self.blocking = false;
+
+                match result {
+                    Ok(response) => {
+                        let id = response.person_view.person.id;
+
+                        if let Some(blocks) = &mut self.blocks {
+                            blocks.retain(|blocked| *blocked != id);
+
+                            if response.blocked {
+                                blocks.push(id);
+                            }
+                        }
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+
+                Command::none()
+            }
+            Message::Markdown(message) => {
+                self.markdown.update(message);
+                Command::none()
+            }
+        }
+    }
+
+    fn view_header<'a>(&'a self, details: &'a GetPersonDetailsResponse) -> Element<'a, Message> {
+        let view = &details.person_view;
+        let person = &view.person;
+        let mut col = column!().spacing(15);
+
+        if let Some(banner) = &self.banner {
+            col = col.push(
+                Container::new(Image::new(banner.clone()).height(120))
+                    .width(Length::Fill)
+                    .center_x(),
+            );
+        }
+
+        let mut title_row = row!().spacing(15).align_items(Alignment::Center);
+
+        if let Some(avatar) = &self.avatar {
+            title_row = title_row.push(Image::new(avatar.clone()).width(64).height(64));
+        }
+
+        let name = match &person.display_name {
+            Some(display_name) => format!("{display_name} ({})", person.name),
+            None => person.name.clone(),
+        };
+
+        title_row = title_row.push(text(name).size(24));
+
+        if self.api.username.is_some() && !self.is_me() {
+            title_row = title_row.push(
+                button("Message")
+                    .style(theme::Button::Secondary)
+                    .on_press(Message::SendMessage(person.clone())),
+            );
+
+            if let Some(blocked) = self.blocked() {
+                let block_btn = if blocked {
+                    button("Unblock")
+                } else {
+                    button("Block").style(theme::Button::Destructive)
+                };
+
+                title_row = title_row.push(
+                    block_btn.on_press_maybe((!self.blocking).then_some(Message::Block(!blocked))),
+                );
+            }
+        }
+
+        let mut badges = row!(
+            badge(text(format!(
+                "Joined {}",
+                person.published.format("%B %-d, %Y")
+            )))
+            .style(BadgeStyles::Info),
+            badge(text(format!("{} posts", view.counts.post_count))).style(BadgeStyles::Info),
+            badge(text(format!("{} comments", view.counts.comment_count))).style(BadgeStyles::Info),
+        )
+        .spacing(10);
+
+        if view.is_admin {
+            badges = badges.push(badge(text("Admin")).style(BadgeStyles::Primary));
+        }
+
+        if person.bot_account {
+            badges = badges.push(badge(text("Bot")).style(BadgeStyles::Warning));
+        }
+
+        if person.banned {
+            badges = badges.push(badge(text("Banned")).style(BadgeStyles::Danger));
+        }
+
+        if self.blocked() == Some(true) {
+            badges = badges.push(badge(text("Blocked")).style(BadgeStyles::Dark));
+        }
+
+        col = col.push(title_row).push(badges);
+
+        if let Some(bio) = &person.bio {
+            col = col.push(self.markdown.view(bio).map(Message::Markdown));
+        }
+
+        col.into()
+    }
+
+    fn view_tabs(&self) -> Element<'_, Message> {
+        let mut tabs = vec![
+            (ProfileTab::Posts, "Posts"),
+            (ProfileTab::Comments, "Comments"),
+        ];
+
+        if self.is_me() {
+            tabs.push((ProfileTab::Saved, "Saved"));
+        }
+
+        let mut tab_row = row!().spacing(10);
+
+        for (tab, label) in tabs {
+            tab_row = tab_row.push(
+                button(label)
+                    .style(if tab == self.tab {
+                        theme::Button::Primary
+                    } else {
+                        theme::Button::Secondary
+                    })
+                    .on_press(Message::SetTab(tab)),
+            );
+        }
+
+        tab_row.into()
+    }
+
+    fn view_items<'a>(&'a self, details: &'a GetPersonDetailsResponse) -> Element<'a, Message> {
+        let mut col = column!().spacing(15);
+        let show_posts = self.tab != ProfileTab::Comments;
+        let show_comments = self.tab != ProfileTab::Posts;
+
+        if show_posts {
+            for view in &details.posts {
+                col = col.push(
+                    row!(
+                        button(text(&view.post.name))
+                            .style(theme::Button::Secondary)
+                            .width(Length::Fill)
+                            .on_press(Message::OpenPost(view.post.id)),
+                        button(badge(text(&view.community.title)).style(BadgeStyles::Success))
+                            .style(theme::Button::Text)
+                            .padding(0)
+                            .on_press(Message::OpenCommunity(view.community.id)),
+                        badge(text(format!("{} points", view.counts.score)))
+                            .style(BadgeStyles::Secondary),
+                    )
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                );
+            }
+        }
+
+        if show_comments {
+            for view in &details.comments {
+                col = col.push(
+                    column!(
+                        row!(
+                            badge(text(&view.creator.name)).style(BadgeStyles::Primary),
+                            button(text(format!("in {}", view.post.name)))
+                                .style(theme::Button::Text)
+                                .on_press(Message::OpenPost(view.post.id)),
+                            badge(text(format!("{} points", view.counts.score)))
+                                .style(BadgeStyles::Secondary),
+                        )
+                        .spacing(10)
+                        .align_items(Alignment::Center),
+                        self.markdown
+                            .view(&view.comment.content)
+                            .map(Message::Markdown),
+                        horizontal_rule(1),
+                    )
+                    .spacing(8),
+                );
+            }
+        }
+
+        let empty = (!show_posts || details.posts.is_empty())
+            && (!show_comments || details.comments.is_empty());
+
+        if empty {
+            col = col.push(text("Nothing here."));
+        }
+
+        let count = details.posts.len().max(details.comments.len()) as i64;
+
+        col.push(
+            row!(
+                button("Previous").on_press_maybe((self.page > 1).then_some(Message::PreviousPage)),
+                text(format!("Page {}", self.page)),
+                button("Next").on_press_maybe((count >= PAGE_SIZE).then_some(Message::NextPage)),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+        )
+        .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut col = column!(button("Back")
+            .style(theme::Button::Secondary)
+            .on_press(Message::Back))
+        .spacing(20)
+        .padding(30);
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(error, Some(Message::Retry)));
+        }
+
+        match &self.details {
+            Some(details) => {
+                col = col.push(self.view_header(details)).push(self.view_tabs());
+
+                col = if self.loading {
+                    col.push(text("Loading..."))
+                } else {
+                    col.push(self.view_items(details))
+                };
+            }
+            None if self.error.is_none() => col = col.push(text("Loading...")),
+            None => {}
+        }
+
+        Container::new(Scrollable::new(col).width(Length::Fill)).into()
+    }
+}
@@ -0,0 +1,494 @@
+use std::{path::PathBuf, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use iced::{
+    advanced::image::Handle,
+    theme,
+    widget::{button, checkbox, column, row, text, text_input, Container, Image, Scrollable},
+    Alignment, Command, Element, Length,
+};
+use iced_aw::{badge, BadgeStyles};
+use lemmy_api_common::{
+    lemmy_db_schema::RegistrationMode, person::Register as RegisterParams, sensitive::Sensitive,
+    site::GetSiteResponse,
+};
+
+use super::{error_banner, message_banner};
+use crate::{
+    api::{get_captcha, get_site, register, LemnuxError, API},
+    settings::{cache_dir, with_credential_store, Account, Accounts, JWT},
+};
+
+/// A captcha with its image in memory and both files written to the cache
+/// directory, so the audio can be played with any player.
+#[derive(Debug, Clone)]
+pub struct Captcha {
+    uuid: String,
+    image: Handle,
+    png_path: PathBuf,
+    wav_path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct Register {
+    api: Arc<API>,
+    site: Option<GetSiteResponse>,
+    captcha: Option<Captcha>,
+    username: String,
+    email: String,
+    password: String,
+    password_verify: String,
+    answer: String,
+    captcha_answer: String,
+    show_nsfw: bool,
+    submitting: bool,
+    /// What happened to a registration the instance accepted.
+    notice: Option<String>,
+    error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Back,
+    Retry,
+    SiteLoaded(Result<GetSiteResponse, LemnuxError>),
+    NewCaptcha,
+    CaptchaLoaded(Result<Option<Captcha>, LemnuxError>),
+    Username(String),
+    Email(String),
+    Password(String),
+    PasswordVerify(String),
+    Answer(String),
+    CaptchaAnswer(String),
+    ShowNsfw(bool),
+    Submit,
+    Registered(Result<JWT, LemnuxError>),
+    /// The new account was saved and made active, or couldn't be.
+    AccountAdded(Result<(), LemnuxError>),
+}
+
+/// Where the shown captcha's image and audio are written. The names are
+/// fixed so that the instance's uuid never becomes part of a path, and
+/// each captcha replaces the last.
+fn captcha_paths() -> Result<(PathBuf, PathBuf), LemnuxError> {
+    let dir = cache_dir()?.join("captcha");
+
+    Ok((dir.join("captcha.png"), dir.join("captcha.wav")))
+}
+
+/// Deletes the files of the last captcha, which can't be answered again.
+async fn remove_captcha() -> Result<(), LemnuxError> {
+    let (png_path, wav_path) = captcha_paths()?;
+
+    for path in [png_path, wav_path] {
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches a captcha and writes its image and audio to disk.
+async fn load_captcha(api: Arc<API>) -> Result<Option<Captcha>, LemnuxError> {
+    remove_captcha().await?;
+
+    let Some(captcha) = get_captcha(api).await?.ok else {
+        return Ok(None);
+    };
+
+    let decode = |data: &str| {
+        STANDARD
+            .decode(data)
+            .map_err(|err| LemnuxError::Decode(err.to_string()))
+    };
+    let png = decode(&captcha.png)?;
+    let wav = decode(&captcha.wav)?;
+
+    let (png_path, wav_path) = captcha_paths()?;
+
+    if let Some(dir) = png_path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(&png_path, &png).await?;
+    tokio::fs::write(&wav_path, &wav).await?;
+
+    Ok(Some(Captcha {
+        uuid: captcha.uuid,
+        image: Handle::from_memory(png),
+        png_path,
+        wav_path,
+    }))
+}
+
+/// Words Lemmy's sign-up error codes for people.
+fn register_error_message(error: &LemnuxError) -> String {
+    let message = match error.lemmy_error() {
+        Some("registration_closed") => "This instance doesn't accept new accounts.",
+        Some("registration_application_answer_required") => {
+            "This instance asks new users to answer its question."
+        }
+        Some("email_required") => "This instance needs an email address to verify.",
+        Some("captcha_incorrect") => "The captcha answer was wrong. Try the new one.",
+        Some("passwords_do_not_match") => "The passwords don't match.",
+        Some("user_already_exists") => "That username is taken.",
+        Some("email_already_exists") => "An account already uses that email address.",
+        Some("invalid_name") => "Usernames may only use letters, numbers and underscores.",
+        Some("invalid_password") => "Passwords need between 10 and 60 characters.",
+        Some("honeypot_failed") => "The instance took this sign-up for a bot.",
+        _ => return error.to_string(),
+    };
+
+    message.to_string()
+}
+
+impl Register {
+    pub fn new(api: Arc<API>) -> (Self, Command<Message>) {
+        let mut page = Self {
+            api,
+            site: None,
+            captcha: None,
+            username: String::new(),
+            email: String::new(),
+            password: String::new(),
+            password_verify: String::new(),
+            answer: String::new(),
+            captcha_answer: String::new(),
+            show_nsfw: false,
+            submitting: false,
+            notice: None,
+            error: None,
+        };
+        let cmd = page.fetch();
+
+        (page, cmd)
+    }
+
+    fn fetch(&mut self) -> Command<Message> {
+        self.error = None;
+
+        Command::perform(get_site(self.api.clone()), Message::SiteLoaded)
+    }
+
+    fn fetch_captcha(&mut self) -> Command<Message> {
+        self.captcha_answer.clear();
+
+        Command::perform(load_captcha(self.api.clone()), Message::CaptchaLoaded)
+    }
+
+    fn registration_mode(&self) -> Option<RegistrationMode> {
+        self.site
+            .as_ref()
+            .map(|site| site.site_view.local_site.registration_mode)
+    }
+
+    fn captcha_enabled(&self) -> bool {
+        self.site
+            .as_ref()
+            .is_some_and(|site| site.site_view.local_site.captcha_enabled)
+    }
+
+    /// The new account becomes the active one, like after a login.
+    fn add_account(&self, token: Sensitive<String>) -> Command<Message> {
+        let account = Account::new(
+            self.username.trim().to_string().into(),
+            self.api.domain.clone(),
+        );
+
+        Command::perform(
+            with_credential_store(move |store| {
+                let mut accounts = Accounts::load(store)?;
+
+                accounts.add(store, account, Some(token))?;
+                accounts.store()
+            }),
+            Message::AccountAdded,
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Back => Command::none(),
+            Message::Retry => self.fetch(),
+            Message::SiteLoaded(Ok(site)) => {
+                let captcha_enabled = site.site_view.local_site.captcha_enabled;
+                self.site = Some(site);
+
+                if captcha_enabled {
+                    self.fetch_captcha()
+                } else {
+                    Command::none()
+                }
+            }
+            Message::SiteLoaded(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::NewCaptcha => self.fetch_captcha(),
+            Message::CaptchaLoaded(Ok(captcha)) => {
+                self.captcha = captcha;
+                Command::none()
+            }
+            Message::CaptchaLoaded(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::Username(username) => {
+                self.username = username;
+                Command::none()
+            }
+            Message::Email(email) => {
+                self.email = email;
+                Command::none()
+            }
+            Message::Password(password) => {
+                self.password = password;
+                Command::none()
+            }
+            Message::PasswordVerify(password) => {
+                self.password_verify = password;
+                Command::none()
+            }
+            Message::Answer(answer) => {
+                self.answer = answer;
+                Command::none()
+            }
+            Message::CaptchaAnswer(answer) => {
+                self.captcha_answer = answer;
+                Command::none()
+            }
+            Message::ShowNsfw(show_nsfw) => {
+                self.show_nsfw = show_nsfw;
+                Command::none()
+            }
+            Message::Submit => {
+                if self.username.is_empty() || self.password.is_empty() || self.submitting {
+                    return Command::none();
+                }
+
+                let non_empty =
+                    |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+                let params = RegisterParams {
+                    username: self.username.trim().to_string(),
+                    password: self.password.clone().into(),
+                    password_verify: self.password_verify.clone().into(),
+                    show_nsfw: self.show_nsfw,
+                    email: non_empty(&self.email).map(Into::into),
+                    captcha_uuid: self.captcha.as_ref().map(|captcha| captcha.uuid.clone()),
+                    captcha_answer: non_empty(&self.captcha_answer),
+                    honeypot: None,
+                    answer: non_empty(&self.answer),
+                };
+
+                self.submitting = true;
+                self.error = None;
+                self.notice = None;
+
+                Command::perform(register(self.api.clone(), params), Message::Registered)
+            }
+            Message::Registered(Ok(jwt)) => {
+                self.submitting = false;
+
+                // The answered captcha is spent. A file left over in the
+                // cache is harmless, so failing to delete it isn't shown.
+                let forget = Command::perform(
+                    async {
+                        let _ = remove_captcha().await;
+                        Ok(None)
+                    },
+                    Message::CaptchaLoaded,
+                );
+
+                if let Some(token) = jwt.token {
+                    return Command::batch(vec![forget, self.add_account(token)]);
+                }
+
+                self.notice = Some(String::from(if jwt.verify_email_sent {
+                    "Check your inbox and follow the verification link, then log in from the settings."
+                } else if jwt.registration_created {
+                    "Your application was sent. You can log in once an admin approves it."
+                } else {
+                    "Your account was created. Log in from the settings."
+                }));
+
+                forget
+            }
+            Message::AccountAdded(Ok(())) => {
+                self.notice = Some(String::from("Your account is ready and now in use."));
+
+                Command::none()
+            }
+            Message::AccountAdded(Err(error)) => {
+                self.error = Some(error);
+                self.notice = Some(String::from(
+                    "Your account was created, but couldn't be saved. Log in from the settings.",
+                ));
+
+                Command::none()
+            }
+            Message::Registered(Err(error)) => {
+                self.submitting = false;
+                self.error = Some(error);
+
+                // A captcha can only be answered once.
+                if self.captcha_enabled() {
+                    self.fetch_captcha()
+                } else {
+                    Command::none()
+                }
+            }
+        }
+    }
+
+    fn view_form(&self, site: &GetSiteResponse) -> Element<'_, Message> {
+        let local_site = &site.site_view.local_site;
+        let mut col = column!(
+            text_input("Username", &self.username).on_input(Message::Username),
+            text_input(
+                if local_site.require_email_verification {
+                    "Email"
+                } else {
+                    "Email (optional)"
+                },
+                &self.email
+            )
+            .on_input(Message::Email),
+            text_input("Password", &self.password)
+                .secure(true)
+                .on_input(Message::Password),
+            text_input("Repeat password", &self.password_verify)
+                .secure(true)
+                .on_input(Message::PasswordVerify),
+            checkbox("Show NSFW content", self.show_nsfw).on_toggle(Message::ShowNsfw),
+        )
+        .spacing(8);
+
+        if local_site.registration_mode == RegistrationMode::RequireApplication {
+            if let Some(question) = &local_site.application_question {
+                col = col.push(text(question));
+            }
+
+            col = col.push(text_input("Your answer", &self.answer).on_input(Message::Answer));
+        }
+
+        if let Some(captcha) = &self.captcha {
+            col = col
+                .push(Image::new(captcha.image.clone()).width(Length::Shrink))
+                .push(text(format!(
+                    "Saved as {} and, for listening, {}",
+                    captcha.png_path.display(),
+                    captcha.wav_path.display()
+                )))
+                .push(
+                    row!(
+                        text_input("Captcha", &self.captcha_answer)
+                            .on_input(Message::CaptchaAnswer)
+                            .on_submit(Message::Submit),
+                        button("New captcha")
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::NewCaptcha),
+                    )
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                );
+        }
+
+        let label = if self.submitting {
+            "Signing up..."
+        } else {
+            "Sign up"
+        };
+
+        col.push(
+            button(label)
+                .on_press_maybe((!self.submitting).then_some(Message::Submit))
+                .width(Length::Fill),
+        )
+        .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut col = column!(button("Back")
+            .style(theme::Button::Secondary)
+            .on_press(Message::Back))
+        .spacing(15)
+        .padding(30);
+
+        col = col.push(text(format!("Sign up on {}", self.api.domain)).size(24));
+
+        if let Some(error) = &self.error {
+            col = col.push(if self.site.is_some() {
+                message_banner(register_error_message(error), None)
+            } else {
+                error_banner(error, Some(Message::Retry))
+            });
+        }
+
+        if let Some(notice) = &self.notice {
+            col = col.push(badge(text(notice)).style(BadgeStyles::Success));
+        }
+
+        match (&self.site, self.registration_mode()) {
+            (Some(_), Some(RegistrationMode::Closed)) => {
+                col = col.push(text("This instance doesn't accept new accounts."));
+            }
+            (Some(site), Some(_)) if self.notice.is_none() => {
+                col = col.push(self.view_form(site));
+            }
+            (None, _) if self.error.is_none() => {
+                col = col.push(text("Loading..."));
+            }
+            _ => {}
+        }
+
+        Container::new(Scrollable::new(col)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refused(error: &str) -> LemnuxError {
+        LemnuxError::Http {
+            status: 400,
+            error: Some(error.to_string()),
+        }
+    }
+
+    #[test]
+    fn known_codes_are_worded() {
+        assert_eq!(
+            register_error_message(&refused("user_already_exists")),
+            "That username is taken."
+        );
+        assert_eq!(
+            register_error_message(&refused("captcha_incorrect")),
+            "The captcha answer was wrong. Try the new one."
+        );
+    }
+
+    #[test]
+    fn unknown_codes_fall_back_to_the_error() {
+        let error = refused("something_new");
+
+        assert_eq!(register_error_message(&error), error.to_string());
+    }
+
+    #[test]
+    fn errors_without_a_code_fall_back_to_the_error() {
+        let error = LemnuxError::Transport(String::from("connection reset"));
+
+        assert_eq!(register_error_message(&error), error.to_string());
+    }
+
+    #[test]
+    fn captcha_files_have_fixed_names() {
+        let (png_path, wav_path) = captcha_paths().unwrap();
+
+        assert_eq!(png_path.file_name().unwrap(), "captcha.png");
+        assert_eq!(wav_path.file_name().unwrap(), "captcha.wav");
+        assert_eq!(png_path.parent(), wav_path.parent());
+    }
+}
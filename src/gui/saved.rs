@@ -0,0 +1,473 @@
+use std::{collections::HashSet, fmt::Display, sync::Arc};
+
+use iced::{
+    theme,
+    widget::{
+        button, checkbox, column, horizontal_rule, pick_list, row, text, Container, Scrollable,
+    },
+    Alignment, Command, Element, Length, Subscription,
+};
+use iced_aw::{badge, BadgeStyles};
+use lemmy_api_common::{
+    comment::{CommentResponse, GetCommentsResponse},
+    lemmy_db_schema::newtypes::{CommentId, CommunityId, PostId},
+    lemmy_db_views::structs::{CommentView, PostView},
+    post::{GetPostsResponse, PostResponse},
+};
+
+use super::{
+    error_banner,
+    markdown::{self, Markdown},
+    posts::{self, Posts},
+};
+use crate::api::{
+    get_posts, list_saved_comments, save_comment, save_post, LemnuxError, PersonalListing,
+    PostsList, API,
+};
+
+const PAGE_SIZE: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavedView {
+    Posts,
+    Comments,
+    Upvoted,
+    Downvoted,
+}
+
+impl SavedView {
+    const ALL: [SavedView; 4] = [
+        SavedView::Posts,
+        SavedView::Comments,
+        SavedView::Upvoted,
+        SavedView::Downvoted,
+    ];
+}
+
+impl Display for SavedView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            SavedView::Posts => "Saved posts",
+            SavedView::Comments => "Saved comments",
+            SavedView::Upvoted => "Upvoted posts",
+            SavedView::Downvoted => "Downvoted posts",
+        };
+
+        write!(f, "{label}")
+    }
+}
+
+/// What the logged in user kept: saved posts and comments, which can be
+/// unsaved in bulk, and the posts they voted on.
+#[derive(Debug)]
+pub struct Saved {
+    api: Arc<API>,
+    view: SavedView,
+    page: i64,
+    posts: Vec<PostView>,
+    comments: Vec<CommentView>,
+    selected_posts: HashSet<PostId>,
+    selected_comments: HashSet<CommentId>,
+    /// Upvoted or downvoted posts, shown as regular cards.
+    voted: Option<Posts>,
+    loading: bool,
+    /// Unsave requests still running.
+    unsaving: usize,
+    markdown: Markdown,
+    error: Option<LemnuxError>,
+    action_error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Retry,
+    SetView(SavedView),
+    NextPage,
+    PreviousPage,
+    PostsLoaded(Result<GetPostsResponse, LemnuxError>),
+    CommentsLoaded(Result<GetCommentsResponse, LemnuxError>),
+    SelectPost(PostId, bool),
+    SelectComment(CommentId, bool),
+    SelectAll(bool),
+    UnsaveSelected,
+    PostUnsaved(PostId, Result<PostResponse, LemnuxError>),
+    CommentUnsaved(CommentId, Result<CommentResponse, LemnuxError>),
+    OpenPost(PostId),
+    OpenCommunity(CommunityId),
+    Posts(posts::Message),
+    Markdown(markdown::Message),
+}
+
+impl Saved {
+    pub fn new(api: Arc<API>) -> (Self, Command<Message>) {
+        let mut page = Self {
+            api,
+            view: SavedView::Posts,
+            page: 1,
+            posts: Vec::new(),
+            comments: Vec::new(),
+            selected_posts: HashSet::new(),
+            selected_comments: HashSet::new(),
+            voted: None,
+            loading: false,
+            unsaving: 0,
+            markdown: Markdown::new(),
+            error: None,
+            action_error: None,
+        };
+        let cmd = page.fetch();
+
+        (page, cmd)
+    }
+
+    fn fetch(&mut self) -> Command<Message> {
+        self.error = None;
+        self.selected_posts.clear();
+        self.selected_comments.clear();
+
+        if self.api.username.is_none() {
+            return Command::none();
+        }
+
+        match self.view {
+            SavedView::Posts => {
+                self.loading = true;
+
+                let query = PostsList {
+                    page: Some(self.page),
+                    ..PostsList::personal(PersonalListing::Saved, PAGE_SIZE)
+                };
+
+                Command::perform(get_posts(self.api.clone(), query), Message::PostsLoaded)
+            }
+            SavedView::Comments => {
+                self.loading = true;
+
+                Command::perform(
+                    list_saved_comments(self.api.clone(), self.page, PAGE_SIZE),
+                    Message::CommentsLoaded,
+                )
+            }
+            SavedView::Upvoted | SavedView::Downvoted => {
+                let listing = if self.view == SavedView::Upvoted {
+                    PersonalListing::Upvoted
+                } else {
+                    PersonalListing::Downvoted
+                };
+                let mut voted =
+                    Posts::new(self.api.clone(), PostsList::personal(listing, PAGE_SIZE));
+                let cmd = voted.refresh().map(Message::Posts);
+
+                self.voted = Some(voted);
+                cmd
+            }
+        }
+    }
+
+    fn has_more(&self) -> bool {
+        match self.view {
+            SavedView::Posts => self.posts.len() as i64 >= PAGE_SIZE,
+            SavedView::Comments => self.comments.len() as i64 >= PAGE_SIZE,
+            SavedView::Upvoted | SavedView::Downvoted => false,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Retry => self.fetch(),
+            Message::SetView(view) => {
+                self.view = view;
+                self.page = 1;
+                self.voted = None;
+                self.fetch()
+            }
+            Message::NextPage => {
+                self.page += 1;
+                self.fetch()
+            }
+            Message::PreviousPage => {
+                self.page = (self.page - 1).max(1);
+                self.fetch()
+            }
+            Message::PostsLoaded(result) => {
+                self.loading = false;
+
+                match result {
+                    Ok(response) => self.posts = response.posts,
+                    Err(error) => self.error = Some(error),
+                }
+
+                Command::none()
+            }
+            Message::CommentsLoaded(result) => {
+                self.loading = false;
+
+                match result {
+                    Ok(response) => {
+                        let cmds: Vec<_> = response
+                            .comments
+                            .iter()
+                            .map(|view| {
+                                self.markdown
+                                    .load_images(self.api.clone(), &view.comment.content)
+                                    .map(Message::Markdown)
+                            })
+                            .collect();
+
+                        self.comments = response.comments;
+                        return Command::batch(cmds);
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+
+                Command::none()
+            }
+            Message::SelectPost(id, selected) => {
+                if selected {
+                    self.selected_posts.insert(id);
+                } else {
+                    self.selected_posts.remove(&id);
+                }
+
+                Command::none()
+            }
+            Message::SelectComment(id, selected) => {
+                if selected {
+                    self.selected_comments.insert(id);
+                } else {
+                    self.selected_comments.remove(&id);
+                }
+
+                Command::none()
+            }
+            Message::SelectAll(selected) => {
+                self.selected_posts.clear();
+                self.selected_comments.clear();
+
+                if selected {
+                    match self.view {
+                        SavedView::Posts => self
+                            .selected_posts
+                            .extend(self.posts.iter().map(|view| view.post.id)),
+                        SavedView::Comments => self
+                            .selected_comments
+                            .extend(self.comments.iter().map(|view| view.comment.id)),
+                        SavedView::Upvoted | SavedView::Downvoted => {}
+                    }
+                }
+
+                Command::none()
+            }
+            Message::UnsaveSelected => {
+                self.action_error = None;
+
+                let posts = self.selected_posts.iter().map(|id| {
+                    let id = *id;
+
+                    Command::perform(save_post(self.api.clone(), id, false), move |ret| {
+                        Message::PostUnsaved(id, ret)
+                    })
+                });
+                let comments = self.selected_comments.iter().map(|id| {
+                    let id = *id;
+
+                    Command::perform(save_comment(self.api.clone(), id, false), move |ret| {
+                        Message::CommentUnsaved(id, ret)
+                    })
+                });
+                let cmds: Vec<_> = posts.chain(comments).collect();
+
+                self.unsaving += cmds.len();
+
+                Command::batch(cmds)
+            }
+            Message::PostUnsaved(id, result) => {
+                self.unsaving = self.unsaving.saturating_sub(1);
+
+                match result {
+                    Ok(_) => {
+                        self.selected_posts.remove(&id);
+                        self.posts.retain(|view| view.post.id != id);
+                    }
+                    Err(error) => self.action_error = Some(error),
+                }
+
+                Command::none()
+            }
+            Message::CommentUnsaved(id, result) => {
+                self.unsaving = self.unsaving.saturating_sub(1);
+
+                match result {
+                    Ok(_) => {
+                        self.selected_comments.remove(&id);
+                        self.comments.retain(|view| view.comment.id != id);
+                    }
+                    Err(error) => self.action_error = Some(error),
+                }
+
+                Command::none()
+            }
+            Message::OpenPost(_) | Message::OpenCommunity(_) => Command::none(),
+            Message::Posts(message) => match &mut self.voted {
+                Some(voted) => voted.update(message).map(Message::Posts),
+                None => Command::none(),
+            },
+            Message::Markdown(message) => {
+                self.markdown.update(message);
+                Command::none()
+            }
+        }
+    }
+
+    fn view_posts(&self) -> Element<'_, Message> {
+        let mut col = column!().spacing(15);
+
+        for view in &self.posts {
+            let id = view.post.id;
+
+            col = col.push(
+                row!(
+                    checkbox("", self.selected_posts.contains(&id))
+                        .on_toggle(move |selected| Message::SelectPost(id, selected)),
+                    button(text(&view.post.name))
+                        .style(theme::Button::Secondary)
+                        .width(Length::Fill)
+                        .on_press(Message::OpenPost(id)),
+                    button(badge(text(&view.community.title)).style(BadgeStyles::Success))
+                        .style(theme::Button::Text)
+                        .padding(0)
+                        .on_press(Message::OpenCommunity(view.community.id)),
+                    badge(text(format!("{} points", view.counts.score)))
+                        .style(BadgeStyles::Secondary),
+                )
+                .spacing(10)
+                .align_items(Alignment::Center),
+            );
+        }
+
+        col.into()
+    }
+
+    fn view_comments(&self) -> Element<'_, Message> {
+        let mut col = column!().spacing(15);
+
+        for view in &self.comments {
+            let id = view.comment.id;
+
+            col = col.push(
+                column!(
+                    row!(
+                        checkbox("", self.selected_comments.contains(&id))
+                            .on_toggle(move |selected| Message::SelectComment(id, selected)),
+                        badge(text(&view.creator.name)).style(BadgeStyles::Primary),
+                        button(text(format!("in {}", view.post.name)))
+                            .style(theme::Button::Text)
+                            .on_press(Message::OpenPost(view.post.id)),
+                    )
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                    self.markdown
+                        .view(&view.comment.content)
+                        .map(Message::Markdown),
+                    horizontal_rule(1),
+                )
+                .spacing(8),
+            );
+        }
+
+        col.into()
+    }
+
+    /// The listing of voted posts, once one of those tabs was opened.
+    pub fn voted_mut(&mut self) -> Option<&mut Posts> {
+        self.voted.as_mut()
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        match &self.voted {
+            Some(voted) => voted.subscription().map(Message::Posts),
+            None => Subscription::none(),
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut col = column!().spacing(20).padding(30);
+
+        if self.api.username.is_none() {
+            col = col.push(text("Log in from the settings to see what you saved."));
+
+            return Container::new(col).into();
+        }
+
+        let mut controls = row!(
+            text("Show"),
+            pick_list(&SavedView::ALL[..], Some(self.view), Message::SetView),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        let selected = self.selected_posts.len() + self.selected_comments.len();
+
+        if matches!(self.view, SavedView::Posts | SavedView::Comments) {
+            let shown = match self.view {
+                SavedView::Posts => self.posts.len(),
+                _ => self.comments.len(),
+            };
+            let label = if self.unsaving > 0 {
+                String::from("Unsaving...")
+            } else {
+                format!("Unsave selected ({selected})")
+            };
+
+            controls = controls
+                .push(
+                    checkbox("Select all", shown > 0 && selected == shown)
+                        .on_toggle(Message::SelectAll),
+                )
+                .push(
+                    button(text(label))
+                        .style(theme::Button::Destructive)
+                        .on_press_maybe(
+                            (selected > 0 && self.unsaving == 0).then_some(Message::UnsaveSelected),
+                        ),
+                );
+        }
+
+        col = col.push(controls);
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(error, Some(Message::Retry)));
+        }
+
+        if let Some(error) = &self.action_error {
+            col = col.push(error_banner(error, None));
+        }
+
+        match self.view {
+            SavedView::Upvoted | SavedView::Downvoted => {
+                if let Some(voted) = &self.voted {
+                    return column!(col, voted.view().map(Message::Posts)).into();
+                }
+            }
+            _ if self.loading => col = col.push(text("Loading...")),
+            SavedView::Posts if self.posts.is_empty() => col = col.push(text("Nothing saved.")),
+            SavedView::Comments if self.comments.is_empty() => {
+                col = col.push(text("Nothing saved."))
+            }
+            SavedView::Posts => col = col.push(self.view_posts()),
+            SavedView::Comments => col = col.push(self.view_comments()),
+        }
+
+        col = col.push(
+            row!(
+                button("Previous").on_press_maybe((self.page > 1).then_some(Message::PreviousPage)),
+                text(format!("Page {}", self.page)),
+                button("Next").on_press_maybe(self.has_more().then_some(Message::NextPage)),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+        );
+
+        Container::new(Scrollable::new(col).width(Length::Fill)).into()
+    }
+}
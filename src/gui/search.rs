@@ -0,0 +1,349 @@
+use std::{fmt::Display, sync::Arc};
+
+use iced::{
+    theme,
+    widget::{
+        button, column, horizontal_rule, pick_list, row, text, text_input, Container, Scrollable,
+    },
+    Alignment, Command, Element, Length,
+};
+use iced_aw::{badge, BadgeStyles};
+use lemmy_api_common::{
+    lemmy_db_schema::{
+        newtypes::{CommunityId, PersonId, PostId},
+        source::person::Person,
+        ListingType, SearchType, SortType,
+    },
+    site::{Search as SearchParams, SearchResponse},
+};
+
+use super::{error_banner, posts::SORT_TYPES};
+use crate::api::{search, LemnuxError, API};
+
+const PAGE_SIZE: i64 = 20;
+
+const LISTING_TYPES: [ListingType; 3] = [
+    ListingType::All,
+    ListingType::Local,
+    ListingType::Subscribed,
+];
+
+/// `SearchType` can't be compared, which the pick list needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    All,
+    Comments,
+    Posts,
+    Communities,
+    Users,
+    Url,
+}
+
+impl SearchFilter {
+    const ALL: [SearchFilter; 6] = [
+        SearchFilter::All,
+        SearchFilter::Comments,
+        SearchFilter::Posts,
+        SearchFilter::Communities,
+        SearchFilter::Users,
+        SearchFilter::Url,
+    ];
+
+    fn to_search_type(self) -> SearchType {
+        match self {
+            SearchFilter::All => SearchType::All,
+            SearchFilter::Comments => SearchType::Comments,
+            SearchFilter::Posts => SearchType::Posts,
+            SearchFilter::Communities => SearchType::Communities,
+            SearchFilter::Users => SearchType::Users,
+            SearchFilter::Url => SearchType::Url,
+        }
+    }
+}
+
+impl Display for SearchFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_search_type())
+    }
+}
+
+#[derive(Debug)]
+pub struct Search {
+    api: Arc<API>,
+    query: String,
+    filter: SearchFilter,
+    listing_type: ListingType,
+    sort: SortType,
+    page: i64,
+    results: Option<SearchResponse>,
+    searching: bool,
+    error: Option<LemnuxError>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Query(String),
+    Submit,
+    Retry,
+    SetFilter(SearchFilter),
+    SetListingType(ListingType),
+    SetSort(SortType),
+    NextPage,
+    PreviousPage,
+    Loaded(Result<SearchResponse, LemnuxError>),
+    OpenPost(PostId),
+    OpenCommunity(CommunityId),
+    OpenPerson(PersonId),
+    /// Starts or continues a private conversation with the user.
+    SendMessage(Person),
+}
+
+impl Search {
+    pub fn new(api: Arc<API>) -> Self {
+        Self {
+            api,
+            query: String::new(),
+            filter: SearchFilter::All,
+            listing_type: ListingType::All,
+            sort: SortType::TopAll,
+            page: 1,
+            results: None,
+            searching: false,
+            error: None,
+        }
+    }
+
+    fn fetch(&mut self) -> Command<Message> {
+        if self.query.trim().is_empty() {
+            return Command::none();
+        }
+
+        let params = SearchParams {
+            q: self.query.trim().to_string(),
+            type_: Some(self.filter.to_search_type()),
+            sort: Some(self.sort),
+            listing_type: Some(self.listing_type),
+            page: Some(self.page),
+            limit: Some(PAGE_SIZE),
+            ..Default::default()
+        };
+
+        self.searching = true;
+        self.error = None;
+
+        Command::perform(search(self.api.clone(), params), Message::Loaded)
+    }
+
+    /// Starts over from the first page, for when the query or a filter changed.
+    fn restart(&mut self) -> Command<Message> {
+        self.page = 1;
+        self.fetch()
+    }
+
+    fn is_empty(results: &SearchResponse) -> bool {
+        results.posts.is_empty()
+            && results.comments.is_empty()
+            && results.communities.is_empty()
+            && results.users.is_empty()
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Query(query) => {
+                self.query = query;
+                Command::none()
+            }
+            Message::Submit => self.restart(),
+            Message::Retry => self.fetch(),
+            Message::SetFilter(filter) => {
+                self.filter = filter;
+                self.restart()
+            }
+            Message::SetListingType(listing_type) => {
+                self.listing_type = listing_type;
+                self.restart()
+            }
+            Message::SetSort(sort) => {
+                self.sort = sort;
+                self.restart()
+            }
+            Message::NextPage => {
+                self.page += 1;
+                self.fetch()
+            }
+            Message::PreviousPage => {
+                self.page = (self.page - 1).max(1);
+                self.fetch()
+            }
+            Message::Loaded(Ok(results)) => {
+                self.searching = false;
+                self.results = Some(results);
+                Command::none()
+            }
+            Message::Loaded(Err(error)) => {
+                self.searching = false;
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::OpenPost(_)
+            | Message::OpenCommunity(_)
+            | Message::OpenPerson(_)
+            | Message::SendMessage(_) => Command::none(),
+        }
+    }
+
+    fn view_results<'a>(&self, results: &'a SearchResponse) -> Element<'a, Message> {
+        let mut col = column!().spacing(15);
+
+        if !results.communities.is_empty() {
+            col = col.push(text("Communities").size(22));
+
+            for view in &results.communities {
+                col = col.push(
+                    row!(
+                        button(text(&view.community.title))
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::OpenCommunity(view.community.id)),
+                        badge(text(format!("{} subscribers", view.counts.subscribers)))
+                            .style(BadgeStyles::Info),
+                    )
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                );
+            }
+        }
+
+        if !results.users.is_empty() {
+            col = col.push(text("Users").size(22));
+
+            for view in &results.users {
+                let mut user_row = row!(
+                    button(badge(text(&view.person.name)).style(BadgeStyles::Primary))
+                        .style(theme::Button::Text)
+                        .padding(0)
+                        .on_press(Message::OpenPerson(view.person.id)),
+                    badge(text(format!(
+                        "{} posts, {} comments",
+                        view.counts.post_count, view.counts.comment_count
+                    )))
+                    .style(BadgeStyles::Info),
+                )
+                .spacing(10)
+                .align_items(Alignment::Center);
+
+                if self.api.username.is_some() && !self.api.is_me(&view.person) {
+                    user_row = user_row.push(
+                        button("Message")
+                            .style(theme::Button::Secondary)
+                            .on_press(Message::SendMessage(view.person.clone())),
+                    );
+                }
+
+                col = col.push(user_row);
+            }
+        }
+
+        if !results.posts.is_empty() {
+            col = col.push(text("Posts").size(22));
+
+            for view in &results.posts {
+                col = col.push(
+                    row!(
+                        button(text(&view.post.name))
+                            .style(theme::Button::Secondary)
+                            .width(Length::Fill)
+                            .on_press(Message::OpenPost(view.post.id)),
+                        badge(text(&view.community.title)).style(BadgeStyles::Success),
+                    )
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                );
+            }
+        }
+
+        if !results.comments.is_empty() {
+            col = col.push(text("Comments").size(22));
+
+            for view in &results.comments {
+                col = col.push(
+                    column!(
+                        row!(
+                            badge(text(&view.creator.name)).style(BadgeStyles::Primary),
+                            button(text(format!("in {}", view.post.name)))
+                                .style(theme::Button::Text)
+                                .on_press(Message::OpenPost(view.post.id)),
+                        )
+                        .spacing(10)
+                        .align_items(Alignment::Center),
+                        text(&view.comment.content),
+                        horizontal_rule(1),
+                    )
+                    .spacing(8),
+                );
+            }
+        }
+
+        col.into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let search_row = row!(
+            text_input("Search posts, comments, communities and users", &self.query)
+                .on_input(Message::Query)
+                .on_submit(Message::Submit),
+            button("Search").on_press(Message::Submit),
+        )
+        .spacing(10);
+
+        let filter_row = row!(
+            text("Type"),
+            pick_list(
+                &SearchFilter::ALL[..],
+                Some(self.filter),
+                Message::SetFilter
+            ),
+            text("Scope"),
+            pick_list(
+                &LISTING_TYPES[..],
+                Some(self.listing_type),
+                Message::SetListingType
+            ),
+            text("Sort"),
+            pick_list(&SORT_TYPES[..], Some(self.sort), Message::SetSort),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        let mut col = column!().spacing(20).padding(30);
+
+        col = col.push(search_row).push(filter_row);
+
+        if let Some(error) = &self.error {
+            col = col.push(error_banner(error, Some(Message::Retry)));
+        }
+
+        if self.searching {
+            col = col.push(text("Searching..."));
+        } else if let Some(results) = &self.results {
+            if Self::is_empty(results) {
+                col = col.push(text("No results"));
+            } else {
+                col = col.push(self.view_results(results));
+            }
+
+            let pagination = row!(
+                button("Previous Page")
+                    .on_press_maybe((self.page > 1).then_some(Message::PreviousPage)),
+                text(format!("Page {}", self.page)),
+                button("Next Page")
+                    .on_press_maybe((!Self::is_empty(results)).then_some(Message::NextPage)),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center);
+
+            col = col.push(pagination);
+        }
+
+        Container::new(Scrollable::new(col)).into()
+    }
+}
@@ -4,28 +4,117 @@
     clippy::to_string_in_format_args
 )]
 
-use std::fs;
+use std::sync::Arc;
 
 use iced::{
-    widget::{button, column, combo_box, combo_box::State, text, text_input, Container},
-    Command, Element, Length,
+    theme,
+    widget::{
+        button, column, combo_box, combo_box::State, horizontal_rule, pick_list, row, text,
+        text_input, Column, Container,
+    },
+    Alignment, Command, Element, Length,
 };
+use iced_aw::{badge, BadgeStyles};
 
+use super::{error_banner, message_banner};
 use crate::{
-    api::{login, Instance},
-    settings::{AppTheme, Preferences, User, JWT},
+    api::{image_cache, login, Instance, LemnuxError, API},
+    settings::{
+        with_credential_store, Account, Accounts, AppTheme, CachePreferences, CredentialStore,
+        Preferences, TitleAction, JWT,
+    },
 };
 
+const DISK_CACHE_SIZES: [Megabytes; 5] = [
+    Megabytes(100),
+    Megabytes(250),
+    Megabytes(500),
+    Megabytes(1000),
+    Megabytes(2000),
+];
+
+const MEMORY_CACHE_SIZES: [Megabytes; 4] =
+    [Megabytes(32), Megabytes(64), Megabytes(128), Megabytes(256)];
+
+const THUMBNAIL_WIDTHS: [Pixels; 5] = [
+    Pixels(320),
+    Pixels(480),
+    Pixels(640),
+    Pixels(800),
+    Pixels(1024),
+];
+
+/// A thumbnail width choice, shown with its unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pixels(u32);
+
+impl std::fmt::Display for Pixels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} px", self.0)
+    }
+}
+
+/// A cache size choice, shown with its unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Megabytes(u64);
+
+impl std::fmt::Display for Megabytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} MB", self.0)
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Words Lemmy's login error codes for people; anything else keeps the
+/// generic description.
+fn login_error_message(error: &LemnuxError) -> String {
+    let message = match error.lemmy_error() {
+        Some("incorrect_login") => "Wrong username, email or password.",
+        Some("missing_totp_token") => {
+            "This account uses two-factor authentication. Enter the code from your authenticator app."
+        }
+        Some("incorrect_totp_token") => "The two-factor code is wrong or has expired.",
+        Some("email_not_verified") => {
+            "Your email address isn't verified yet. Follow the link in the verification email first."
+        }
+        Some("registration_application_is_pending") => {
+            "Your registration application is still waiting for an admin to approve it."
+        }
+        Some("registration_denied") => "Your registration application was denied.",
+        Some("site_ban") | Some("person_is_banned_from_site") => "This account is banned from the instance.",
+        Some("deleted") => "This account has been deleted.",
+        _ => return error.to_string(),
+    };
+
+    message.to_string()
+}
+
 #[derive(Debug, Clone)]
 pub struct Settings {
-    instance: Option<Instance>,
+    api: Arc<API>,
     instances_to_search: State<Instance>,
     user_selected_instance: Option<Instance>,
     app_theme_chooser: State<AppTheme>,
     user_theme: AppTheme,
     username_field: String,
     password_field: String,
-    user: Option<User>,
+    totp_field: String,
+    /// The instance asked for a two-factor code on the last attempt.
+    totp_required: bool,
+    /// Outcome of a login that worked but gave no token.
+    notice: Option<String>,
+    accounts: Accounts,
+    cache: CachePreferences,
+    thumbnail_width: u32,
+    title_opens: TitleAction,
+    /// Bytes the image cache uses on disk and in memory, once measured.
+    cache_usage: Option<(u64, u64)>,
+    /// Why the last login failed, worded for people and offered again.
+    login_error: Option<LemnuxError>,
+    error: Option<LemnuxError>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,59 +125,110 @@ pub enum Message {
     SetTheme(AppTheme),
     Username(String),
     Password(String),
+    Totp(String),
     Login,
-    Logged(Option<JWT>),
+    Logged(Result<JWT, LemnuxError>),
+    /// Signs out of the active account and forgets it.
     Logout,
+    SwitchAccount(Option<usize>),
+    RemoveAccount(usize),
+    /// The account list was saved after a change, or couldn't be.
+    AccountsChanged(Result<Accounts, LemnuxError>),
+    OpenRegister,
+    SetTitleOpens(TitleAction),
+    SetThumbnailWidth(Pixels),
+    SetDiskCache(Megabytes),
+    SetMemoryCache(Megabytes),
+    CacheUsage(Result<(u64, u64), LemnuxError>),
+    ClearCache,
 }
 
 impl Settings {
-    pub fn new(instances: Vec<Instance>) -> Self {
-        let user = if let Ok(config) = confy::load::<crate::settings::Settings>("lemnux", "user") {
-            config.user
-        } else {
-            None
-        };
-
+    pub fn new(api: Arc<API>, instances: Vec<Instance>, accounts: Accounts) -> Self {
         let themes = AppTheme::to_vec();
         let app_theme_chooser = State::new(themes.clone());
-        let user_theme = if let Ok(config) =
-            confy::load::<crate::settings::Preferences>("lemnux", "preferences")
-        {
-            config.theme
-        } else {
-            themes[0].clone()
+        let (preferences, error) = match Preferences::load() {
+            Ok(preferences) => (preferences, None),
+            Err(error) => (Preferences::default(), Some(error)),
         };
-
+        let user_theme = preferences.theme.clone();
         Self {
-            instance: None,
+            api,
             instances_to_search: State::new(instances),
             user_selected_instance: None,
             app_theme_chooser,
             user_theme,
             username_field: String::new(),
             password_field: String::new(),
-            user,
+            totp_field: String::new(),
+            totp_required: false,
+            notice: None,
+            accounts,
+            cache: preferences.cache,
+            thumbnail_width: preferences.thumbnail_width,
+            title_opens: preferences.title_opens,
+            cache_usage: None,
+            login_error: None,
+            error,
         }
     }
 
-    pub fn update(&mut self, message: Message) -> Command<Message> {
-        match message {
-            Message::NotFound => Command::none(),
-            Message::SetInstance(inst) => {
-                self.instance = Some(inst);
+    /// Measures the image cache for the usage shown next to "Clear cache".
+    pub fn measure_cache() -> Command<Message> {
+        Command::perform(image_cache::usage(), Message::CacheUsage)
+    }
 
-                if self.instance.is_some() {
-                    let instance = self.instance.as_ref().unwrap();
+    fn set_cache(&mut self, cache: CachePreferences) -> Command<Message> {
+        self.cache = cache;
+        self.error = Preferences::load()
+            .and_then(|mut prefs| prefs.set_cache(cache))
+            .err();
+        image_cache::set_limits(&cache);
 
-                    let settings = crate::settings::Settings {
-                        instance: Some(instance.clone()),
-                        ..Default::default()
-                    };
+        Self::measure_cache()
+    }
 
-                    confy::store("lemnux", "instance", settings).unwrap();
-                }
+    pub fn with_error(mut self, error: LemnuxError) -> Self {
+        self.error = Some(error);
+        self
+    }
 
-                Command::none()
+    pub fn set_api(&mut self, api: Arc<API>) {
+        self.api = api;
+    }
+
+    /// Applies `change` to a copy of the account list and saves it, away
+    /// from the UI thread since tokens go through the keyring.
+    fn change_accounts(
+        &self,
+        change: impl FnOnce(&mut Accounts, &dyn CredentialStore) -> Result<(), LemnuxError>
+            + Send
+            + 'static,
+    ) -> Command<Message> {
+        let mut accounts = self.accounts.clone();
+
+        Command::perform(
+            with_credential_store(move |store| {
+                change(&mut accounts, store)?;
+                accounts.store()?;
+
+                Ok(accounts)
+            }),
+            Message::AccountsChanged,
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::NotFound => Command::none(),
+            Message::SetInstance(inst) => {
+                // Picking an instance browses it anonymously; logging in from
+                // here then adds an account on it.
+                self.change_accounts(|accounts, _| {
+                    accounts.switch(None);
+                    accounts.instance = Some(inst.domain);
+                    Ok(())
+                })
             }
             Message::UserSelectedInstance(inst) => {
                 self.user_selected_instance = Some(inst);
@@ -97,8 +237,9 @@ impl Settings {
             }
             Message::SetTheme(theme) => {
                 self.user_theme = theme.clone();
-                let prefs = Preferences { theme };
-                confy::store("lemnux", "preferences", prefs).unwrap();
+                self.error = Preferences::load()
+                    .and_then(|mut prefs| prefs.set_theme(theme))
+                    .err();
 
                 Command::none()
             }
@@ -110,13 +251,24 @@ impl Settings {
                 self.password_field = pwd;
                 Command::none()
             }
+            Message::Totp(code) => {
+                self.totp_field = code;
+                Command::none()
+            }
             Message::Login => {
                 if !self.username_field.is_empty() && !self.password_field.is_empty() {
+                    let totp = Some(self.totp_field.trim().to_string())
+                        .filter(|code| self.totp_required && !code.is_empty());
+
+                    self.notice = None;
+                    self.login_error = None;
+
                     Command::perform(
                         login(
+                            self.api.clone(),
                             self.username_field.clone().into(),
                             self.password_field.clone().into(),
-                            None,
+                            totp,
                         ),
                         Message::Logged,
                     )
@@ -124,35 +276,203 @@ impl Settings {
                     Command::none()
                 }
             }
-            Message::Logged(jwt) => {
-                let settings = crate::settings::Settings {
-                    user: Some(User::new(self.username_field.clone().into(), jwt, true)),
-                    ..Default::default()
-                };
+            Message::Logged(Err(error)) => {
+                match error.lemmy_error() {
+                    Some("missing_totp_token") => self.totp_required = true,
+                    Some("incorrect_totp_token") => self.totp_field.clear(),
+                    _ => {}
+                }
 
-                confy::store("lemnux", "user", settings).unwrap();
+                self.login_error = Some(error);
 
                 Command::none()
             }
-            Message::Logout => {
-                let config_path = confy::get_configuration_file_path("lemnux", "user");
+            Message::Logged(Ok(jwt)) => {
+                self.login_error = None;
+
+                let Some(token) = jwt.token else {
+                    self.notice = Some(String::from(if jwt.verify_email_sent {
+                        "Check your inbox and follow the verification link, then log in again."
+                    } else if jwt.registration_created {
+                        "Your registration application was sent. You can log in once an admin approves it."
+                    } else {
+                        "The instance accepted the login but sent no token."
+                    }));
+
+                    return Command::none();
+                };
 
-                if let Ok(file) = config_path {
-                    if file.exists() {
-                        fs::remove_file(file).unwrap();
-                    }
+                // The login went to whatever instance the client points at.
+                let account =
+                    Account::new(self.username_field.clone().into(), self.api.domain.clone());
+
+                self.username_field.clear();
+                self.password_field.clear();
+                self.totp_field.clear();
+                self.totp_required = false;
+
+                self.change_accounts(|accounts, store| accounts.add(store, account, Some(token)))
+            }
+            Message::Logout => match self.accounts.active {
+                Some(index) => {
+                    self.change_accounts(move |accounts, store| accounts.remove(store, index))
                 }
+                None => Command::none(),
+            },
+            Message::SwitchAccount(index) => self.change_accounts(move |accounts, _| {
+                accounts.switch(index);
+                Ok(())
+            }),
+            Message::OpenRegister => Command::none(),
+            Message::RemoveAccount(index) => {
+                self.change_accounts(move |accounts, store| accounts.remove(store, index))
+            }
+            Message::AccountsChanged(Ok(accounts)) => {
+                self.accounts = accounts;
+                self.error = None;
+
+                Command::none()
+            }
+            Message::AccountsChanged(Err(error)) => {
+                self.error = Some(error);
+
+                Command::none()
+            }
+            Message::SetTitleOpens(action) => {
+                self.title_opens = action;
+                self.error = Preferences::load()
+                    .and_then(|mut prefs| prefs.set_title_opens(action))
+                    .err();
+
+                Command::none()
+            }
+            Message::SetThumbnailWidth(Pixels(width)) => {
+                self.thumbnail_width = width;
+                self.error = Preferences::load()
+                    .and_then(|mut prefs| prefs.set_thumbnail_width(width))
+                    .err();
+
+                Command::none()
+            }
+            Message::SetDiskCache(Megabytes(disk_mb)) => self.set_cache(CachePreferences {
+                disk_mb,
+                ..self.cache
+            }),
+            Message::SetMemoryCache(Megabytes(memory_mb)) => self.set_cache(CachePreferences {
+                memory_mb,
+                ..self.cache
+            }),
+            Message::CacheUsage(Ok(usage)) => {
+                self.cache_usage = Some(usage);
 
-                self.instance = None;
-                self.user = None;
+                Command::none()
+            }
+            Message::CacheUsage(Err(error)) => {
+                self.error = Some(error);
 
                 Command::none()
             }
+            Message::ClearCache => Command::perform(
+                async {
+                    image_cache::clear().await?;
+                    image_cache::usage().await
+                },
+                Message::CacheUsage,
+            ),
+        }
+    }
+
+    fn view_cache(&self) -> Element<'_, Message> {
+        let usage = match self.cache_usage {
+            Some((disk, memory)) => format!(
+                "Using {} on disk and {} in memory",
+                format_size(disk),
+                format_size(memory)
+            ),
+            None => String::from("Measuring..."),
+        };
+
+        column!(
+            text("Images").size(22),
+            row!(
+                text("Thumbnail width").width(Length::Fill),
+                pick_list(
+                    &THUMBNAIL_WIDTHS[..],
+                    Some(Pixels(self.thumbnail_width)),
+                    Message::SetThumbnailWidth
+                ),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+            row!(
+                text("Cache on disk").width(Length::Fill),
+                pick_list(
+                    &DISK_CACHE_SIZES[..],
+                    Some(Megabytes(self.cache.disk_mb)),
+                    Message::SetDiskCache
+                ),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+            row!(
+                text("Cache in memory").width(Length::Fill),
+                pick_list(
+                    &MEMORY_CACHE_SIZES[..],
+                    Some(Megabytes(self.cache.memory_mb)),
+                    Message::SetMemoryCache
+                ),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+            row!(
+                text(usage).width(Length::Fill),
+                button("Clear cache")
+                    .style(theme::Button::Destructive)
+                    .on_press(Message::ClearCache),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+        )
+        .spacing(8)
+        .into()
+    }
+
+    fn view_accounts(&self) -> Element<'_, Message> {
+        let mut col = Column::new().spacing(8);
+
+        for (index, account) in self.accounts.accounts.iter().enumerate() {
+            let state: Element<'_, Message> = if self.accounts.active == Some(index) {
+                badge(text("Active")).style(BadgeStyles::Success).into()
+            } else {
+                button("Use")
+                    .on_press(Message::SwitchAccount(Some(index)))
+                    .into()
+            };
+
+            col = col.push(
+                row!(
+                    text(account.label()).width(Length::Fill),
+                    state,
+                    button("Remove")
+                        .style(theme::Button::Destructive)
+                        .on_press(Message::RemoveAccount(index)),
+                )
+                .spacing(10)
+                .align_items(Alignment::Center),
+            );
         }
+
+        let anonymous = if self.accounts.active.is_some() {
+            button("Browse anonymously").on_press(Message::SwitchAccount(None))
+        } else {
+            button("Browsing anonymously")
+        };
+
+        col.push(anonymous).into()
     }
 
-    pub fn view(&self) -> Element<Message> {
-        let mut content = column!();
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut content = column!().spacing(15);
 
         content = content.push(combo_box(
             &self.app_theme_chooser,
@@ -161,6 +481,26 @@ impl Settings {
             Message::SetTheme,
         ));
 
+        content = content
+            .push(horizontal_rule(1))
+            .push(text("Accounts").size(22))
+            .push(self.view_accounts());
+
+        content = content.push(
+            row!(
+                text("Clicking a post title opens").width(Length::Fill),
+                pick_list(
+                    &TitleAction::ALL[..],
+                    Some(self.title_opens),
+                    Message::SetTitleOpens
+                ),
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+        );
+
+        content = content.push(horizontal_rule(1)).push(self.view_cache());
+
         content = content.push(
             combo_box(
                 &self.instances_to_search,
@@ -171,32 +511,95 @@ impl Settings {
             .on_option_hovered(Message::UserSelectedInstance),
         );
 
-        if self.instance.is_some() || self.user.is_some() {
-            let username_field =
-                text_input("Username", &self.username_field).on_input(Message::Username);
-
-            let password_field = text_input("Password", &self.password_field)
-                .secure(true)
-                .on_input(Message::Password);
-
-            let login_btn = button("Log in")
-                .on_press(Message::Login)
-                .width(Length::Fill);
-
-            let col = if self.user.is_some() && self.user.as_ref().unwrap().is_logged {
-                let welcome_message = text(format!(
-                    "Welcome, {}",
-                    self.user.as_ref().unwrap().username.to_string()
-                ));
-                let logout_btn = button("Logout").on_press(Message::Logout);
-                column!(welcome_message, logout_btn)
-            } else {
-                column!(username_field, password_field, login_btn).spacing(8)
-            };
+        if let Some(account) = self.accounts.active() {
+            let welcome_message = text(format!("Welcome, {}", account.label()));
+            let logout_btn = button("Logout").on_press(Message::Logout);
+
+            content = content.push(column!(welcome_message, logout_btn).spacing(8));
+        }
+
+        let username_field =
+            text_input("Username", &self.username_field).on_input(Message::Username);
+
+        let password_field = text_input("Password", &self.password_field)
+            .secure(true)
+            .on_input(Message::Password);
+
+        let login_btn = button("Log in")
+            .on_press(Message::Login)
+            .width(Length::Fill);
+
+        let mut login_form = column!(
+            text(format!("Add an account on {}", self.api.domain)),
+            username_field,
+            password_field,
+        )
+        .spacing(8);
 
-            content = content.push(col);
+        if self.totp_required {
+            login_form = login_form.push(
+                text_input("Two-factor code", &self.totp_field)
+                    .on_input(Message::Totp)
+                    .on_submit(Message::Login),
+            );
         }
 
-        Container::new(content).into()
+        let register_btn = button("Create an account")
+            .style(theme::Button::Secondary)
+            .on_press(Message::OpenRegister)
+            .width(Length::Fill);
+
+        content = content.push(login_form.push(login_btn).push(register_btn));
+
+        if let Some(notice) = &self.notice {
+            content = content.push(badge(text(notice)).style(BadgeStyles::Info));
+        }
+
+        if let Some(error) = &self.login_error {
+            let retry = (!self.username_field.is_empty()).then_some(Message::Login);
+
+            content = content.push(message_banner(login_error_message(error), retry));
+        }
+
+        if let Some(error) = &self.error {
+            content = content.push(error_banner(error, None));
+        }
+
+        Container::new(content).padding(30).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_codes_are_worded() {
+        let error = LemnuxError::Http {
+            status: 400,
+            error: Some(String::from("incorrect_totp_token")),
+        };
+
+        assert_eq!(
+            login_error_message(&error),
+            "The two-factor code is wrong or has expired."
+        );
+    }
+
+    #[test]
+    fn rejected_logins_are_worded() {
+        let error = LemnuxError::Auth(String::from("incorrect_login"));
+
+        assert_eq!(
+            login_error_message(&error),
+            "Wrong username, email or password."
+        );
+    }
+
+    #[test]
+    fn other_errors_fall_back_to_the_error() {
+        let error = LemnuxError::Transport(String::from("connection reset"));
+
+        assert_eq!(login_error_message(&error), error.to_string());
     }
 }
@@ -1,10 +1,18 @@
 use gui::App;
-use iced::{Application, Settings};
+use iced::{window, Application, Settings};
 
 pub mod api;
 pub mod gui;
 pub mod settings;
+pub mod store;
 
 pub fn main() -> iced::Result {
-    App::run(Settings::default())
+    // Closing is handled by the app, which writes out the drafts first.
+    App::run(Settings {
+        window: window::Settings {
+            exit_on_close_request: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
 }
@@ -1,8 +1,25 @@
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
 
-use crate::api::Instance;
+use crate::api::{Instance, LemnuxError};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use directories::ProjectDirs;
 use iced::Theme;
-use lemmy_api_common::sensitive::Sensitive;
+use lemmy_api_common::{
+    lemmy_db_schema::{ListingType, SortType},
+    sensitive::Sensitive,
+};
+use secret_service::{blocking::SecretService, EncryptionType};
 use serde_derive::{Deserialize, Serialize};
 
 pub const LEMNUX_UA: &str = "Lemnux v0.1.0";
@@ -114,26 +131,733 @@ impl User {
     }
 }
 
+/// `$XDG_CACHE_HOME/lemnux`, for files that can be fetched again.
+pub fn cache_dir() -> Result<PathBuf, LemnuxError> {
+    ProjectDirs::from("", "", "lemnux")
+        .map(|dirs| dirs.cache_dir().to_path_buf())
+        .ok_or_else(|| LemnuxError::Config(String::from("no home directory")))
+}
+
+/// `$XDG_DATA_HOME/lemnux`, for files that cannot be fetched again.
+pub fn data_dir() -> Result<PathBuf, LemnuxError> {
+    ProjectDirs::from("", "", "lemnux")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .ok_or_else(|| LemnuxError::Config(String::from("no home directory")))
+}
+
+/// Where account tokens are kept. The confy `accounts` file only records
+/// the key each token was stored under.
+pub trait CredentialStore: std::fmt::Debug + Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Sensitive<String>>, LemnuxError>;
+    fn set(&self, key: &str, secret: &Sensitive<String>) -> Result<(), LemnuxError>;
+    fn delete(&self, key: &str) -> Result<(), LemnuxError>;
+
+    /// Tokens outlive the process. Stores that don't are never handed
+    /// tokens from older versions, which would be lost with them.
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
+
+/// Picks the store named by `LEMNUX_CREDENTIAL_STORE` (`secret-service`,
+/// `file` or `mock`). Without it the Secret Service is used when a provider
+/// answers on D-Bus, and the encrypted file otherwise.
+///
+/// The store is picked on the first call and shared afterwards. Its methods
+/// may block on D-Bus, so the app reaches it through
+/// [`with_credential_store`].
+pub fn credential_store() -> Result<Arc<dyn CredentialStore>, LemnuxError> {
+    static STORE: OnceLock<Result<Arc<dyn CredentialStore>, LemnuxError>> = OnceLock::new();
+
+    STORE
+        .get_or_init(
+            || match std::env::var("LEMNUX_CREDENTIAL_STORE").as_deref() {
+                Ok("mock") => Ok(Arc::new(MockStore::default())),
+                Ok("file") => Ok(Arc::new(EncryptedFileStore::new()?)),
+                Ok("secret-service") => Ok(Arc::new(SecretServiceStore::connect()?)),
+                _ => match SecretServiceStore::connect() {
+                    Ok(store) => Ok(Arc::new(store)),
+                    Err(_) => Ok(Arc::new(EncryptedFileStore::new()?)),
+                },
+            },
+        )
+        .clone()
+}
+
+/// Runs `f` with the [`credential_store`] on tokio's blocking pool, keeping
+/// keyring round trips off the UI thread.
+pub async fn with_credential_store<T: Send + 'static>(
+    f: impl FnOnce(&dyn CredentialStore) -> Result<T, LemnuxError> + Send + 'static,
+) -> Result<T, LemnuxError> {
+    tokio::task::spawn_blocking(move || f(&*credential_store()?))
+        .await
+        .map_err(|err| LemnuxError::Secret(err.to_string()))?
+}
+
+/// Tokens kept by the desktop keyring (GNOME Keyring, KWallet...) over the
+/// freedesktop Secret Service D-Bus API.
+#[derive(Debug)]
+pub struct SecretServiceStore;
+
+impl SecretServiceStore {
+    /// Fails when no Secret Service provider is running.
+    pub fn connect() -> Result<Self, LemnuxError> {
+        SecretService::connect(EncryptionType::Dh)?;
+
+        Ok(Self)
+    }
+
+    fn attributes(key: &str) -> HashMap<&str, &str> {
+        HashMap::from([("application", "lemnux"), ("account", key)])
+    }
+}
+
+impl CredentialStore for SecretServiceStore {
+    fn get(&self, key: &str) -> Result<Option<Sensitive<String>>, LemnuxError> {
+        let service = SecretService::connect(EncryptionType::Dh)?;
+        let items = service.search_items(Self::attributes(key))?;
+        let Some(item) = items.unlocked.first().or(items.locked.first()) else {
+            return Ok(None);
+        };
+
+        item.ensure_unlocked()?;
+
+        let secret = String::from_utf8(item.get_secret()?)
+            .map_err(|_| LemnuxError::Secret(String::from("stored token is not UTF-8")))?;
+
+        Ok(Some(secret.into()))
+    }
+
+    fn set(&self, key: &str, secret: &Sensitive<String>) -> Result<(), LemnuxError> {
+        let service = SecretService::connect(EncryptionType::Dh)?;
+        let collection = service.get_default_collection()?;
+
+        collection.ensure_unlocked()?;
+        collection.create_item(
+            &format!("Lemnux token for {}", key),
+            Self::attributes(key),
+            secret.as_bytes(),
+            true,
+            "text/plain",
+        )?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), LemnuxError> {
+        let service = SecretService::connect(EncryptionType::Dh)?;
+        let items = service.search_items(Self::attributes(key))?;
+
+        for item in items.unlocked.iter().chain(&items.locked) {
+            item.delete()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fallback for desktops without a keyring: tokens are encrypted with a
+/// random key kept in a separate, owner-only file next to the config.
+///
+/// This keeps tokens out of the config files people copy around, but anyone
+/// who can read both files can decrypt them.
+#[derive(Debug)]
+pub struct EncryptedFileStore {
+    dir: PathBuf,
+}
+
+impl EncryptedFileStore {
+    const NONCE_LEN: usize = 12;
+
+    pub fn new() -> Result<Self, LemnuxError> {
+        let path = confy::get_configuration_file_path("lemnux", "secrets")?;
+        let dir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| LemnuxError::Secret(String::from("no configuration directory")))?;
+
+        Ok(Self { dir })
+    }
+
+    fn io_error(error: std::io::Error) -> LemnuxError {
+        LemnuxError::Secret(error.to_string())
+    }
+
+    /// Writes `contents` readable by the owner only.
+    fn write_private(path: &Path, contents: &[u8]) -> Result<(), LemnuxError> {
+        fs::create_dir_all(path.parent().unwrap_or(path)).map_err(Self::io_error)?;
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(Self::io_error)?;
+
+        file.write_all(contents).map_err(Self::io_error)
+    }
+
+    fn cipher(&self) -> Result<ChaCha20Poly1305, LemnuxError> {
+        let path = self.dir.join("secrets.key");
+
+        let key = match fs::read(&path) {
+            Ok(key) if key.len() == 32 => Key::clone_from_slice(&key),
+            Ok(_) => return Err(LemnuxError::Secret(String::from("malformed key file"))),
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                Self::write_private(&path, &key)?;
+                key
+            }
+            Err(error) => return Err(Self::io_error(error)),
+        };
+
+        Ok(ChaCha20Poly1305::new(&key))
+    }
+
+    /// Secrets are stored as `key\tsecret` lines, encrypted as one blob
+    /// prefixed by its nonce.
+    fn read(&self) -> Result<HashMap<String, String>, LemnuxError> {
+        let data = match fs::read(self.dir.join("secrets.bin")) {
+            Ok(data) => data,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(error) => return Err(Self::io_error(error)),
+        };
+
+        if data.len() < Self::NONCE_LEN {
+            return Err(LemnuxError::Secret(String::from("malformed secrets file")));
+        }
+
+        let (nonce, ciphertext) = data.split_at(Self::NONCE_LEN);
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| LemnuxError::Secret(String::from("secrets file can't be decrypted")))?;
+        let plaintext = String::from_utf8(plaintext)
+            .map_err(|_| LemnuxError::Secret(String::from("malformed secrets file")))?;
+
+        Ok(plaintext
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(key, secret)| (key.to_string(), secret.to_string()))
+            .collect())
+    }
+
+    fn write(&self, secrets: &HashMap<String, String>) -> Result<(), LemnuxError> {
+        let plaintext: String = secrets
+            .iter()
+            .map(|(key, secret)| format!("{}\t{}\n", key, secret))
+            .collect();
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()?
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| LemnuxError::Secret(String::from("token can't be encrypted")))?;
+
+        Self::write_private(
+            &self.dir.join("secrets.bin"),
+            &[&nonce[..], &ciphertext].concat(),
+        )
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn get(&self, key: &str) -> Result<Option<Sensitive<String>>, LemnuxError> {
+        Ok(self.read()?.remove(key).map(Sensitive::from))
+    }
+
+    fn set(&self, key: &str, secret: &Sensitive<String>) -> Result<(), LemnuxError> {
+        let mut secrets = self.read()?;
+        secrets.insert(key.to_string(), secret.to_string());
+
+        self.write(&secrets)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), LemnuxError> {
+        let mut secrets = self.read()?;
+
+        if secrets.remove(key).is_some() {
+            self.write(&secrets)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// In-memory store for trying the account flows without touching a real
+/// keyring. Selected with `LEMNUX_CREDENTIAL_STORE=mock`.
+#[derive(Debug, Default)]
+pub struct MockStore {
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl CredentialStore for MockStore {
+    fn get(&self, key: &str) -> Result<Option<Sensitive<String>>, LemnuxError> {
+        let secrets = self.secrets.lock().unwrap();
+
+        Ok(secrets.get(key).cloned().map(Sensitive::from))
+    }
+
+    fn set(&self, key: &str, secret: &Sensitive<String>) -> Result<(), LemnuxError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        secrets.insert(key.to_string(), secret.to_string());
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), LemnuxError> {
+        self.secrets.lock().unwrap().remove(key);
+
+        Ok(())
+    }
+
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+/// A login on one instance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub username: Sensitive<String>,
+    /// Domain of the instance the account lives on.
+    pub instance: String,
+    /// Key of the token in the [`CredentialStore`].
+    #[serde(default)]
+    pub token_ref: Option<String>,
+    /// Plaintext token written by earlier versions. Moved into the
+    /// credential store on load and never written back.
+    #[serde(default, skip_serializing)]
+    jwt: Option<JWT>,
+}
+
+impl Account {
+    pub fn new(username: Sensitive<String>, instance: String) -> Self {
+        Self {
+            username,
+            instance,
+            token_ref: None,
+            jwt: None,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{}@{}", &*self.username, self.instance)
+    }
+
+    pub fn token(
+        &self,
+        store: &dyn CredentialStore,
+    ) -> Result<Option<Sensitive<String>>, LemnuxError> {
+        match &self.token_ref {
+            Some(key) => store.get(key),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Every account the user logged into and which one is in use, stored in
+/// the confy `accounts` file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Accounts {
+    pub accounts: Vec<Account>,
+    /// Index into `accounts`; `None` browses anonymously.
+    pub active: Option<usize>,
+    /// Instance browsed while no account is active.
+    pub instance: Option<String>,
+}
+
+impl Accounts {
+    /// Loads the account list, converting the single `user` and `instance`
+    /// files of older versions and moving plaintext tokens into `store`.
+    /// Nothing is converted or deleted for a store that isn't persistent.
+    pub fn load(store: &dyn CredentialStore) -> Result<Self, LemnuxError> {
+        let path = confy::get_configuration_file_path("lemnux", "accounts")?;
+        let mut accounts: Self = if path.exists() {
+            confy::load("lemnux", "accounts")?
+        } else {
+            Self::from_legacy()?
+        };
+
+        if !store.is_persistent() {
+            return Ok(accounts);
+        }
+
+        if accounts.migrate(store)? || !path.exists() {
+            accounts.store()?;
+        }
+
+        // The legacy file held the token in plaintext.
+        let user_path = confy::get_configuration_file_path("lemnux", "user")?;
+
+        if user_path.exists() {
+            fs::remove_file(user_path).map_err(|error| LemnuxError::Config(error.to_string()))?;
+        }
+
+        Ok(accounts)
+    }
+
+    /// Moves the plaintext tokens of older versions into `store`, telling
+    /// whether there were any.
+    fn migrate(&mut self, store: &dyn CredentialStore) -> Result<bool, LemnuxError> {
+        let mut migrated = false;
+
+        for account in &mut self.accounts {
+            if let Some(token) = account.jwt.take().and_then(|jwt| jwt.token) {
+                let key = account.label();
+
+                store.set(&key, &token)?;
+                account.token_ref = Some(key);
+                migrated = true;
+            }
+        }
+
+        Ok(migrated)
+    }
+
+    fn from_legacy() -> Result<Self, LemnuxError> {
+        Ok(Self::from_settings(
+            confy::load("lemnux", "user")?,
+            confy::load("lemnux", "instance")?,
+        ))
+    }
+
+    fn from_settings(user: Settings, instance: Settings) -> Self {
+        let domain = instance.instance.map(|instance| instance.domain);
+        let mut accounts = Self {
+            instance: domain.clone(),
+            ..Default::default()
+        };
+
+        if let Some(user) = user.user.filter(|user| user.is_logged) {
+            accounts.accounts.push(Account {
+                jwt: user.jwt,
+                ..Account::new(
+                    user.username,
+                    domain.unwrap_or_else(|| String::from("lemmy.ml")),
+                )
+            });
+            accounts.active = Some(0);
+        }
+
+        accounts
+    }
+
+    pub fn store(&self) -> Result<(), LemnuxError> {
+        Ok(confy::store("lemnux", "accounts", self)?)
+    }
+
+    pub fn active(&self) -> Option<&Account> {
+        self.active.and_then(|index| self.accounts.get(index))
+    }
+
+    /// Instance the app talks to: the active account's, or the one picked
+    /// for anonymous browsing.
+    pub fn domain(&self) -> &str {
+        self.active()
+            .map(|account| account.instance.as_str())
+            .or(self.instance.as_deref())
+            .unwrap_or("lemmy.ml")
+    }
+
+    /// Adds `account` with its `token`, replacing an earlier login of the
+    /// same user on the same instance, and makes it the active one.
+    pub fn add(
+        &mut self,
+        store: &dyn CredentialStore,
+        mut account: Account,
+        token: Option<Sensitive<String>>,
+    ) -> Result<(), LemnuxError> {
+        let label = account.label();
+
+        if let Some(token) = token {
+            store.set(&label, &token)?;
+            account.token_ref = Some(label.clone());
+        }
+
+        match self
+            .accounts
+            .iter()
+            .position(|known| known.label() == label)
+        {
+            Some(index) => {
+                self.accounts[index] = account;
+                self.active = Some(index);
+            }
+            None => {
+                self.accounts.push(account);
+                self.active = Some(self.accounts.len() - 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the account at `index` and its token. Removing the active
+    /// account falls back to browsing its instance anonymously.
+    pub fn remove(&mut self, store: &dyn CredentialStore, index: usize) -> Result<(), LemnuxError> {
+        if index >= self.accounts.len() {
+            return Ok(());
+        }
+
+        if let Some(key) = &self.accounts[index].token_ref {
+            store.delete(key)?;
+        }
+
+        let account = self.accounts.remove(index);
+
+        self.active = match self.active {
+            Some(active) if active == index => {
+                self.instance = Some(account.instance);
+                None
+            }
+            Some(active) if active > index => Some(active - 1),
+            active => active,
+        };
+
+        Ok(())
+    }
+
+    /// Makes the account at `index` active, or browses anonymously on `None`.
+    /// Going anonymous keeps browsing the instance of the account left.
+    pub fn switch(&mut self, index: Option<usize>) {
+        if let (None, Some(account)) = (index, self.active()) {
+            self.instance = Some(account.instance.clone());
+        }
+
+        self.active = index.filter(|index| *index < self.accounts.len());
+    }
+}
+
+/// Comment and message text that hasn't been sent yet, kept across page
+/// changes and restarts. Keys name what the text answers or edits, see
+/// [`Drafts::key`].
+///
+/// Pages change the drafts in memory, shared by all of them; the app writes
+/// them out with [`Drafts::flush`] every few seconds and before exiting.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Drafts {
+    #[serde(default)]
+    drafts: HashMap<String, String>,
+    /// Changed since the last write.
+    #[serde(skip)]
+    unsaved: bool,
+}
+
+impl Drafts {
+    fn shared() -> &'static Mutex<Drafts> {
+        static DRAFTS: OnceLock<Mutex<Drafts>> = OnceLock::new();
+
+        DRAFTS.get_or_init(|| Mutex::new(confy::load("lemnux", "drafts").unwrap_or_default()))
+    }
+
+    /// Ids are only unique on one instance, so keys carry the domain, e.g.
+    /// `lemmy.ml/comment/42`.
+    pub fn key(domain: &str, kind: &str, id: i32) -> String {
+        format!("{domain}/{kind}/{id}")
+    }
+
+    pub fn get(key: &str) -> Option<String> {
+        Self::shared().lock().unwrap().drafts.get(key).cloned()
+    }
+
+    /// Keeps `text` under `key`, or forgets the draft when it is blank.
+    pub fn set(key: String, text: String) {
+        let mut shared = Self::shared().lock().unwrap();
+
+        shared.unsaved |= if text.trim().is_empty() {
+            shared.drafts.remove(&key).is_some()
+        } else {
+            shared.drafts.insert(key, text.clone()).as_ref() != Some(&text)
+        };
+    }
+
+    /// There are changes [`Drafts::flush`] hasn't written yet.
+    pub fn unsaved() -> bool {
+        Self::shared().lock().unwrap().unsaved
+    }
+
+    /// Writes the drafts out if they changed since the last write.
+    pub async fn flush() -> Result<(), LemnuxError> {
+        tokio::task::spawn_blocking(|| {
+            // Taking the copy while holding this keeps writes in order.
+            static WRITING: Mutex<()> = Mutex::new(());
+            let _writing = WRITING.lock().unwrap();
+
+            let drafts = {
+                let mut shared = Self::shared().lock().unwrap();
+
+                if !shared.unsaved {
+                    return Ok(());
+                }
+
+                shared.unsaved = false;
+                shared.clone()
+            };
+
+            confy::store("lemnux", "drafts", &drafts).map_err(|err| {
+                Self::shared().lock().unwrap().unsaved = true;
+                LemnuxError::from(err)
+            })
+        })
+        .await
+        .map_err(|err| LemnuxError::Config(err.to_string()))?
+    }
+}
+
+/// How a post listing tab is sorted and how many posts a page holds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ListingPreferences {
+    pub sort: SortType,
+    pub limit: i64,
+}
+
+impl Default for ListingPreferences {
+    fn default() -> Self {
+        Self {
+            sort: SortType::Hot,
+            limit: 20,
+        }
+    }
+}
+
+/// What clicking a post title in a listing opens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TitleAction {
+    #[default]
+    Comments,
+    /// The post's link in the browser, for posts that have one.
+    Link,
+}
+
+impl TitleAction {
+    pub const ALL: [TitleAction; 2] = [TitleAction::Comments, TitleAction::Link];
+}
+
+impl Display for TitleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TitleAction::Comments => write!(f, "Comments"),
+            TitleAction::Link => write!(f, "Link"),
+        }
+    }
+}
+
+/// Size caps of the image cache, in megabytes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CachePreferences {
+    pub disk_mb: u64,
+    pub memory_mb: u64,
+}
+
+impl CachePreferences {
+    pub const DEFAULT_DISK_MB: u64 = 500;
+    pub const DEFAULT_MEMORY_MB: u64 = 128;
+}
+
+impl Default for CachePreferences {
+    fn default() -> Self {
+        Self {
+            disk_mb: Self::DEFAULT_DISK_MB,
+            memory_mb: Self::DEFAULT_MEMORY_MB,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Preferences {
     pub theme: AppTheme,
+    #[serde(default)]
+    pub all: ListingPreferences,
+    #[serde(default)]
+    pub local: ListingPreferences,
+    #[serde(default)]
+    pub subscribed: ListingPreferences,
+    #[serde(default)]
+    pub cache: CachePreferences,
+    /// Widest a post thumbnail is shown, in pixels.
+    #[serde(default = "Preferences::default_thumbnail_width")]
+    pub thumbnail_width: u32,
+    #[serde(default)]
+    pub title_opens: TitleAction,
 }
 
 impl Preferences {
     pub fn new() -> Self {
-        let self_ = Preferences {
+        Preferences {
             theme: AppTheme::SolarizedDark,
-        };
-
-        confy::store("lemnux", "preferences", &self_).unwrap();
+            all: ListingPreferences::default(),
+            local: ListingPreferences::default(),
+            subscribed: ListingPreferences::default(),
+            cache: CachePreferences::default(),
+            thumbnail_width: Self::default_thumbnail_width(),
+            title_opens: TitleAction::default(),
+        }
+    }
 
-        self_
+    /// The stored preferences, or the defaults when there are none yet. A
+    /// file that can't be read is an error and is left as it is, so that
+    /// callers never save defaults over it.
+    pub fn load() -> Result<Self, LemnuxError> {
+        Ok(confy::load("lemnux", "preferences")?)
     }
 
-    pub fn set_theme(&mut self, theme: AppTheme) -> anyhow::Result<()> {
+    pub fn set_theme(&mut self, theme: AppTheme) -> Result<(), LemnuxError> {
         self.theme = theme;
 
-        confy::store("lemnux", "preferences", &self).unwrap();
+        confy::store("lemnux", "preferences", &self)?;
+
+        Ok(())
+    }
+
+    pub fn listing(&self, type_: Option<ListingType>) -> ListingPreferences {
+        match type_ {
+            Some(ListingType::Local) => self.local,
+            Some(ListingType::Subscribed) => self.subscribed,
+            _ => self.all,
+        }
+    }
+
+    pub fn set_listing(
+        &mut self,
+        type_: Option<ListingType>,
+        listing: ListingPreferences,
+    ) -> Result<(), LemnuxError> {
+        match type_ {
+            Some(ListingType::Local) => self.local = listing,
+            Some(ListingType::Subscribed) => self.subscribed = listing,
+            _ => self.all = listing,
+        }
+
+        confy::store("lemnux", "preferences", &self)?;
+
+        Ok(())
+    }
+
+    fn default_thumbnail_width() -> u32 {
+        640
+    }
+
+    pub fn set_thumbnail_width(&mut self, width: u32) -> Result<(), LemnuxError> {
+        self.thumbnail_width = width;
+
+        confy::store("lemnux", "preferences", &self)?;
+
+        Ok(())
+    }
+
+    pub fn set_title_opens(&mut self, action: TitleAction) -> Result<(), LemnuxError> {
+        self.title_opens = action;
+
+        confy::store("lemnux", "preferences", &self)?;
+
+        Ok(())
+    }
+
+    pub fn set_cache(&mut self, cache: CachePreferences) -> Result<(), LemnuxError> {
+        self.cache = cache;
+
+        confy::store("lemnux", "preferences", &self)?;
 
         Ok(())
     }
@@ -166,10 +890,11 @@ impl Settings {
     }
 
     pub fn load_theme() -> Theme {
-        let preferences: crate::settings::Preferences =
-            confy::load("lemnux", "preferences").unwrap();
+        let theme = Preferences::load()
+            .map(|preferences| preferences.theme)
+            .unwrap_or(AppTheme::SolarizedDark);
 
-        Self::translate_app_theme(preferences.theme)
+        Self::translate_app_theme(theme)
     }
 
     pub fn translate_app_theme(theme: AppTheme) -> Theme {
@@ -198,3 +923,220 @@ impl Settings {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(name: &str, instance: &str) -> Account {
+        Account::new(String::from(name).into(), String::from(instance))
+    }
+
+    fn token(value: &str) -> Sensitive<String> {
+        String::from(value).into()
+    }
+
+    fn legacy(username: &str, token: &str) -> (Settings, Settings) {
+        let jwt = JWT {
+            token: Some(String::from(token).into()),
+            ..Default::default()
+        };
+        let user = Settings {
+            user: Some(User::new(String::from(username).into(), Some(jwt), true)),
+            ..Default::default()
+        };
+        let instance = Settings {
+            instance: Some(Instance {
+                id: 1,
+                domain: String::from("beehaw.org"),
+                published: String::new(),
+                updated: None,
+                software: None,
+                version: None,
+                federation_state: None,
+            }),
+            ..Default::default()
+        };
+
+        (user, instance)
+    }
+
+    #[test]
+    fn legacy_token_moves_into_the_store() {
+        let (user, instance) = legacy("alice", "secret");
+        let mut accounts = Accounts::from_settings(user, instance);
+        let store = MockStore::default();
+
+        assert!(accounts.migrate(&store).unwrap());
+
+        let active = accounts.active().unwrap();
+
+        assert_eq!(active.label(), "alice@beehaw.org");
+        assert_eq!(active.token_ref.as_deref(), Some("alice@beehaw.org"));
+        assert!(active.jwt.is_none());
+        assert_eq!(
+            active
+                .token(&store)
+                .unwrap()
+                .map(|secret| secret.to_string()),
+            Some(String::from("secret"))
+        );
+        // Nothing is left to move the second time.
+        assert!(!accounts.migrate(&store).unwrap());
+    }
+
+    #[test]
+    fn migrated_token_is_not_written_back() {
+        let (user, instance) = legacy("alice", "secret");
+        let mut accounts = Accounts::from_settings(user, instance);
+
+        accounts.migrate(&MockStore::default()).unwrap();
+
+        let written = serde_json::to_string(&accounts).unwrap();
+
+        assert!(!written.contains("secret"));
+    }
+
+    #[test]
+    fn add_activates_and_replaces_the_same_login() {
+        let store = MockStore::default();
+        let mut accounts = Accounts::default();
+
+        accounts
+            .add(&store, account("alice", "lemmy.ml"), Some(token("one")))
+            .unwrap();
+        accounts
+            .add(&store, account("bob", "lemmy.ml"), Some(token("two")))
+            .unwrap();
+        assert_eq!(accounts.active, Some(1));
+
+        accounts
+            .add(&store, account("alice", "lemmy.ml"), Some(token("three")))
+            .unwrap();
+
+        assert_eq!(accounts.accounts.len(), 2);
+        assert_eq!(accounts.active, Some(0));
+        assert_eq!(
+            store
+                .get("alice@lemmy.ml")
+                .unwrap()
+                .map(|secret| secret.to_string()),
+            Some(String::from("three"))
+        );
+    }
+
+    #[test]
+    fn remove_keeps_the_active_account() {
+        let store = MockStore::default();
+        let mut accounts = Accounts::default();
+
+        for name in ["alice", "bob", "carol"] {
+            accounts
+                .add(&store, account(name, "lemmy.ml"), Some(token(name)))
+                .unwrap();
+        }
+
+        // Removing an account before the active one shifts its index.
+        accounts.remove(&store, 0).unwrap();
+        assert_eq!(accounts.active().unwrap().label(), "carol@lemmy.ml");
+        assert!(store.get("alice@lemmy.ml").unwrap().is_none());
+
+        // Removing one after it leaves the index alone.
+        accounts.switch(Some(0));
+        accounts.remove(&store, 1).unwrap();
+        assert_eq!(accounts.active().unwrap().label(), "bob@lemmy.ml");
+
+        // Out of range is ignored.
+        accounts.remove(&store, 5).unwrap();
+        assert_eq!(accounts.accounts.len(), 1);
+    }
+
+    #[test]
+    fn removing_the_active_account_browses_its_instance() {
+        let store = MockStore::default();
+        let mut accounts = Accounts::default();
+
+        accounts
+            .add(&store, account("alice", "beehaw.org"), Some(token("one")))
+            .unwrap();
+        accounts.remove(&store, 0).unwrap();
+
+        assert_eq!(accounts.active, None);
+        assert_eq!(accounts.domain(), "beehaw.org");
+    }
+
+    #[test]
+    fn switch_ignores_unknown_accounts() {
+        let store = MockStore::default();
+        let mut accounts = Accounts::default();
+
+        accounts
+            .add(&store, account("alice", "beehaw.org"), None)
+            .unwrap();
+
+        accounts.switch(Some(3));
+        assert_eq!(accounts.active, None);
+        assert_eq!(accounts.domain(), "lemmy.ml");
+
+        accounts.switch(Some(0));
+        accounts.switch(None);
+        assert_eq!(accounts.active, None);
+        assert_eq!(accounts.domain(), "beehaw.org");
+    }
+
+    fn file_store() -> EncryptedFileStore {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "lemnux-secrets-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+
+        EncryptedFileStore { dir }
+    }
+
+    #[test]
+    fn encrypted_file_round_trip() {
+        let store = file_store();
+
+        store.set("alice@lemmy.ml", &token("one")).unwrap();
+        store.set("bob@lemmy.ml", &token("two")).unwrap();
+        store.delete("bob@lemmy.ml").unwrap();
+
+        assert_eq!(
+            store
+                .get("alice@lemmy.ml")
+                .unwrap()
+                .map(|secret| secret.to_string()),
+            Some(String::from("one"))
+        );
+        assert!(store.get("bob@lemmy.ml").unwrap().is_none());
+
+        let written = fs::read(store.dir.join("secrets.bin")).unwrap();
+
+        assert!(!String::from_utf8_lossy(&written).contains("one"));
+
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn encrypted_file_fails_with_another_key() {
+        let store = file_store();
+
+        store.set("alice@lemmy.ml", &token("one")).unwrap();
+        fs::write(
+            store.dir.join("secrets.key"),
+            ChaCha20Poly1305::generate_key(&mut OsRng),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            store.get("alice@lemmy.ml"),
+            Err(LemnuxError::Secret(_))
+        ));
+
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
@@ -0,0 +1,409 @@
+//! Content kept for offline use in `$XDG_DATA_HOME/lemnux/offline.sqlite`.
+//!
+//! Listings, posts, comment threads and communities are stored as the
+//! JSON the instance sent, so they can be shown again when it cannot be
+//! reached. Votes and saves made while offline wait in a queue until they
+//! can be sent, and posts hidden from listings stay hidden.
+//!
+//! Every function here blocks on SQLite; async code runs them through
+//! [`blocking`] so the UI thread never waits on the file.
+
+use std::{
+    collections::HashSet,
+    sync::{Mutex, OnceLock},
+};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use lemmy_api_common::lemmy_db_schema::newtypes::PostId;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+
+use crate::{api::LemnuxError, settings::data_dir};
+
+/// Stored responses older than this are dropped on startup.
+const MAX_AGE: Duration = Duration::days(30);
+
+/// Total size of the stored responses kept on startup; the oldest go first.
+const MAX_BYTES: i64 = 50 * 1024 * 1024;
+
+// Incremental vacuuming only takes effect on a new file, before any table.
+const SCHEMA: &str = "
+    PRAGMA auto_vacuum = INCREMENTAL;
+    CREATE TABLE IF NOT EXISTS responses (
+        kind TEXT NOT NULL,
+        key TEXT NOT NULL,
+        body TEXT NOT NULL,
+        fetched INTEGER NOT NULL,
+        PRIMARY KEY (kind, key)
+    );
+    CREATE INDEX IF NOT EXISTS responses_fetched ON responses (fetched);
+    CREATE TABLE IF NOT EXISTS pending (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account TEXT NOT NULL,
+        post_id INTEGER NOT NULL,
+        action TEXT NOT NULL,
+        value INTEGER NOT NULL,
+        UNIQUE (account, post_id, action)
+    );
+    CREATE TABLE IF NOT EXISTS hidden (
+        account TEXT NOT NULL,
+        post_id INTEGER NOT NULL,
+        PRIMARY KEY (account, post_id)
+    );
+";
+
+/// What a stored response is, so keys of different requests never meet.
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Listing,
+    Post,
+    Comments,
+    Community,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Listing => "listing",
+            Kind::Post => "post",
+            Kind::Comments => "comments",
+            Kind::Community => "community",
+        }
+    }
+}
+
+/// A vote or save made while offline.
+#[derive(Debug, Clone, Copy)]
+pub enum PendingAction {
+    Vote(PostId, i16),
+    Save(PostId, bool),
+}
+
+impl PendingAction {
+    pub fn post_id(&self) -> PostId {
+        match self {
+            PendingAction::Vote(post_id, _) | PendingAction::Save(post_id, _) => *post_id,
+        }
+    }
+}
+
+fn connection() -> Result<&'static Mutex<Connection>, LemnuxError> {
+    static CONNECTION: OnceLock<Result<Mutex<Connection>, LemnuxError>> = OnceLock::new();
+
+    CONNECTION
+        .get_or_init(|| {
+            let dir = data_dir()?;
+
+            std::fs::create_dir_all(&dir)?;
+
+            let connection = Connection::open(dir.join("offline.sqlite"))?;
+
+            connection.execute_batch(SCHEMA)?;
+            prune(&connection, Utc::now())?;
+
+            Ok(Mutex::new(connection))
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Drops responses fetched before [`MAX_AGE`], then the oldest ones until
+/// the rest fit in [`MAX_BYTES`], and gives the freed pages back.
+fn prune(connection: &Connection, now: DateTime<Utc>) -> Result<(), LemnuxError> {
+    connection.execute(
+        "DELETE FROM responses WHERE fetched < ?1",
+        params![(now - MAX_AGE).timestamp()],
+    )?;
+    connection.execute(
+        "DELETE FROM responses WHERE rowid IN (
+            SELECT rowid FROM (
+                SELECT rowid, SUM(length(body)) OVER (ORDER BY fetched DESC, rowid DESC) AS total
+                FROM responses
+            ) WHERE total > ?1
+        )",
+        params![MAX_BYTES],
+    )?;
+    connection.execute_batch("PRAGMA incremental_vacuum;")?;
+
+    Ok(())
+}
+
+/// Runs `f` on tokio's blocking pool.
+pub async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, LemnuxError> + Send + 'static,
+) -> Result<T, LemnuxError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| LemnuxError::Store(err.to_string()))?
+}
+
+/// Keeps `body`, the JSON of an answer, as the latest answer to the
+/// request `key`.
+pub fn put(kind: Kind, key: &str, body: &str) -> Result<(), LemnuxError> {
+    connection()?.lock().unwrap().execute(
+        "INSERT OR REPLACE INTO responses (kind, key, body, fetched) VALUES (?1, ?2, ?3, ?4)",
+        params![kind.as_str(), key, body, Utc::now().timestamp()],
+    )?;
+
+    Ok(())
+}
+
+/// The stored answer to the request `key`, and when it was fetched.
+pub fn get<T: DeserializeOwned>(
+    kind: Kind,
+    key: &str,
+) -> Result<Option<(T, DateTime<Utc>)>, LemnuxError> {
+    let row: Option<(String, i64)> = connection()?
+        .lock()
+        .unwrap()
+        .query_row(
+            "SELECT body, fetched FROM responses WHERE kind = ?1 AND key = ?2",
+            params![kind.as_str(), key],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let Some((body, fetched)) = row else {
+        return Ok(None);
+    };
+    let fetched = Utc.timestamp_opt(fetched, 0).single().unwrap_or_default();
+
+    Ok(Some((serde_json::from_str(&body)?, fetched)))
+}
+
+/// Queues `actions` for `account`, each replacing an older one of the
+/// same kind on the same post.
+pub fn queue(account: &str, actions: &[PendingAction]) -> Result<(), LemnuxError> {
+    insert_pending(&connection()?.lock().unwrap(), account, actions)
+}
+
+fn insert_pending(
+    connection: &Connection,
+    account: &str,
+    actions: &[PendingAction],
+) -> Result<(), LemnuxError> {
+    for action in actions {
+        let (post_id, name, value) = match *action {
+            PendingAction::Vote(post_id, score) => (post_id, "vote", i64::from(score)),
+            PendingAction::Save(post_id, save) => (post_id, "save", i64::from(save)),
+        };
+
+        connection.execute(
+            "INSERT OR REPLACE INTO pending (account, post_id, action, value) VALUES (?1, ?2, ?3, ?4)",
+            params![account, post_id.0, name, value],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Actions queued for `account`, oldest first, with the id to pass to
+/// [`done`] once sent.
+pub fn pending(account: &str) -> Result<Vec<(i64, PendingAction)>, LemnuxError> {
+    select_pending(&connection()?.lock().unwrap(), account)
+}
+
+fn select_pending(
+    connection: &Connection,
+    account: &str,
+) -> Result<Vec<(i64, PendingAction)>, LemnuxError> {
+    let mut statement = connection
+        .prepare("SELECT id, post_id, action, value FROM pending WHERE account = ?1 ORDER BY id")?;
+    let rows = statement.query_map(params![account], |row| {
+        let post_id = PostId(row.get(1)?);
+        let value: i64 = row.get(3)?;
+        let action = match row.get::<_, String>(2)?.as_str() {
+            "vote" => PendingAction::Vote(post_id, value as i16),
+            _ => PendingAction::Save(post_id, value != 0),
+        };
+
+        Ok((row.get(0)?, action))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn done(id: i64) -> Result<(), LemnuxError> {
+    connection()?
+        .lock()
+        .unwrap()
+        .execute("DELETE FROM pending WHERE id = ?1", params![id])?;
+
+    Ok(())
+}
+
+/// Keeps `post_id` out of the listings of `account` from now on.
+pub fn hide(account: &str, post_id: PostId) -> Result<(), LemnuxError> {
+    insert_hidden(&connection()?.lock().unwrap(), account, post_id)
+}
+
+fn insert_hidden(
+    connection: &Connection,
+    account: &str,
+    post_id: PostId,
+) -> Result<(), LemnuxError> {
+    connection.execute(
+        "INSERT OR IGNORE INTO hidden (account, post_id) VALUES (?1, ?2)",
+        params![account, post_id.0],
+    )?;
+
+    Ok(())
+}
+
+/// Posts `account` hid from its listings.
+pub fn hidden(account: &str) -> Result<HashSet<PostId>, LemnuxError> {
+    select_hidden(&connection()?.lock().unwrap(), account)
+}
+
+fn select_hidden(connection: &Connection, account: &str) -> Result<HashSet<PostId>, LemnuxError> {
+    let mut statement = connection.prepare("SELECT post_id FROM hidden WHERE account = ?1")?;
+    let rows = statement.query_map(params![account], |row| Ok(PostId(row.get(0)?)))?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        connection
+    }
+
+    fn insert(connection: &Connection, key: &str, size: usize, fetched: DateTime<Utc>) {
+        connection
+            .execute(
+                "INSERT INTO responses (kind, key, body, fetched) VALUES ('post', ?1, ?2, ?3)",
+                params![key, "x".repeat(size), fetched.timestamp()],
+            )
+            .unwrap();
+    }
+
+    fn keys(connection: &Connection) -> Vec<String> {
+        let mut statement = connection
+            .prepare("SELECT key FROM responses ORDER BY key")
+            .unwrap();
+        let rows = statement.query_map([], |row| row.get(0)).unwrap();
+
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn prune_drops_old_responses() {
+        let connection = memory();
+        let now = Utc::now();
+
+        insert(&connection, "fresh", 10, now - Duration::days(1));
+        insert(&connection, "old", 10, now - MAX_AGE - Duration::days(1));
+        prune(&connection, now).unwrap();
+
+        assert_eq!(keys(&connection), vec!["fresh"]);
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_that_fit() {
+        let connection = memory();
+        let now = Utc::now();
+        let half = MAX_BYTES as usize / 2;
+
+        insert(&connection, "a", half, now - Duration::hours(3));
+        insert(&connection, "b", half, now - Duration::hours(2));
+        insert(&connection, "c", half, now - Duration::hours(1));
+        prune(&connection, now).unwrap();
+
+        assert_eq!(keys(&connection), vec!["b", "c"]);
+    }
+
+    fn summary(connection: &Connection, account: &str) -> Vec<(i32, &'static str, i64)> {
+        select_pending(connection, account)
+            .unwrap()
+            .into_iter()
+            .map(|(_, action)| match action {
+                PendingAction::Vote(post_id, score) => (post_id.0, "vote", i64::from(score)),
+                PendingAction::Save(post_id, save) => (post_id.0, "save", i64::from(save)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn queue_keeps_order_of_arrival() {
+        let connection = memory();
+
+        insert_pending(
+            &connection,
+            "lemmy.ml/alice",
+            &[
+                PendingAction::Vote(PostId(2), 1),
+                PendingAction::Save(PostId(1), true),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            summary(&connection, "lemmy.ml/alice"),
+            vec![(2, "vote", 1), (1, "save", 1)]
+        );
+    }
+
+    #[test]
+    fn queue_replaces_the_same_action_on_a_post() {
+        let connection = memory();
+        let account = "lemmy.ml/alice";
+
+        insert_pending(&connection, account, &[PendingAction::Vote(PostId(1), 1)]).unwrap();
+        insert_pending(
+            &connection,
+            account,
+            &[PendingAction::Save(PostId(1), true)],
+        )
+        .unwrap();
+        insert_pending(&connection, account, &[PendingAction::Vote(PostId(1), -1)]).unwrap();
+
+        // The newer vote replaces the older one and is now sent last.
+        assert_eq!(
+            summary(&connection, account),
+            vec![(1, "save", 1), (1, "vote", -1)]
+        );
+    }
+
+    #[test]
+    fn queue_is_per_account() {
+        let connection = memory();
+
+        insert_pending(
+            &connection,
+            "lemmy.ml/alice",
+            &[PendingAction::Vote(PostId(1), 1)],
+        )
+        .unwrap();
+        insert_pending(
+            &connection,
+            "lemmy.ml/bob",
+            &[PendingAction::Vote(PostId(1), -1)],
+        )
+        .unwrap();
+
+        assert_eq!(summary(&connection, "lemmy.ml/alice"), vec![(1, "vote", 1)]);
+        assert_eq!(summary(&connection, "lemmy.ml/bob"), vec![(1, "vote", -1)]);
+    }
+
+    #[test]
+    fn hidden_posts_are_per_account() {
+        let connection = memory();
+
+        insert_hidden(&connection, "lemmy.ml/alice", PostId(1)).unwrap();
+        insert_hidden(&connection, "lemmy.ml/alice", PostId(1)).unwrap();
+        insert_hidden(&connection, "lemmy.ml/bob", PostId(2)).unwrap();
+
+        assert_eq!(
+            select_hidden(&connection, "lemmy.ml/alice").unwrap(),
+            HashSet::from([PostId(1)])
+        );
+        assert_eq!(
+            select_hidden(&connection, "lemmy.ml/bob").unwrap(),
+            HashSet::from([PostId(2)])
+        );
+    }
+}